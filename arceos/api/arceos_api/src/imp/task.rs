@@ -20,7 +20,10 @@ pub fn ax_exit(_exit_code: i32) -> ! {
     #[cfg(feature = "multitask")]
     axtask::exit(_exit_code);
     #[cfg(not(feature = "multitask"))]
-    axhal::misc::terminate();
+    {
+        axlog::flush();
+        axhal::misc::terminate();
+    }
 }
 
 cfg_task! {
@@ -0,0 +1,495 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axfs_vfs::{VfsError, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+use core::time::Duration;
+
+use crate::{node_meta, DeviceId, DeviceNode, DirNode, FileNode, RamFileSystem, SymlinkNode};
+
+const NEWC_MAGIC: &str = "070701";
+const NEWC_HEADER_LEN: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+/// The file type bits of a cpio mode.
+fn type_bits(ty: VfsNodeType) -> u32 {
+    (ty as u32) << 12
+}
+
+/// The node type of a cpio mode.
+fn node_type(mode: u32) -> Option<VfsNodeType> {
+    Some(match (mode >> 12) & 0o17 {
+        0o1 => VfsNodeType::Fifo,
+        0o2 => VfsNodeType::CharDevice,
+        0o4 => VfsNodeType::Dir,
+        0o6 => VfsNodeType::BlockDevice,
+        0o10 => VfsNodeType::File,
+        0o12 => VfsNodeType::SymLink,
+        0o14 => VfsNodeType::Socket,
+        _ => return None,
+    })
+}
+
+/// The fields of a newc header, besides the magic, the name size and the
+/// checksum.
+#[derive(Default)]
+struct NewcHeader {
+    ino: u32,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    mtime: u32,
+    filesize: u32,
+    rdev_major: u32,
+    rdev_minor: u32,
+}
+
+/// Writes the header of an entry and its name.
+fn write_header(
+    write: &mut impl FnMut(&[u8]) -> VfsResult,
+    hdr: &NewcHeader,
+    name: &str,
+) -> VfsResult {
+    let namesize = name.len() + 1;
+    let fields = [
+        hdr.ino,
+        hdr.mode,
+        hdr.uid,
+        hdr.gid,
+        hdr.nlink,
+        hdr.mtime,
+        hdr.filesize,
+        0, // devmajor
+        0, // devminor
+        hdr.rdev_major,
+        hdr.rdev_minor,
+        namesize as u32,
+        0, // check
+    ];
+    let mut buf = String::with_capacity(NEWC_HEADER_LEN + namesize + 3);
+    buf.push_str(NEWC_MAGIC);
+    for field in fields {
+        buf.push_str(&format!("{:08X}", field));
+    }
+    buf.push_str(name);
+    buf.push('\0');
+    buf.push_str(&"\0\0\0"[..(4 - buf.len() % 4) % 4]);
+    write(buf.as_bytes())
+}
+
+/// Writes the padding after `len` bytes of data.
+fn write_padding(write: &mut impl FnMut(&[u8]) -> VfsResult, len: usize) -> VfsResult {
+    match len % 4 {
+        0 => Ok(()),
+        n => write(&[0; 3][..4 - n]),
+    }
+}
+
+struct CpioWriter<W> {
+    write: W,
+    /// Inode numbers of the files already written, so that hard links keep
+    /// the same one.
+    inodes: BTreeMap<*const (), u32>,
+    next_ino: u32,
+}
+
+impl<W: FnMut(&[u8]) -> VfsResult> CpioWriter<W> {
+    fn write_dir(&mut self, dir: &DirNode, prefix: &str) -> VfsResult {
+        for (name, node) in dir.child_nodes() {
+            let path = format!("{}{}", prefix, name);
+            self.write_node(&node, &path)?;
+            if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+                self.write_dir(dir, &format!("{}/", path))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_node(&mut self, node: &VfsNodeRef, path: &str) -> VfsResult {
+        let Ok(meta) = node_meta(node.as_ref()) else {
+            return Ok(()); // not created by the RAM filesystem
+        };
+        let attr = node.get_attr()?;
+        let (uid, gid) = meta.owner();
+        let mut hdr = NewcHeader {
+            mode: type_bits(attr.file_type()) | attr.perm().bits() as u32,
+            uid,
+            gid,
+            nlink: 1,
+            mtime: meta.times().mtime.as_secs() as u32,
+            ..Default::default()
+        };
+        let any = node.as_any();
+        let mut data_len = 0;
+        if let Some(file) = any.downcast_ref::<FileNode>() {
+            hdr.nlink = file.nlink() as u32;
+            let key = Arc::as_ptr(node) as *const ();
+            if let Some(&ino) = self.inodes.get(&key) {
+                // 硬链接的数据只写一次
+                hdr.ino = ino;
+            } else {
+                hdr.ino = self.alloc_ino();
+                self.inodes.insert(key, hdr.ino);
+                data_len = attr.size();
+            }
+        } else {
+            hdr.ino = self.alloc_ino();
+            if any.is::<DirNode>() {
+                hdr.nlink = 2;
+            } else if any.is::<SymlinkNode>() {
+                data_len = attr.size();
+            } else if let Some(dev) = any.downcast_ref::<DeviceNode>() {
+                hdr.rdev_major = dev.id().major;
+                hdr.rdev_minor = dev.id().minor;
+            }
+        }
+        hdr.filesize = u32::try_from(data_len).map_err(|_| VfsError::InvalidData)?;
+        write_header(&mut self.write, &hdr, path)?;
+
+        let mut buf = [0; 4096];
+        let mut offset = 0;
+        while offset < data_len {
+            let n = node.read_at(offset, &mut buf)?;
+            let n = n.min((data_len - offset) as usize);
+            if n == 0 {
+                return Err(VfsError::UnexpectedEof);
+            }
+            (self.write)(&buf[..n])?;
+            offset += n as u64;
+        }
+        write_padding(&mut self.write, data_len as usize)
+    }
+
+    fn alloc_ino(&mut self) -> u32 {
+        self.next_ino += 1;
+        self.next_ino
+    }
+}
+
+impl RamFileSystem {
+    /// Writes the content of the filesystem as a cpio archive in the "newc"
+    /// format, the one of initramfs images, passing its bytes to `write`.
+    ///
+    /// The entries keep the names, types, sizes, permissions, owners,
+    /// modification times and device IDs of the nodes, relative to the root
+    /// and without a leading `/`. The data of a file with several hard links
+    /// is only in the first of its entries, and that of a symbolic link is
+    /// its target. Files larger than 4 GiB return
+    /// [`VfsError::InvalidData`].
+    pub fn export_cpio(&self, write: impl FnMut(&[u8]) -> VfsResult) -> VfsResult {
+        let mut writer = CpioWriter {
+            write,
+            inodes: BTreeMap::new(),
+            next_ino: 0,
+        };
+        writer.write_dir(&self.root, "")?;
+        let trailer = NewcHeader {
+            nlink: 1,
+            ..Default::default()
+        };
+        write_header(&mut writer.write, &trailer, TRAILER)
+    }
+}
+
+/// A node read from an archive.
+struct Entry<'a> {
+    path: &'a str,
+    ty: VfsNodeType,
+    perm: u32,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    data: &'a [u8],
+    dev: DeviceId,
+}
+
+/// Creates the nodes read from an archive.
+struct Loader<'a> {
+    fs: &'a RamFileSystem,
+    /// Modification times of the directories, set once their content is
+    /// created.
+    dir_times: Vec<(VfsNodeRef, Duration)>,
+}
+
+impl<'a> Loader<'a> {
+    fn new(fs: &'a RamFileSystem) -> Self {
+        Self {
+            fs,
+            dir_times: Vec::new(),
+        }
+    }
+
+    /// Returns the directory containing `path`, creating the missing ones,
+    /// and the last name of `path`.
+    fn parent_dir<'p>(&self, path: &'p str) -> VfsResult<(VfsNodeRef, &'p str)> {
+        let (parents, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let mut dir: VfsNodeRef = self.fs.root_dir_node();
+        for name in parents.split('/').filter(|&s| !s.is_empty() && s != ".") {
+            let d = as_dir(&dir)?;
+            if !d.exist(name) {
+                d.create_node(name, VfsNodeType::Dir)?;
+            }
+            dir = dir.clone().lookup(name)?;
+        }
+        as_dir(&dir)?;
+        Ok((dir, name))
+    }
+
+    /// Creates the node of `entry`, and returns it unless its type is not
+    /// supported.
+    fn create(&mut self, entry: &Entry) -> VfsResult<Option<VfsNodeRef>> {
+        let path = entry.path.trim_start_matches("./").trim_matches('/');
+        if path.split('/').any(|s| s == "..") {
+            return Err(VfsError::InvalidData);
+        }
+        let node = if path.is_empty() || path == "." {
+            self.fs.root_dir_node() as VfsNodeRef
+        } else {
+            let (parent, name) = self.parent_dir(path)?;
+            let dir = as_dir(&parent)?;
+            match entry.ty {
+                VfsNodeType::Dir if dir.exist(name) => {}
+                VfsNodeType::CharDevice | VfsNodeType::BlockDevice => {
+                    dir.create_device(name, entry.ty, entry.dev)?
+                }
+                VfsNodeType::File | VfsNodeType::Dir | VfsNodeType::Fifo => {
+                    dir.create_node(name, entry.ty)?
+                }
+                VfsNodeType::SymLink => {
+                    let target = core::str::from_utf8(entry.data);
+                    dir.symlink(name, target.map_err(|_| VfsError::InvalidData)?)?
+                }
+                _ => {
+                    log::warn!("ramfs: skip {:?} {} from archive", entry.ty, path);
+                    return Ok(None);
+                }
+            }
+            // 符号链接本身, 而不是它的目标
+            let node = match entry.ty {
+                VfsNodeType::SymLink => dir.child(name)?,
+                _ => parent.lookup(name)?,
+            };
+            if entry.ty.is_file() && !entry.data.is_empty() {
+                node.write_at(0, entry.data)?;
+            }
+            node
+        };
+        let meta = node_meta(node.as_ref())?;
+        meta.set_perm(VfsNodePerm::from_bits_truncate(entry.perm as u16));
+        meta.set_owner(Some(entry.uid), Some(entry.gid));
+        let mtime = Duration::from_secs(entry.mtime);
+        if entry.ty.is_dir() {
+            self.dir_times.push((node.clone(), mtime));
+        } else {
+            meta.set_mtime(mtime);
+        }
+        Ok(Some(node))
+    }
+
+    /// Adds a hard link at `path` to `node`.
+    fn link(&self, path: &str, node: VfsNodeRef) -> VfsResult {
+        let (parent, name) = self.parent_dir(path.trim_start_matches("./"))?;
+        as_dir(&parent)?.link(name, node)
+    }
+
+    fn finish(self) -> VfsResult {
+        for (node, mtime) in self.dir_times {
+            node_meta(node.as_ref())?.set_mtime(mtime);
+        }
+        Ok(())
+    }
+}
+
+fn as_dir(node: &VfsNodeRef) -> VfsResult<&DirNode> {
+    node.as_any()
+        .downcast_ref::<DirNode>()
+        .ok_or(VfsError::NotADirectory)
+}
+
+/// Parses a number of a cpio header, in hexadecimal.
+fn parse_hex(field: &[u8]) -> VfsResult<u32> {
+    let s = core::str::from_utf8(field).map_err(|_| VfsError::InvalidData)?;
+    u32::from_str_radix(s, 16).map_err(|_| VfsError::InvalidData)
+}
+
+/// Parses a number of a tar header, in octal and ended by spaces or NULs.
+fn parse_octal(field: &[u8]) -> VfsResult<u64> {
+    let s = core::str::from_utf8(field).map_err(|_| VfsError::InvalidData)?;
+    let s = s.trim_matches(|c| c == ' ' || c == '\0');
+    if s.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(s, 8).map_err(|_| VfsError::InvalidData)
+}
+
+/// Returns the string of a tar header field, ended by a NUL unless it fills
+/// the field.
+fn parse_str(field: &[u8]) -> VfsResult<&str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..len]).map_err(|_| VfsError::InvalidData)
+}
+
+/// Returns `data[start..start + len]`, or [`VfsError::InvalidData`] if the
+/// archive is truncated.
+fn slice(data: &[u8], start: usize, len: usize) -> VfsResult<&[u8]> {
+    let end = start.checked_add(len).ok_or(VfsError::InvalidData)?;
+    data.get(start..end).ok_or(VfsError::InvalidData)
+}
+
+impl RamFileSystem {
+    /// Creates a new instance with the content of a cpio archive in the
+    /// "newc" format, e.g., an initramfs image, see
+    /// [`load_cpio`](Self::load_cpio).
+    pub fn from_cpio(data: &[u8]) -> VfsResult<Self> {
+        let fs = Self::new();
+        fs.load_cpio(data)?;
+        Ok(fs)
+    }
+
+    /// Adds the content of a cpio archive in the "newc" format to the
+    /// filesystem, creating the missing parent directories.
+    ///
+    /// Files, directories, device nodes, FIFOs and symbolic links are created
+    /// with their permissions, owners and modification times, and hard links
+    /// are kept.
+    /// Other node types are skipped. A malformed archive returns
+    /// [`VfsError::InvalidData`], and existing nodes other than directories
+    /// [`VfsError::AlreadyExists`].
+    pub fn load_cpio(&self, data: &[u8]) -> VfsResult {
+        let mut loader = Loader::new(self);
+        // 硬链接: 归档中的 inode 号 -> 节点
+        let mut inodes = BTreeMap::new();
+        let mut pos = 0;
+        loop {
+            let hdr = slice(data, pos, NEWC_HEADER_LEN)?;
+            // 070702 是带校验和的格式, 校验和不检查
+            if &hdr[..6] != NEWC_MAGIC.as_bytes() && &hdr[..6] != b"070702" {
+                return Err(VfsError::InvalidData);
+            }
+            let field = |i: usize| parse_hex(&hdr[6 + i * 8..14 + i * 8]);
+            let namesize = field(11)? as usize;
+            let name = slice(data, pos + NEWC_HEADER_LEN, namesize)?;
+            let Some((&0, name)) = name.split_last() else {
+                return Err(VfsError::InvalidData);
+            };
+            let name = core::str::from_utf8(name).map_err(|_| VfsError::InvalidData)?;
+            pos = (pos + NEWC_HEADER_LEN + namesize).next_multiple_of(4);
+            let filesize = field(6)? as usize;
+            let file_data = slice(data, pos, filesize)?;
+            pos = (pos + filesize).next_multiple_of(4);
+            if name == TRAILER {
+                break;
+            }
+
+            let mode = field(1)?;
+            let (ino, nlink) = (field(0)?, field(4)?);
+            let Some(ty) = node_type(mode) else {
+                return Err(VfsError::InvalidData);
+            };
+            let entry = Entry {
+                path: name,
+                ty,
+                perm: mode & 0o777,
+                uid: field(2)?,
+                gid: field(3)?,
+                mtime: field(5)? as u64,
+                data: file_data,
+                dev: DeviceId::new(field(9)?, field(10)?),
+            };
+            if ty.is_file() && nlink > 1 {
+                if let Some(node) = inodes.get(&ino) {
+                    loader.link(name, VfsNodeRef::clone(node))?;
+                    if !file_data.is_empty() {
+                        node.write_at(0, file_data)?;
+                    }
+                    continue;
+                }
+            }
+            if let Some(node) = loader.create(&entry)? {
+                if ty.is_file() && nlink > 1 {
+                    inodes.insert(ino, node);
+                }
+            }
+        }
+        loader.finish()
+    }
+
+    /// Creates a new instance with the content of a tar archive in the
+    /// "ustar" format, see [`load_tar`](Self::load_tar).
+    pub fn from_tar(data: &[u8]) -> VfsResult<Self> {
+        let fs = Self::new();
+        fs.load_tar(data)?;
+        Ok(fs)
+    }
+
+    /// Adds the content of a tar archive in the "ustar" format to the
+    /// filesystem, like [`load_cpio`](Self::load_cpio).
+    ///
+    /// The extended headers of the "pax" format are skipped.
+    pub fn load_tar(&self, data: &[u8]) -> VfsResult {
+        const BLOCK: usize = 512;
+        let mut loader = Loader::new(self);
+        let mut pos = 0;
+        while let Some(hdr) = data.get(pos..pos + BLOCK) {
+            if hdr.iter().all(|&b| b == 0) {
+                break; // end of the archive
+            }
+            // 校验和按 chksum 字段全为空格计算
+            let sum = hdr.iter().enumerate().fold(0u64, |sum, (i, &b)| {
+                let b = if (148..156).contains(&i) { b' ' } else { b };
+                sum + b as u64
+            });
+            if &hdr[257..262] != b"ustar" || parse_octal(&hdr[148..156])? != sum {
+                return Err(VfsError::InvalidData);
+            }
+            let size = parse_octal(&hdr[124..136])?;
+            let size = usize::try_from(size).map_err(|_| VfsError::InvalidData)?;
+            let mut file_data = slice(data, pos + BLOCK, size)?;
+            pos += BLOCK + size.next_multiple_of(BLOCK);
+
+            let (name, prefix) = (parse_str(&hdr[..100])?, parse_str(&hdr[345..500])?);
+            let path = if prefix.is_empty() {
+                String::from(name)
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            let ty = match hdr[156] {
+                b'0' | b'\0' | b'7' => VfsNodeType::File,
+                b'1' => {
+                    let target = parse_str(&hdr[157..257])?;
+                    let target = target.trim_start_matches("./");
+                    let node = self.root_dir_node().lookup(target)?;
+                    loader.link(&path, node)?;
+                    continue;
+                }
+                b'2' => {
+                    // 符号链接的目标在 linkname 字段中
+                    file_data = parse_str(&hdr[157..257])?.as_bytes();
+                    VfsNodeType::SymLink
+                }
+                b'3' => VfsNodeType::CharDevice,
+                b'4' => VfsNodeType::BlockDevice,
+                b'5' => VfsNodeType::Dir,
+                b'6' => VfsNodeType::Fifo,
+                _ => continue, // extended headers
+            };
+            let dev = DeviceId::new(
+                parse_octal(&hdr[329..337])? as u32,
+                parse_octal(&hdr[337..345])? as u32,
+            );
+            loader.create(&Entry {
+                path: &path,
+                ty,
+                perm: parse_octal(&hdr[100..108])? as u32 & 0o777,
+                uid: parse_octal(&hdr[108..116])? as u32,
+                gid: parse_octal(&hdr[116..124])? as u32,
+                mtime: parse_octal(&hdr[136..148])?,
+                data: file_data,
+                dev,
+            })?;
+        }
+        loader.finish()
+    }
+}
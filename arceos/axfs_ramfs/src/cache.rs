@@ -0,0 +1,149 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::Weak;
+use axfs_vfs::{VfsError, VfsNodeOps, VfsNodeRef, VfsResult};
+
+use crate::DirNode;
+
+/// A cached lookup: the directory it started from, kept so that its address
+/// is not reused, and the node found, or `None` if it was not found.
+struct CacheEntry {
+    _dir: Weak<DirNode>,
+    node: Option<Weak<dyn VfsNodeOps>>,
+}
+
+/// The results of the recent path lookups of a filesystem, including those
+/// that found nothing, by directory and path.
+///
+/// Any change of the names in the filesystem clears it. It is not used while
+/// other filesystems are mounted in this one, whose changes it would miss.
+pub(crate) struct LookupCache {
+    capacity: usize,
+    entries: BTreeMap<(usize, String), CacheEntry>,
+    /// The keys of `entries`, the oldest first.
+    order: VecDeque<(usize, String)>,
+    /// Incremented by each change, the lookups started before are not cached.
+    generation: u64,
+    /// Number of filesystems mounted in this one.
+    mounts: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl LookupCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: BTreeMap::new(),
+            order: VecDeque::new(),
+            generation: 0,
+            mounts: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the cached result of looking `path` up from `dir`, with the
+    /// key under which the result is to be [inserted](Self::insert) if it is
+    /// not cached, or `None` if the lookup can't be cached.
+    pub(crate) fn get(
+        &mut self,
+        dir: &DirNode,
+        path: &str,
+    ) -> Option<Result<VfsResult<VfsNodeRef>, (u64, String)>> {
+        if self.capacity == 0 || self.mounts > 0 {
+            return None;
+        }
+        let path = canonicalize(path)?;
+        let key = (dir as *const DirNode as usize, path);
+        let cached = self.entries.get(&key).map(|entry| match &entry.node {
+            Some(node) => node.upgrade().ok_or(VfsError::NotFound),
+            None => Err(VfsError::NotFound),
+        });
+        match cached {
+            Some(result) => {
+                self.hits += 1;
+                Some(Ok(result))
+            }
+            None => {
+                self.misses += 1;
+                Some(Err((self.generation, key.1)))
+            }
+        }
+    }
+
+    /// Caches the result of a lookup of `path` from `dir` that missed the
+    /// cache at `generation`, unless the names have changed since then.
+    pub(crate) fn insert(
+        &mut self,
+        generation: u64,
+        dir: Weak<DirNode>,
+        path: String,
+        result: &VfsResult<VfsNodeRef>,
+    ) {
+        let node = match result {
+            Ok(node) => Some(alloc::sync::Arc::downgrade(node)),
+            Err(VfsError::NotFound) => None,
+            Err(_) => return,
+        };
+        if generation != self.generation {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        let key = (dir.as_ptr() as usize, path);
+        self.order.push_back(key.clone());
+        self.entries.insert(key, CacheEntry { _dir: dir, node });
+    }
+
+    /// Forgets the cached lookups, after a change of the names.
+    pub(crate) fn invalidate(&mut self) {
+        self.generation += 1;
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    /// Accounts for a filesystem mounted in this one, or unmounted if
+    /// `mounted` is false.
+    pub(crate) fn set_mounted(&mut self, mounted: bool) {
+        if mounted {
+            self.mounts += 1;
+        } else {
+            self.mounts -= 1;
+        }
+        self.invalidate();
+    }
+
+    /// Returns the number of hits and misses.
+    pub(crate) fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+}
+
+/// Returns `path` without its empty and `.` components, or `None` if it can't
+/// be cached: with a `..` component, as the parent of the root directory is
+/// in another filesystem, or ending with `/` or `.`, as the last node must
+/// then be a directory.
+fn canonicalize(path: &str) -> Option<String> {
+    let mut canon = String::with_capacity(path.len());
+    let mut last = "";
+    for comp in path.split('/') {
+        last = comp;
+        match comp {
+            "" | "." => continue,
+            ".." => return None,
+            _ => {}
+        }
+        if !canon.is_empty() {
+            canon.push('/');
+        }
+        canon.push_str(comp);
+    }
+    if last.is_empty() || last == "." {
+        return None;
+    }
+    Some(canon)
+}
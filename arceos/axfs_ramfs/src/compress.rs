@@ -0,0 +1,82 @@
+//! Compression of the file data, with PackBits: a run-length encoding whose
+//! runs are a header byte `n`, followed by `n + 1` literal bytes if `n < 128`,
+//! or by a byte repeated `257 - n` times otherwise.
+
+use alloc::vec::Vec;
+
+use crate::{DirNode, FileNode};
+
+/// Maximum length of a run.
+const MAX_RUN: usize = 128;
+
+/// Returns whether `data` starts with 3 equal bytes, worth a repeated run.
+fn starts_run(data: &[u8]) -> bool {
+    data.len() >= 3 && data[0] == data[1] && data[1] == data[2]
+}
+
+/// Compresses `data`.
+pub(crate) fn pack(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        if starts_run(&data[i..]) {
+            let byte = data[i];
+            let len = data[i..]
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|&&b| b == byte)
+                .count();
+            out.extend([(257 - len) as u8, byte]);
+            i += len;
+        } else {
+            let start = i;
+            while i < data.len() && i - start < MAX_RUN && !starts_run(&data[i..]) {
+                i += 1;
+            }
+            out.push((i - start - 1) as u8);
+            out.extend_from_slice(&data[start..i]);
+        }
+    }
+    out
+}
+
+/// Decompresses the bytes of `packed` from `from` into `dst`, without
+/// decompressing the others.
+pub(crate) fn unpack(packed: &[u8], from: usize, dst: &mut [u8]) {
+    let to = from + dst.len();
+    let (mut pos, mut i) = (0, 0);
+    while pos < to && i < packed.len() {
+        let n = packed[i] as usize;
+        let (len, literal) = if n < MAX_RUN {
+            (n + 1, true)
+        } else {
+            (257 - n, false)
+        };
+        let (start, end) = (pos.max(from), (pos + len).min(to));
+        if start < end {
+            let out = &mut dst[start - from..end - from];
+            if literal {
+                out.copy_from_slice(&packed[i + 1 + start - pos..i + 1 + end - pos]);
+            } else {
+                out.fill(packed[i + 1]);
+            }
+        }
+        i += 1 + if literal { len } else { 1 };
+        pos += len;
+    }
+}
+
+/// Compresses the files in `dir` and its subdirectories, see
+/// [`FileNode::compress`]. Returns the number of bytes saved.
+pub(crate) fn compress_dir(dir: &DirNode) -> usize {
+    let mut saved = 0;
+    for (_, node) in dir.child_nodes() {
+        let any = node.as_any();
+        if let Some(file) = any.downcast_ref::<FileNode>() {
+            saved += file.compress();
+        } else if let Some(dir) = any.downcast_ref::<DirNode>() {
+            saved += compress_dir(dir);
+        }
+    }
+    saved
+}
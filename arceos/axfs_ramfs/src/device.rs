@@ -0,0 +1,119 @@
+use alloc::sync::Arc;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm};
+use axfs_vfs::{VfsNodeRef, VfsNodeType, VfsResult};
+
+use crate::meta::{FsContext, NodeMeta, NodeTimes};
+
+/// Major and minor numbers of a device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeviceId {
+    /// Identifies the driver.
+    pub major: u32,
+    /// Identifies the device among those of the driver.
+    pub minor: u32,
+}
+
+impl DeviceId {
+    /// Creates a device ID from its major and minor numbers.
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+/// The character or block device node in the RAM filesystem.
+///
+/// It only holds the [`DeviceId`]. The operations on its content are passed
+/// to the device registered with that ID by
+/// [`RamFileSystem::register_device`](crate::RamFileSystem::register_device),
+/// and fail with [`NotFound`](axfs_vfs::VfsError::NotFound) if there is none.
+pub struct DeviceNode {
+    ty: VfsNodeType,
+    id: DeviceId,
+    meta: NodeMeta,
+}
+
+impl DeviceNode {
+    pub(super) fn new(ty: VfsNodeType, id: DeviceId, ctx: Arc<FsContext>) -> Self {
+        Self {
+            ty,
+            id,
+            meta: NodeMeta::new(ctx, VfsNodePerm::default_file()),
+        }
+    }
+
+    pub(super) fn clone_in(&self, ctx: Arc<FsContext>) -> Self {
+        ctx.charge(0);
+        Self {
+            ty: self.ty,
+            id: self.id,
+            meta: self.meta.clone_in(ctx),
+        }
+    }
+
+    pub(super) fn meta(&self) -> &NodeMeta {
+        &self.meta
+    }
+
+    /// Returns the timestamps of the node.
+    pub fn times(&self) -> NodeTimes {
+        self.meta.times()
+    }
+
+    /// Returns the ID of the device.
+    pub fn id(&self) -> DeviceId {
+        self.id
+    }
+
+    /// Returns the registered device.
+    fn device(&self) -> VfsResult<VfsNodeRef> {
+        self.meta.ctx().device(self.ty, self.id)
+    }
+}
+
+impl Drop for DeviceNode {
+    fn drop(&mut self) {
+        self.meta.ctx().free_inode();
+    }
+}
+
+impl VfsNodeOps for DeviceNode {
+    fn open(&self) -> VfsResult {
+        self.device()?.open()
+    }
+
+    fn release(&self) -> VfsResult {
+        self.device()?.release()
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = self.meta.perm();
+        Ok(VfsNodeAttr::new(perm, self.ty, 0, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let n = self.device()?.read_at(offset, buf)?;
+        self.meta.accessed();
+        Ok(n)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let n = self.device()?.write_at(offset, buf)?;
+        self.meta.modified();
+        Ok(n)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.device()?.fsync()
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.device()?.truncate(size)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
+
+/// Returns whether `ty` is a device node type.
+pub(crate) fn is_device(ty: VfsNodeType) -> bool {
+    matches!(ty, VfsNodeType::CharDevice | VfsNodeType::BlockDevice)
+}
@@ -1,14 +1,21 @@
 use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
-use alloc::{string::String, vec::Vec};
-use alloc::string::ToString;
-#[allow(unused_imports)]
-use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
-use axfs_vfs::{VfsError, VfsResult};
-use log::debug;
+use alloc::{format, string::String, vec::Vec};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsOps, VfsResult};
 use spin::RwLock;
 
+use crate::device::{is_device, DeviceId, DeviceNode};
+use crate::entries::DirEntries;
+use crate::fifo::FifoNode;
 use crate::file::FileNode;
+use crate::meta::{FsContext, NodeMeta, NodeTimes};
+use crate::symlink::SymlinkNode;
+use crate::watch::{WatchEventKind, Watcher};
+
+/// Maximum number of symbolic links followed by a lookup, as `MAXSYMLINKS`
+/// in Linux.
+const MAX_SYMLINKS: usize = 40;
 
 /// The directory node in the RAM filesystem.
 /// 一个目录树啊
@@ -16,64 +23,564 @@ use crate::file::FileNode;
 pub struct DirNode {
     this: Weak<DirNode>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
-    children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    /// The entries, copied when they are changed while a
+    /// [snapshot](Self::entries) of them is in use. The lock is only held to
+    /// access them, never while calling into other nodes.
+    children: RwLock<Arc<DirEntries>>,
+    mount: RwLock<Option<Mount>>,
+    meta: NodeMeta,
+}
+
+/// The error of a recursive removal, see [`DirNode::remove_recursive`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoveError {
+    /// The path of the entry that could not be removed, relative to the
+    /// directory the removal started from.
+    pub path: String,
+    /// Why it could not be removed.
+    pub error: VfsError,
+}
+
+impl From<RemoveError> for VfsError {
+    fn from(err: RemoveError) -> Self {
+        err.error
+    }
+}
+
+/// A filesystem mounted on a directory.
+struct Mount {
+    fs: Arc<dyn VfsOps>,
+    root: VfsNodeRef,
+    /// References to `root` when it was mounted, more mean that it is in use.
+    root_refs: usize,
 }
 
 impl DirNode {
-    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
+    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>, ctx: Arc<FsContext>) -> Arc<Self> {
         Arc::new_cyclic(|this| Self {
             this: this.clone(),
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
-            children: RwLock::new(BTreeMap::new()),
+            children: RwLock::new(Arc::new(DirEntries::new(&ctx))),
+            mount: RwLock::new(None),
+            meta: NodeMeta::new(ctx, VfsNodePerm::default_dir()),
         })
     }
 
+    pub(super) fn meta(&self) -> &NodeMeta {
+        &self.meta
+    }
+
+    /// Returns the timestamps of the directory.
+    pub fn times(&self) -> NodeTimes {
+        self.meta.times()
+    }
+
+    /// Queues the changes of the entries and the attributes of the
+    /// directory to `watcher`.
+    pub fn watch(&self, watcher: &Watcher) {
+        self.meta.watch(watcher);
+    }
+
+    /// Mounts `fs` on this directory. The operations on the directory and the
+    /// lookups through it then go to the root directory of `fs`, whose `..`
+    /// is the parent of this directory.
+    ///
+    /// Returns [`VfsError::ResourceBusy`] if a filesystem is already mounted
+    /// here.
+    pub fn mount(&self, fs: Arc<dyn VfsOps>) -> VfsResult {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let mut mount = self.mount.write();
+        if mount.is_some() {
+            return Err(VfsError::ResourceBusy);
+        }
+        fs.mount("", this)?;
+        let root = fs.root_dir();
+        let root_refs = Arc::strong_count(&root);
+        *mount = Some(Mount {
+            fs,
+            root,
+            root_refs,
+        });
+        self.meta.ctx().lookups().set_mounted(true);
+        Ok(())
+    }
+
+    /// Unmounts the filesystem mounted on this directory.
+    ///
+    /// Returns [`VfsError::InvalidInput`] if there is none, and
+    /// [`VfsError::ResourceBusy`] if its root directory is still referenced,
+    /// e.g., as the current directory.
+    pub fn umount(&self) -> VfsResult {
+        let mut mount = self.mount.write();
+        let Some(m) = mount.as_ref() else {
+            return Err(VfsError::InvalidInput);
+        };
+        if Arc::strong_count(&m.root) > m.root_refs {
+            return Err(VfsError::ResourceBusy);
+        }
+        m.fs.umount()?;
+        *mount = None;
+        self.meta.ctx().lookups().set_mounted(false);
+        Ok(())
+    }
+
+    /// Returns whether a filesystem is mounted on this directory.
+    pub fn is_mount_point(&self) -> bool {
+        self.mount.read().is_some()
+    }
+
+    /// Returns the root directory of the filesystem mounted here, if any.
+    fn mounted(&self) -> Option<VfsNodeRef> {
+        self.mount.read().as_ref().map(|m| m.root.clone())
+    }
+
+    /// Looks `path` up from this directory, without the lookup cache.
+    ///
+    /// `links` counts the symbolic links followed so far.
+    fn walk(self: Arc<Self>, path: &str, links: &mut usize) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self
+                .children
+                .read()
+                .get(name)
+                .cloned()
+                .map(cross_mount)
+                .ok_or(VfsError::NotFound),
+        }?;
+        self.meta.accessed();
+        if let Some(link) = node.as_any().downcast_ref::<SymlinkNode>() {
+            let target = link.target();
+            if !target.is_empty() {
+                return self.follow(&target, rest, links);
+            }
+        }
+
+        let Some(rest) = rest else {
+            return Ok(node);
+        };
+        // 同一文件系统的子目录直接继续查找，不再查缓存
+        let dir = node.as_any().downcast_ref::<DirNode>();
+        match dir.and_then(|dir| dir.this.upgrade()) {
+            Some(dir) if Arc::ptr_eq(dir.meta.ctx(), self.meta.ctx()) => dir.walk(rest, links),
+            _ => node.lookup(rest),
+        }
+    }
+
+    /// Looks `rest` up from `target`, the target of a symbolic link in this
+    /// directory.
+    ///
+    /// Returns [`VfsError::InvalidInput`] after [`MAX_SYMLINKS`] links, e.g.,
+    /// for a link to itself.
+    fn follow(
+        self: Arc<Self>,
+        target: &str,
+        rest: Option<&str>,
+        links: &mut usize,
+    ) -> VfsResult<VfsNodeRef> {
+        *links += 1;
+        if *links > MAX_SYMLINKS {
+            return Err(VfsError::InvalidInput);
+        }
+        let path = match rest {
+            Some(rest) => format!("{}/{}", target, rest),
+            None => target.into(),
+        };
+        self.meta.ctx().check_path(&path)?;
+        // 绝对路径从这个文件系统的根目录开始
+        let start = if path.starts_with('/') {
+            self.fs_root()
+        } else {
+            self
+        };
+        start.walk(&path, links)
+    }
+
+    /// Returns the root directory of the filesystem of this directory.
+    fn fs_root(self: Arc<Self>) -> Arc<Self> {
+        let mut root = self;
+        while let Some(parent) = root.parent() {
+            let parent = parent.as_any().downcast_ref::<DirNode>();
+            match parent.and_then(|dir| dir.this.upgrade()) {
+                Some(dir) if Arc::ptr_eq(dir.meta.ctx(), root.meta.ctx()) => root = dir,
+                _ => break,
+            }
+        }
+        root
+    }
+
     pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
         *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
     }
 
+    /// Returns a snapshot of the entries, which does not block the changes
+    /// to the directory and is not affected by them.
+    fn entries(&self) -> Arc<DirEntries> {
+        self.children.read().clone()
+    }
+
     /// Returns a string list of all entries in this directory.
     pub fn get_entries(&self) -> Vec<String> {
-        self.children.read().keys().cloned().collect()
+        let children = self.entries();
+        children.iter().map(|(name, _)| name.into()).collect()
+    }
+
+    /// Calls `f` with the name and node of the entries in this directory, in
+    /// the order of [`get_entries`](Self::get_entries), until it returns
+    /// `false`. It starts after the entry named `last` if given, so that a
+    /// large directory can be listed in several calls without allocating.
+    ///
+    /// The entries present during all the calls are seen exactly once, even
+    /// if others are inserted or removed in between. `f` may modify the
+    /// directory, the changes are not seen by the current call.
+    pub fn for_each_entry(&self, last: Option<&str>, mut f: impl FnMut(&str, &VfsNodeRef) -> bool) {
+        let children = self.entries();
+        match last {
+            Some(last) => children.iter_after(last).all(|(name, node)| f(name, node)),
+            None => children.iter().all(|(name, node)| f(name, node)),
+        };
+    }
+
+    /// Returns the entries of this directory with their nodes.
+    pub(crate) fn child_nodes(&self) -> Vec<(String, VfsNodeRef)> {
+        let children = self.entries();
+        children
+            .iter()
+            .map(|(name, node)| (name.into(), node.clone()))
+            .collect()
+    }
+
+    /// Returns the node of the entry `name` of this directory, not following
+    /// it if it is a symbolic link.
+    pub(crate) fn child(&self, name: &str) -> VfsResult<VfsNodeRef> {
+        let node = self.children.read().get(name).cloned();
+        node.ok_or(VfsError::NotFound)
     }
 
     /// Checks whether a node with the given name exists in this directory.
     pub fn exist(&self, name: &str) -> bool {
-        self.children.read().contains_key(name)
+        self.children.read().contains(name)
     }
 
     /// Creates a new node with the given name and type in this directory.
+    ///
+    /// Illegal names return [`VfsError::InvalidInput`]: empty, `.`, `..`,
+    /// with a `/` or a NUL, or longer than
+    /// [`RamFsOptions::name_max`](crate::RamFsOptions::name_max).
+    ///
+    /// Device nodes get the ID `0:0`, see [`create_device`](Self::create_device).
     pub fn create_node(&self, name: &str, ty: VfsNodeType) -> VfsResult {
+        self.create_node_with(name, ty, DeviceId::default())
+    }
+
+    /// Creates a new character or block device node with the given name and
+    /// device ID in this directory.
+    pub fn create_device(&self, name: &str, ty: VfsNodeType, id: DeviceId) -> VfsResult {
+        if !is_device(ty) {
+            return Err(VfsError::InvalidInput);
+        }
+        self.create_node_with(name, ty, id)
+    }
+
+    /// Creates a symbolic link to `target` with the given name in this
+    /// directory, see [`SymlinkNode`].
+    ///
+    /// An empty target or one longer than
+    /// [`RamFsOptions::path_max`](crate::RamFsOptions::path_max) returns
+    /// [`VfsError::InvalidInput`].
+    pub fn symlink(&self, name: &str, target: &str) -> VfsResult {
+        if target.is_empty() {
+            return Err(VfsError::InvalidInput);
+        }
+        self.meta.ctx().check_path(target)?;
+        self.add_node(name, |ctx| {
+            Ok(Arc::new(SymlinkNode::new(ctx.clone(), target)))
+        })
+    }
+
+    fn create_node_with(&self, name: &str, ty: VfsNodeType, id: DeviceId) -> VfsResult {
+        self.add_node(name, |ctx| {
+            Ok(match ty {
+                VfsNodeType::File => Arc::new(FileNode::new(ctx.clone())),
+                VfsNodeType::Dir => Self::new(Some(self.this.clone()), ctx.clone()),
+                VfsNodeType::CharDevice | VfsNodeType::BlockDevice => {
+                    Arc::new(DeviceNode::new(ty, id, ctx.clone()))
+                }
+                VfsNodeType::Fifo => Arc::new(FifoNode::new(ctx.clone())),
+                VfsNodeType::SymLink => Arc::new(SymlinkNode::new(ctx.clone(), "")),
+                _ => return Err(VfsError::Unsupported),
+            })
+        })
+    }
+
+    /// Adds the node returned by `new` to this directory under the given
+    /// name, as a new inode of the filesystem.
+    fn add_node(
+        &self,
+        name: &str,
+        new: impl FnOnce(&Arc<FsContext>) -> VfsResult<VfsNodeRef>,
+    ) -> VfsResult {
+        self.meta.ctx().check_writable()?;
+        self.meta.ctx().check_name(name)?;
         if self.exist(name) {
             log::error!("AlreadyExists {}", name);
             return Err(VfsError::AlreadyExists);
         }
-        let node: VfsNodeRef = match ty {
-            VfsNodeType::File => Arc::new(FileNode::new()),
-            VfsNodeType::Dir => Self::new(Some(self.this.clone())),
-            _ => return Err(VfsError::Unsupported),
+        let ctx = self.meta.ctx();
+        ctx.alloc_inode()?;
+        let node = match new(ctx) {
+            Ok(node) => node,
+            Err(err) => {
+                ctx.free_inode();
+                return Err(err);
+            }
         };
-        self.children.write().insert(name.into(), node);
+        Arc::make_mut(&mut self.children.write()).insert(name, node);
+        ctx.lookups().invalidate();
+        self.meta.modified();
+        self.meta.notify(WatchEventKind::Create, Some(name));
+        Ok(())
+    }
+
+    /// Adds `node` to this directory under the given name, as a hard link.
+    ///
+    /// Only files of the same filesystem can be linked, directories return
+    /// [`VfsError::PermissionDenied`]. The file data is freed when the file
+    /// has no name and no user left, it can't be linked anymore then and
+    /// returns [`VfsError::NotFound`].
+    ///
+    /// The number of names of a file is reported by
+    /// [`get_attr`](VfsNodeOps::get_attr), see [`VfsNodeAttr::nlink`].
+    pub fn link(&self, name: &str, node: VfsNodeRef) -> VfsResult {
+        let Some(file) = node.as_any().downcast_ref::<FileNode>() else {
+            if node.as_any().is::<DirNode>() {
+                return Err(VfsError::PermissionDenied);
+            }
+            return Err(VfsError::Unsupported);
+        };
+        if !Arc::ptr_eq(file.meta().ctx(), self.meta.ctx()) {
+            return Err(VfsError::Unsupported);
+        }
+        self.meta.ctx().check_writable()?;
+        self.meta.ctx().check_name(name)?;
+        let mut children = self.children.write();
+        if children.contains(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        file.inc_nlink()?;
+        Arc::make_mut(&mut children).insert(name, node);
+        self.meta.ctx().lookups().invalidate();
+        self.meta.modified();
+        self.meta.notify(WatchEventKind::Create, Some(name));
         Ok(())
     }
 
     /// Removes a node by the given name in this directory.
     pub fn remove_node(&self, name: &str) -> VfsResult {
+        self.meta.ctx().check_writable()?;
         let mut children = self.children.write();
         let node = children.get(name).ok_or(VfsError::NotFound)?;
         if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            if dir.is_mount_point() {
+                return Err(VfsError::ResourceBusy);
+            }
             if !dir.children.read().is_empty() {
                 return Err(VfsError::DirectoryNotEmpty);
             }
         }
-        children.remove(name);
+        if let Some(node) = Arc::make_mut(&mut children).remove(name) {
+            if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+                file.dec_nlink();
+            }
+        }
+        self.meta.ctx().lookups().invalidate();
+        self.meta.modified();
+        self.meta.notify(WatchEventKind::Remove, Some(name));
         Ok(())
     }
+
+    /// Removes the entry `name` of this directory and, if it is a directory,
+    /// everything below it.
+    ///
+    /// The subtree is removed bottom-up by [`remove_node`](Self::remove_node),
+    /// so a single directory is locked at a time. On failure, the entries
+    /// already removed stay removed and the error tells which one could not
+    /// be. Mount points are not crossed, they fail with
+    /// [`VfsError::ResourceBusy`].
+    pub fn remove_recursive(&self, name: &str) -> Result<(), RemoveError> {
+        let fail = |error| RemoveError {
+            path: name.into(),
+            error,
+        };
+        let node = self.children.read().get(name).cloned();
+        let node = node.ok_or_else(|| fail(VfsError::NotFound))?;
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            if !dir.is_mount_point() {
+                for (child, _) in dir.child_nodes() {
+                    dir.remove_recursive(&child).map_err(|err| RemoveError {
+                        path: format!("{}/{}", name, err.path),
+                        error: err.error,
+                    })?;
+                }
+            }
+        }
+        self.remove_node(name).map_err(fail)
+    }
+
+    /// Removes the entry at `path`, relative to this directory, and
+    /// everything below it, see [`remove_recursive`](Self::remove_recursive).
+    pub(crate) fn remove_all(&self, path: &str) -> Result<(), RemoveError> {
+        let fail = |error| RemoveError {
+            path: path.into(),
+            error,
+        };
+        if let Some(root) = self.mounted() {
+            let root = root.as_any().downcast_ref::<DirNode>();
+            return root.ok_or(fail(VfsError::Unsupported))?.remove_all(path);
+        }
+        self.meta.ctx().check_path(path).map_err(fail)?;
+        let (dir, name) = self.parent_of(path).map_err(fail)?;
+        let prefix = &path[..path.trim_end_matches('/').len() - name.len()];
+        dir.remove_recursive(name).map_err(|err| RemoveError {
+            path: format!("{}{}", prefix, err.path),
+            error: err.error,
+        })
+    }
+
+    /// Returns the target of the symbolic link at `path`, relative to this
+    /// directory. The last component of `path` is not followed, and returns
+    /// [`VfsError::InvalidInput`] if it is not a symbolic link.
+    pub fn read_link(&self, path: &str) -> VfsResult<String> {
+        if let Some(root) = self.mounted() {
+            let root = root.as_any().downcast_ref::<DirNode>();
+            return root.ok_or(VfsError::Unsupported)?.read_link(path);
+        }
+        self.meta.ctx().check_path(path)?;
+        let (dir, name) = self.parent_of(path)?;
+        let node = dir.child(name)?;
+        let link = node.as_any().downcast_ref::<SymlinkNode>();
+        Ok(link.ok_or(VfsError::InvalidInput)?.target())
+    }
+
+    /// Returns the directory containing the last component of `path`, and
+    /// the name of that component.
+    fn parent_of<'a>(&self, path: &'a str) -> VfsResult<(Arc<Self>, &'a str)> {
+        let (dir, name) = split_rpath(path.trim_end_matches('/'));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let Some(dir) = dir else {
+            return Ok((this, name));
+        };
+        let node = this.lookup(dir)?;
+        let Some(dir) = node.as_any().downcast_ref::<DirNode>() else {
+            if node.get_attr()?.is_dir() {
+                return Err(VfsError::Unsupported); // another filesystem
+            }
+            return Err(VfsError::NotADirectory);
+        };
+        Ok((dir.this.upgrade().ok_or(VfsError::NotFound)?, name))
+    }
+
+    /// Returns a copy of this directory and its descendants for the
+    /// filesystem `ctx`, see [`clone_children_into`](Self::clone_children_into).
+    pub(super) fn clone_in(
+        &self,
+        parent: Option<Weak<dyn VfsNodeOps>>,
+        ctx: Arc<FsContext>,
+        links: &mut BTreeMap<*const (), VfsNodeRef>,
+    ) -> Arc<Self> {
+        let copy = Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
+            children: RwLock::new(Arc::new(DirEntries::new(&ctx))),
+            mount: RwLock::new(None),
+            meta: self.meta.clone_in(ctx),
+        });
+        self.clone_children_into(&copy, links);
+        copy
+    }
+
+    /// Replaces the children of `dst`, a directory of another filesystem, by
+    /// copies of those of this directory.
+    ///
+    /// `links` maps the files already copied to their copies, so that hard
+    /// links stay shared.
+    pub(super) fn clone_children_into(
+        &self,
+        dst: &Arc<DirNode>,
+        links: &mut BTreeMap<*const (), VfsNodeRef>,
+    ) {
+        let ctx = dst.meta.ctx();
+        let mut children = DirEntries::new(ctx);
+        for (name, node) in self.entries().iter() {
+            let any = node.as_any();
+            let copy: VfsNodeRef = if let Some(dir) = any.downcast_ref::<DirNode>() {
+                ctx.charge(0);
+                let parent = Arc::downgrade(dst) as _;
+                dir.clone_in(Some(parent), ctx.clone(), links)
+            } else if let Some(file) = any.downcast_ref::<FileNode>() {
+                let key = Arc::as_ptr(node) as *const ();
+                let copy = links
+                    .entry(key)
+                    .or_insert_with(|| Arc::new(file.clone_in(ctx.clone())) as VfsNodeRef);
+                copy.clone()
+            } else if let Some(dev) = any.downcast_ref::<DeviceNode>() {
+                Arc::new(dev.clone_in(ctx.clone()))
+            } else if let Some(fifo) = any.downcast_ref::<FifoNode>() {
+                Arc::new(fifo.clone_in(ctx.clone()))
+            } else if let Some(link) = any.downcast_ref::<SymlinkNode>() {
+                Arc::new(link.clone_in(ctx.clone()))
+            } else {
+                continue; // not created by the RAM filesystem
+            };
+            children.insert(name, copy);
+        }
+        *dst.children.write() = Arc::new(children);
+        ctx.lookups().invalidate();
+    }
+
+    /// Returns the entry `name` of this directory to continue a path in it,
+    /// following it if it is a symbolic link.
+    fn subdir(&self, name: &str) -> VfsResult<VfsNodeRef> {
+        let node = self.child(name)?;
+        if node.as_any().is::<SymlinkNode>() {
+            let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+            return this.walk(name, &mut 0);
+        }
+        Ok(node)
+    }
+
+    /// Returns whether this directory is `dir` or one of its descendants.
+    fn is_within(&self, dir: &DirNode) -> bool {
+        let mut cur = self.this.upgrade().map(|this| this as VfsNodeRef);
+        while let Some(node) = cur {
+            match node.as_any().downcast_ref::<DirNode>() {
+                Some(d) if core::ptr::eq(d, dir) => return true,
+                Some(d) => cur = d.parent(),
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+impl Drop for DirNode {
+    fn drop(&mut self) {
+        self.meta.ctx().free_inode();
+    }
 }
 
 impl VfsNodeOps for DirNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(4096, 0))
+        if let Some(root) = self.mounted() {
+            return root.get_attr();
+        }
+        let perm = self.meta.perm();
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::Dir, 4096, 0))
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
@@ -81,41 +588,57 @@ impl VfsNodeOps for DirNode {
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
-        let (name, rest) = split_path(path);
-        let node = match name {
-            "" | "." => Ok(self.clone() as VfsNodeRef),
-            ".." => self.parent().ok_or(VfsError::NotFound),
-            _ => self
-                .children
-                .read()
-                .get(name)
-                .cloned()
-                .ok_or(VfsError::NotFound),
-        }?;
+        if let Some(root) = self.mounted() {
+            return root.lookup(path);
+        }
+        self.meta.ctx().check_path(path)?;
+        let cached = self.meta.ctx().lookups().get(&self, path);
+        match cached {
+            None => self.walk(path, &mut 0),
+            Some(Ok(result)) => result,
+            Some(Err((generation, key))) => {
+                let result = self.clone().walk(path, &mut 0);
+                let mut lookups = self.meta.ctx().lookups();
+                lookups.insert(generation, self.this.clone(), key, &result);
+                result
+            }
+        }
+    }
 
-        if let Some(rest) = rest {
-            node.lookup(rest)
-        } else {
-            Ok(node)
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        if let Some(root) = self.mounted() {
+            return root.read_dir(start_idx, dirents);
+        }
+        let children = self.entries();
+        let mut children = children.iter().skip(start_idx.max(2) - 2);
+        let mut n = 0;
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            *ent = match i + start_idx {
+                0 => VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => match children.next() {
+                    Some((name, node)) => VfsDirEntry::new(name, node.get_attr()?.file_type()),
+                    None => break,
+                },
+            };
+            n += 1;
         }
+        self.meta.accessed();
+        Ok(n)
     }
 
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
         log::debug!("create {:?} at ramfs: {}", ty, path);
+        if let Some(root) = self.mounted() {
+            return root.create(path, ty);
+        }
+        self.meta.ctx().check_path(path)?;
         let (name, rest) = split_path(path);
         if let Some(rest) = rest {
             match name {
                 "" | "." => self.create(rest, ty),
                 ".." => self.parent().ok_or(VfsError::NotFound)?.create(rest, ty),
-                _ => {
-                    let subdir = self
-                        .children
-                        .read()
-                        .get(name)
-                        .ok_or(VfsError::NotFound)?
-                        .clone();
-                    subdir.create(rest, ty)
-                }
+                _ => self.subdir(name)?.create(rest, ty),
             }
         } else if name.is_empty() || name == "." || name == ".." {
             Ok(()) // already exists
@@ -126,20 +649,16 @@ impl VfsNodeOps for DirNode {
 
     fn remove(&self, path: &str) -> VfsResult {
         log::debug!("remove at ramfs: {}", path);
+        if let Some(root) = self.mounted() {
+            return root.remove(path);
+        }
+        self.meta.ctx().check_path(path)?;
         let (name, rest) = split_path(path);
         if let Some(rest) = rest {
             match name {
                 "" | "." => self.remove(rest),
                 ".." => self.parent().ok_or(VfsError::NotFound)?.remove(rest),
-                _ => {
-                    let subdir = self
-                        .children
-                        .read()
-                        .get(name)
-                        .ok_or(VfsError::NotFound)?
-                        .clone();
-                    subdir.remove(rest)
-                }
+                _ => self.subdir(name)?.remove(rest),
             }
         } else if name.is_empty() || name == "." || name == ".." {
             Err(VfsError::InvalidInput)
@@ -148,48 +667,136 @@ impl VfsNodeOps for DirNode {
         }
     }
 
-    fn rename(&self, src: &str, dst: &str) -> VfsResult {
-        log::debug!("\n\nself: [{:?}]\n\n", self.children.read().keys().cloned().collect::<Vec<_>>());
-        let this = self.this.upgrade().unwrap();
-        let Ok(node) = this.clone().lookup(src) else {
-            return Err(VfsError::NotFound);
-        };
-        if let Ok(_) = this.clone().lookup(dst) {
-            return Err(VfsError::AlreadyExists);
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        log::debug!("rename at ramfs: {} -> {}", src_path, dst_path);
+        if let Some(root) = self.mounted() {
+            return root.rename(src_path, dst_path);
+        }
+        let ctx = self.meta.ctx();
+        ctx.check_path(src_path)?;
+        ctx.check_path(dst_path)?;
+        let (src_dir, src_name) = self.parent_of(src_path)?;
+        let (dst_dir, dst_name) = self.parent_of(dst_path)?;
+        // 挂载的另一个 ramfs 也是 DirNode, 但不能跨文件系统移动
+        let ctx = dst_dir.meta.ctx();
+        if !Arc::ptr_eq(src_dir.meta.ctx(), ctx) {
+            return Err(VfsError::Unsupported);
         }
+        ctx.check_writable()?;
+        ctx.check_name(dst_name)?;
 
-        let (_dst_dir, dst_name) = split_rpath(dst);
-        // match dst_dir {
-        //     None => {
-        //         let mut children = self.children.write();
-        //         children.insert(dst_name.to_string(), node);
-        //         children.remove(src);
-        //     }
-        //     Some(prefix) => {
-        //         let this = self.parent().expect("xx").as_any()
-        //             .downcast_ref::<DirNode>()
-        //             .unwrap().this.upgrade().unwrap();
-        //         let Ok(dir) = this.clone().lookup(prefix) else {
-        //             return Err(VfsError::NotFound);
-        //         };
-        //         let dir = dir.as_any().downcast_ref::<DirNode>().unwrap();
-        //         let mut children = dir.children.write();
-        //         children.insert(dst_name.to_string(), node);
-        //         let mut children = self.children.write();
-        //         children.remove(src);
-        //     }
-        // }
-        let mut children = self.children.write();
-        children.insert(dst_name.to_string(), node);
-        log::debug!("x..............................xx");
-        children.remove(src);
-        // log::debug!("\n\nself-after: [{:?}]\n\n", self.children.read().keys().cloned().collect::<Vec<_>>());
+        let node = src_dir.children.read().get(src_name).cloned();
+        let node = node.ok_or(VfsError::NotFound)?;
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            if dir.is_mount_point() {
+                return Err(VfsError::ResourceBusy);
+            }
+            // 不能把目录移到它自己的子树里
+            if dst_dir.is_within(dir) {
+                return Err(VfsError::InvalidInput);
+            }
+        }
+
+        // 目标在同一次加锁中被替换, 查找不会看到它不存在的时刻
+        let replaced = if Arc::ptr_eq(&src_dir, &dst_dir) {
+            let mut children = src_dir.children.write();
+            let children = Arc::make_mut(&mut children);
+            let node = children.get(src_name).cloned().ok_or(VfsError::NotFound)?;
+            // 忽略大小写时, 只改大小写的重命名不算替换
+            if children.key(dst_name) != children.key(src_name) {
+                if let Some(old) = children.get(dst_name) {
+                    if Arc::ptr_eq(&node, old) {
+                        return Ok(());
+                    }
+                    check_replace(&node, old, None)?;
+                }
+            }
+            children.remove(src_name);
+            children.insert(dst_name, node)
+        } else {
+            // 两个目录按地址顺序加锁，避免反向的 rename 死锁
+            let src_first = Arc::as_ptr(&src_dir) < Arc::as_ptr(&dst_dir);
+            let (mut src_children, mut dst_children) = if src_first {
+                let src_children = src_dir.children.write();
+                (src_children, dst_dir.children.write())
+            } else {
+                let dst_children = dst_dir.children.write();
+                (src_dir.children.write(), dst_children)
+            };
+            let node = src_children.get(src_name).cloned();
+            let node = node.ok_or(VfsError::NotFound)?;
+            if let Some(old) = dst_children.get(dst_name) {
+                if Arc::ptr_eq(&node, old) {
+                    return Ok(());
+                }
+                // 被替换的目录可能就是源目录, 它已经加锁了
+                let old_is_src = Arc::as_ptr(old) as *const () == Arc::as_ptr(&src_dir) as _;
+                check_replace(&node, old, old_is_src.then_some(&**src_children))?;
+            }
+            Arc::make_mut(&mut src_children).remove(src_name);
+            if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+                dir.set_parent(Some(&(dst_dir.clone() as VfsNodeRef)));
+            }
+            dst_dir.meta.modified();
+            Arc::make_mut(&mut dst_children).insert(dst_name, node)
+        };
+        ctx.lookups().invalidate();
+        if let Some(old) = replaced {
+            if let Some(file) = old.as_any().downcast_ref::<FileNode>() {
+                file.dec_nlink();
+            }
+        }
+        src_dir.meta.modified();
+        if let Ok(meta) = crate::node_meta(node.as_ref()) {
+            meta.changed();
+        }
+        let (src_name, dst_name) = (Some(src_name), Some(dst_name));
+        src_dir.meta.notify(WatchEventKind::MovedFrom, src_name);
+        dst_dir.meta.notify(WatchEventKind::MovedTo, dst_name);
         Ok(())
     }
 
     axfs_vfs::impl_vfs_dir_default! {}
 }
 
+/// Returns the root directory of the filesystem mounted on `node`, or `node`
+/// itself if it is not a mount point.
+fn cross_mount(node: VfsNodeRef) -> VfsNodeRef {
+    let root = node
+        .as_any()
+        .downcast_ref::<DirNode>()
+        .and_then(DirNode::mounted);
+    root.unwrap_or(node)
+}
+
+/// Checks that `node` can replace `old` in a rename: a directory can only
+/// replace an empty directory, and other nodes anything but a directory.
+///
+/// `old_children` are the entries of `old` if they are already locked.
+fn check_replace(
+    node: &VfsNodeRef,
+    old: &VfsNodeRef,
+    old_children: Option<&DirEntries>,
+) -> VfsResult {
+    let is_dir = node.as_any().is::<DirNode>();
+    match old.as_any().downcast_ref::<DirNode>() {
+        Some(old) if old.is_mount_point() => Err(VfsError::ResourceBusy),
+        Some(old) if is_dir => {
+            let empty = match old_children {
+                Some(children) => children.is_empty(),
+                None => old.children.read().is_empty(),
+            };
+            if !empty {
+                return Err(VfsError::DirectoryNotEmpty);
+            }
+            Ok(())
+        }
+        Some(_) => Err(VfsError::IsADirectory),
+        None if is_dir => Err(VfsError::NotADirectory),
+        None => Ok(()),
+    }
+}
+
 /// a/b/c  ->  a Some(b/c)
 /// 但是只做了一级
 fn split_path(path: &str) -> (&str, Option<&str>) {
@@ -200,6 +807,7 @@ fn split_path(path: &str) -> (&str, Option<&str>) {
     })
 }
 
+/// a/b/c  ->  Some(a/b) c
 fn split_rpath(path: &str) -> (Option<&str>, &str) {
     let trimmed_path = path.trim_start_matches('/');
     trimmed_path.rfind('/').map_or((None, trimmed_path), |n| {
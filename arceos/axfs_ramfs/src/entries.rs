@@ -0,0 +1,205 @@
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use axfs_vfs::VfsNodeRef;
+use core::ops::Bound;
+
+use crate::meta::FsContext;
+
+/// An entry: its name and node.
+type Entry = (String, VfsNodeRef);
+
+/// The entries of a directory, by name.
+///
+/// With `fold_case`, names that only differ by case are the same entry, which
+/// keeps the name it was inserted with.
+#[derive(Clone)]
+pub(crate) struct DirEntries {
+    map: Map,
+    fold_case: bool,
+}
+
+#[derive(Clone)]
+enum Map {
+    /// The entries by their key.
+    Sorted(BTreeMap<String, Entry>),
+    /// The entries in a hash table, for large directories.
+    Hashed(HashTable),
+}
+
+impl DirEntries {
+    pub(crate) fn new(ctx: &FsContext) -> Self {
+        let map = if ctx.hashed_dirs() {
+            Map::Hashed(HashTable::default())
+        } else {
+            Map::Sorted(BTreeMap::new())
+        };
+        Self {
+            map,
+            fold_case: ctx.fold_case(),
+        }
+    }
+
+    /// Returns the key of `name`, the same for all the names of an entry.
+    pub(crate) fn key<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if self.fold_case {
+            Cow::Owned(name.to_lowercase())
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&VfsNodeRef> {
+        let key = self.key(name);
+        let entry = match &self.map {
+            Map::Sorted(map) => map.get(key.as_ref()),
+            Map::Hashed(table) => table.get(hash(&key), &key),
+        };
+        entry.map(|(_, node)| node)
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Inserts an entry, replacing the one with the same key.
+    pub(crate) fn insert(&mut self, name: &str, node: VfsNodeRef) -> Option<VfsNodeRef> {
+        let key = self.key(name).into_owned();
+        let old = match &mut self.map {
+            Map::Sorted(map) => map.insert(key, (name.into(), node)),
+            Map::Hashed(table) => table.insert(hash(&key), key, (name.into(), node)),
+        };
+        old.map(|(_, node)| node)
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> Option<VfsNodeRef> {
+        let key = self.key(name);
+        let old = match &mut self.map {
+            Map::Sorted(map) => map.remove(key.as_ref()),
+            Map::Hashed(table) => table.remove(hash(&key), &key),
+        };
+        old.map(|(_, node)| node)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        match &self.map {
+            Map::Sorted(map) => map.is_empty(),
+            Map::Hashed(table) => table.len == 0,
+        }
+    }
+
+    /// Returns the names and nodes of the entries, sorted by key, or by hash
+    /// if the directories of the filesystem are hashed.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &VfsNodeRef)> {
+        let (sorted, hashed) = match &self.map {
+            Map::Sorted(map) => (Some(map.values()), None),
+            Map::Hashed(table) => (None, Some(table.iter_from(0, ""))),
+        };
+        let entries = sorted.into_iter().flatten();
+        let entries = entries.chain(hashed.into_iter().flatten());
+        entries.map(|(name, node)| (name.as_str(), node))
+    }
+
+    /// Returns the names and nodes of the entries after the one named `last`,
+    /// whether it exists or not, in the order of [`iter`](Self::iter).
+    pub(crate) fn iter_after(&self, last: &str) -> impl Iterator<Item = (&str, &VfsNodeRef)> {
+        let key = self.key(last);
+        let (sorted, hashed) = match &self.map {
+            Map::Sorted(map) => {
+                let range = (Bound::Excluded(key.as_ref()), Bound::Unbounded);
+                (Some(map.range::<str, _>(range)), None)
+            }
+            Map::Hashed(table) => (None, Some(table.iter_after(hash(&key), &key))),
+        };
+        let entries = sorted.into_iter().flatten().map(|(_, entry)| entry);
+        let entries = entries.chain(hashed.into_iter().flatten());
+        entries.map(|(name, node)| (name.as_str(), node))
+    }
+}
+
+/// A hash table whose buckets are indexed by the high bits of the hashes, so
+/// that going through them in order lists the entries by hash, whatever the
+/// number of buckets. It keeps the order of [`DirEntries::iter`] stable.
+#[derive(Clone, Default)]
+struct HashTable {
+    /// The buckets, each sorted by hash and key.
+    buckets: Vec<Vec<(u64, String, Entry)>>,
+    len: usize,
+}
+
+impl HashTable {
+    fn bucket(&self, hash: u64) -> usize {
+        let bits = self.buckets.len().trailing_zeros();
+        hash.checked_shr(64 - bits).unwrap_or(0) as usize
+    }
+
+    /// Returns the position of an entry in its bucket, or where to insert it.
+    fn find(&self, hash: u64, key: &str) -> (usize, Result<usize, usize>) {
+        let idx = self.bucket(hash);
+        let bucket = self.buckets.get(idx).map_or(&[][..], Vec::as_slice);
+        let pos = bucket.binary_search_by(|(h, k, _)| (*h, k.as_str()).cmp(&(hash, key)));
+        (idx, pos)
+    }
+
+    fn get(&self, hash: u64, key: &str) -> Option<&Entry> {
+        match self.find(hash, key) {
+            (idx, Ok(pos)) => Some(&self.buckets[idx][pos].2),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, hash: u64, key: String, entry: Entry) -> Option<Entry> {
+        if self.len >= self.buckets.len() {
+            self.grow();
+        }
+        match self.find(hash, &key) {
+            (idx, Ok(pos)) => Some(core::mem::replace(&mut self.buckets[idx][pos].2, entry)),
+            (idx, Err(pos)) => {
+                self.buckets[idx].insert(pos, (hash, key, entry));
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    fn remove(&mut self, hash: u64, key: &str) -> Option<Entry> {
+        let (idx, pos) = self.find(hash, key);
+        let (_, _, entry) = self.buckets[idx].remove(pos.ok()?);
+        self.len -= 1;
+        Some(entry)
+    }
+
+    /// Doubles the number of buckets, keeping the order of the entries.
+    fn grow(&mut self) {
+        let old = core::mem::take(&mut self.buckets);
+        self.buckets.resize_with((old.len() * 2).max(8), Vec::new);
+        for (hash, key, entry) in old.into_iter().flatten() {
+            let idx = self.bucket(hash);
+            self.buckets[idx].push((hash, key, entry));
+        }
+    }
+
+    /// Returns the entries from the one with the given hash and key, in order.
+    fn iter_from(&self, hash: u64, key: &str) -> impl Iterator<Item = &Entry> {
+        let (idx, pos) = self.find(hash, key);
+        let first = self.buckets.get(idx).into_iter();
+        let first = first.flat_map(move |bucket| &bucket[pos.unwrap_or_else(|pos| pos)..]);
+        let rest = self.buckets.get(idx + 1..).into_iter().flatten().flatten();
+        first.chain(rest).map(|(_, _, entry)| entry)
+    }
+
+    /// Returns the entries after the one with the given hash and key, which
+    /// may not exist, in order.
+    fn iter_after(&self, hash: u64, key: &str) -> impl Iterator<Item = &Entry> {
+        let exists = self.get(hash, key).is_some();
+        self.iter_from(hash, key).skip(exists as usize)
+    }
+}
+
+/// Hashes a key with FNV-1a.
+fn hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
@@ -0,0 +1,102 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm};
+use axfs_vfs::{VfsNodeType, VfsResult};
+use spin::Mutex;
+
+use crate::meta::{FsContext, NodeMeta, NodeTimes};
+
+/// Maximum number of bytes buffered in a FIFO.
+const FIFO_CAPACITY: usize = 4096;
+
+/// The named pipe (FIFO) node in the RAM filesystem.
+///
+/// The bytes written are buffered in a ring buffer until they are read, the
+/// offsets are ignored. The filesystem cannot block, so reading an empty
+/// FIFO or writing a full one returns [`VfsError::WouldBlock`], and the
+/// caller waits and tries again. A write only partially done when the
+/// buffer becomes full returns the number of bytes written.
+pub struct FifoNode {
+    buf: Mutex<VecDeque<u8>>,
+    meta: NodeMeta,
+}
+
+impl FifoNode {
+    pub(super) fn new(ctx: Arc<FsContext>) -> Self {
+        Self {
+            buf: Mutex::new(VecDeque::new()),
+            meta: NodeMeta::new(ctx, VfsNodePerm::default_file()),
+        }
+    }
+
+    /// Returns an empty FIFO with the same metadata for the filesystem `ctx`.
+    pub(super) fn clone_in(&self, ctx: Arc<FsContext>) -> Self {
+        ctx.charge(0);
+        Self {
+            buf: Mutex::new(VecDeque::new()),
+            meta: self.meta.clone_in(ctx),
+        }
+    }
+
+    pub(super) fn meta(&self) -> &NodeMeta {
+        &self.meta
+    }
+
+    /// Returns the timestamps of the FIFO.
+    pub fn times(&self) -> NodeTimes {
+        self.meta.times()
+    }
+}
+
+impl Drop for FifoNode {
+    fn drop(&mut self) {
+        self.meta.ctx().free_inode();
+    }
+}
+
+impl VfsNodeOps for FifoNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let size = self.buf.lock().len() as u64;
+        let perm = self.meta.perm();
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::Fifo, size, 0))
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        // 和 Linux 一样, 打开时的截断对 FIFO 没有作用
+        Ok(())
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut fifo = self.buf.lock();
+        if fifo.is_empty() {
+            return Err(VfsError::WouldBlock);
+        }
+        let n = buf.len().min(fifo.len());
+        for (dst, src) in buf.iter_mut().zip(fifo.drain(..n)) {
+            *dst = src;
+        }
+        drop(fifo);
+        self.meta.accessed();
+        Ok(n)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut fifo = self.buf.lock();
+        let n = buf.len().min(FIFO_CAPACITY - fifo.len());
+        if n == 0 {
+            return Err(VfsError::WouldBlock);
+        }
+        fifo.extend(&buf[..n]);
+        drop(fifo);
+        self.meta.modified();
+        Ok(n)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
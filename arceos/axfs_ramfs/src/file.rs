@@ -1,55 +1,579 @@
-use alloc::vec::Vec;
-use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm};
+use axfs_vfs::{VfsNodeType, VfsResult};
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "compress")]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::RwLock;
 
+use crate::lock::{FileLock, FlockOp};
+use crate::meta::{FsContext, NodeMeta, NodeTimes};
+use crate::watch::{WatchEventKind, Watcher};
+
+/// Size of the blocks counted in [`VfsNodeAttr::blocks`].
+const BLOCK_SIZE: usize = 512;
+
+/// Size of the pages returned by [`FileNode::pin_page`].
+pub const PAGE_SIZE: usize = 4096;
+
+/// Size of the chunks holding the file data, one page so that they can be
+/// mapped.
+const CHUNK_SIZE: usize = PAGE_SIZE;
+
+/// A chunk, shared with the snapshots until it is written.
+type Chunk = Arc<Page>;
+
+/// The data of a chunk, in its own page-aligned allocation.
+#[derive(Clone)]
+struct Page(Box<Aligned>);
+
+/// A chunk aligned to [`PAGE_SIZE`].
+#[derive(Clone)]
+#[repr(align(4096))]
+struct Aligned([u8; CHUNK_SIZE]);
+
+impl Page {
+    fn zeroed() -> Chunk {
+        Arc::new(Self(Box::new(Aligned([0; CHUNK_SIZE]))))
+    }
+}
+
+impl Deref for Page {
+    type Target = [u8; CHUNK_SIZE];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0 .0
+    }
+}
+
+impl DerefMut for Page {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0 .0
+    }
+}
+
+/// A page of a file pinned in memory, see [`FileNode::pin_page`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilePage {
+    /// The virtual address of the page, aligned to [`PAGE_SIZE`].
+    pub vaddr: usize,
+    /// The offset of the page in the file.
+    pub offset: u64,
+    /// Number of bytes of the page inside the file when it was pinned.
+    pub len: usize,
+}
+
+/// The content of a file, in chunks indexed by their position.
+#[derive(Default)]
+struct Content {
+    /// The chunks that have been written, the others are zero.
+    chunks: BTreeMap<usize, Chunk>,
+    /// The compressed chunks, which are not in `chunks`.
+    #[cfg(feature = "compress")]
+    packed: BTreeMap<usize, Arc<[u8]>>,
+    /// Sum of the sizes of the compressed chunks.
+    #[cfg(feature = "compress")]
+    packed_size: usize,
+    /// Number of pins of the pinned chunks, see [`FileNode::pin_page`].
+    pins: BTreeMap<usize, usize>,
+    /// The size of the file.
+    size: usize,
+}
+
+impl Content {
+    fn alloc_size(&self) -> usize {
+        #[cfg(feature = "compress")]
+        return self.chunks.len() * CHUNK_SIZE + self.packed_size;
+        #[cfg(not(feature = "compress"))]
+        return self.chunks.len() * CHUNK_SIZE;
+    }
+
+    /// Returns the number of bytes to allocate to write the chunk `idx`.
+    fn missing_bytes(&self, idx: usize) -> usize {
+        if self.chunks.contains_key(&idx) {
+            return 0;
+        }
+        #[cfg(feature = "compress")]
+        if let Some(packed) = self.packed.get(&idx) {
+            return CHUNK_SIZE - packed.len();
+        }
+        CHUNK_SIZE
+    }
+
+    /// Returns the chunk `idx` to write it, decompressed or allocated if
+    /// needed. The memory must have been accounted for, see
+    /// [`missing_bytes`](Self::missing_bytes).
+    #[cfg_attr(not(feature = "compress"), allow(unused_variables))]
+    fn chunk_mut(&mut self, idx: usize, ctx: &FsContext) -> &mut Page {
+        #[cfg(feature = "compress")]
+        self.unpack(idx, ctx);
+        let chunk = self.chunks.entry(idx).or_insert_with(Page::zeroed);
+        // 和快照共享的块在这里复制
+        Arc::make_mut(chunk)
+    }
+
+    /// Reads the bytes of the chunk `idx` from `from` into `dst`.
+    fn read(&self, idx: usize, from: usize, dst: &mut [u8]) {
+        if let Some(chunk) = self.chunks.get(&idx) {
+            dst.copy_from_slice(&chunk[from..from + dst.len()]);
+            return;
+        }
+        #[cfg(feature = "compress")]
+        if let Some(packed) = self.packed.get(&idx) {
+            crate::compress::unpack(packed, from, dst);
+            return;
+        }
+        // 未写过的块读出来是 0
+        dst.fill(0);
+    }
+
+    /// Returns a copy sharing the chunks, except the pinned ones which are
+    /// copied, as they must stay where they are mapped.
+    fn share(&self) -> Self {
+        let chunks = self.chunks.range(..self.size.div_ceil(CHUNK_SIZE));
+        let chunks = chunks.map(|(&idx, chunk)| {
+            if self.pins.contains_key(&idx) {
+                (idx, Arc::new(Page::clone(chunk)))
+            } else {
+                (idx, chunk.clone())
+            }
+        });
+        #[cfg(feature = "compress")]
+        let packed: BTreeMap<_, _> = self
+            .packed
+            .range(..self.size.div_ceil(CHUNK_SIZE))
+            .map(|(&idx, packed)| (idx, packed.clone()))
+            .collect();
+        Self {
+            chunks: chunks.collect(),
+            #[cfg(feature = "compress")]
+            packed_size: packed.values().map(|packed| packed.len()).sum(),
+            #[cfg(feature = "compress")]
+            packed,
+            pins: BTreeMap::new(),
+            size: self.size,
+        }
+    }
+
+    /// Drops the chunks past `size` and zeroes the end of the last one,
+    /// which must not be compressed.
+    ///
+    /// The pinned chunks are zeroed instead, and freed when unpinned.
+    fn shrink(&mut self, size: usize, ctx: &FsContext) {
+        let end = size.div_ceil(CHUNK_SIZE);
+        for (idx, mut chunk) in self.chunks.split_off(&end) {
+            if self.pins.contains_key(&idx) {
+                Arc::make_mut(&mut chunk).fill(0);
+                self.chunks.insert(idx, chunk);
+            } else {
+                ctx.free_bytes(CHUNK_SIZE);
+            }
+        }
+        #[cfg(feature = "compress")]
+        for (_, packed) in self.packed.split_off(&end) {
+            self.packed_size -= packed.len();
+            ctx.free_bytes(packed.len());
+            ctx.sub_packed(1, packed.len());
+        }
+        // 最后一块中被截掉的部分清零, 以便再次扩展时读出 0
+        if let Some(chunk) = self.chunks.get_mut(&(size / CHUNK_SIZE)) {
+            Arc::make_mut(chunk)[size % CHUNK_SIZE..].fill(0);
+        }
+    }
+
+    /// Compresses the chunk `idx` if it gets at least twice smaller. Returns
+    /// the number of bytes saved.
+    #[cfg(feature = "compress")]
+    fn pack(&mut self, idx: usize, ctx: &FsContext) -> usize {
+        let packed = crate::compress::pack(&self.chunks[&idx][..]);
+        if packed.len() > CHUNK_SIZE / 2 {
+            return 0;
+        }
+        self.chunks.remove(&idx);
+        self.packed_size += packed.len();
+        ctx.add_packed(1, packed.len());
+        let saved = CHUNK_SIZE - packed.len();
+        ctx.free_bytes(saved);
+        self.packed.insert(idx, packed.into());
+        saved
+    }
+
+    /// Decompresses the chunk `idx` if it is compressed. The memory must have
+    /// been accounted for, see [`missing_bytes`](Self::missing_bytes).
+    #[cfg(feature = "compress")]
+    fn unpack(&mut self, idx: usize, ctx: &FsContext) {
+        if let Some(packed) = self.packed.remove(&idx) {
+            let mut chunk = Page::zeroed();
+            crate::compress::unpack(&packed, 0, &mut Arc::make_mut(&mut chunk)[..]);
+            self.chunks.insert(idx, chunk);
+            self.packed_size -= packed.len();
+            ctx.sub_packed(1, packed.len());
+        }
+    }
+
+    /// Calls `f` with each chunk index and the range in that chunk covered
+    /// by `start..end`.
+    fn for_each_span(start: usize, end: usize, mut f: impl FnMut(usize, usize, usize)) {
+        let mut pos = start;
+        while pos < end {
+            let (idx, off) = (pos / CHUNK_SIZE, pos % CHUNK_SIZE);
+            let len = (CHUNK_SIZE - off).min(end - pos);
+            f(idx, off, off + len);
+            pos += len;
+        }
+    }
+}
+
 /// The file node in the RAM filesystem.
 ///
-/// It implements [`axfs_vfs::VfsNodeOps`].
+/// It implements [`axfs_vfs::VfsNodeOps`]. The data is kept in page-sized
+/// chunks, which are only allocated when written, so extending a file by
+/// [`truncate`](VfsNodeOps::truncate) does not allocate memory.
+///
+/// Like in POSIX, a file removed while it is open keeps its data until it is
+/// [released](VfsNodeOps::release) by all the users that opened it and its
+/// pages are [unpinned](Self::unpin_page). It is then freed, even if the node
+/// is still referenced.
+///
+/// The pages of a file can be [pinned](Self::pin_page) to map them directly
+/// into an address space.
 pub struct FileNode {
-    content: RwLock<Vec<u8>>,
+    content: RwLock<Content>,
+    nlink: AtomicUsize,
+    /// Number of [`open`](VfsNodeOps::open) not yet released.
+    opens: AtomicUsize,
+    lock: FileLock,
+    /// Whether [`compress`](Self::compress) compresses the data.
+    #[cfg(feature = "compress")]
+    compressible: AtomicBool,
+    meta: NodeMeta,
 }
 
 impl FileNode {
-    pub(super) const fn new() -> Self {
+    pub(super) fn new(ctx: Arc<FsContext>) -> Self {
+        Self {
+            content: RwLock::new(Content::default()),
+            nlink: AtomicUsize::new(1),
+            opens: AtomicUsize::new(0),
+            lock: FileLock::default(),
+            #[cfg(feature = "compress")]
+            compressible: AtomicBool::new(true),
+            meta: NodeMeta::new(ctx, VfsNodePerm::default_file()),
+        }
+    }
+
+    /// Creates a file with no name, open once, see
+    /// [`RamFileSystem::create_anonymous_file`](crate::RamFileSystem::create_anonymous_file).
+    pub(super) fn new_anonymous(ctx: Arc<FsContext>) -> Self {
+        let file = Self::new(ctx);
+        file.nlink.store(0, Ordering::Relaxed);
+        file.opens.store(1, Ordering::Relaxed);
+        file
+    }
+
+    /// Returns a copy of the file for the filesystem `ctx`, sharing the data
+    /// until it is written.
+    pub(super) fn clone_in(&self, ctx: Arc<FsContext>) -> Self {
+        let content = self.content.read().share();
+        ctx.charge(content.alloc_size());
+        ctx.resize_file(0, content.size);
+        #[cfg(feature = "compress")]
+        ctx.add_packed(content.packed.len(), content.packed_size);
         Self {
-            content: RwLock::new(Vec::new()),
+            content: RwLock::new(content),
+            nlink: AtomicUsize::new(self.nlink()),
+            opens: AtomicUsize::new(0),
+            lock: FileLock::default(),
+            #[cfg(feature = "compress")]
+            compressible: AtomicBool::new(self.compressible.load(Ordering::Relaxed)),
+            meta: self.meta.clone_in(ctx),
+        }
+    }
+
+    pub(super) fn meta(&self) -> &NodeMeta {
+        &self.meta
+    }
+
+    /// Returns the timestamps of the file.
+    pub fn times(&self) -> NodeTimes {
+        self.meta.times()
+    }
+
+    /// Returns the number of names of the file, see [`DirNode::link`]. It is
+    /// also reported by [`get_attr`](VfsNodeOps::get_attr).
+    ///
+    /// [`DirNode::link`]: crate::DirNode::link
+    pub fn nlink(&self) -> usize {
+        self.nlink.load(Ordering::Acquire)
+    }
+
+    /// Takes, converts or releases the advisory lock of the file for
+    /// `owner`, a token chosen by the caller, e.g., the address of the open
+    /// file.
+    ///
+    /// The lock is not enforced on reads and writes. Returns
+    /// [`VfsError::WouldBlock`] if another owner holds a conflicting lock.
+    pub fn flock(&self, owner: u64, op: FlockOp) -> VfsResult {
+        self.lock.apply(owner, op)
+    }
+
+    /// Queues the writes and attribute changes of the file to `watcher`.
+    pub fn watch(&self, watcher: &Watcher) {
+        self.meta.watch(watcher);
+    }
+
+    /// Allocates the storage of `offset..offset + len` without changing the
+    /// size of the file, like `fallocate` with `FALLOC_FL_KEEP_SIZE`, so that
+    /// the writes to this range can't fail with [`VfsError::StorageFull`].
+    ///
+    /// Returns [`VfsError::StorageFull`] without allocating anything if it
+    /// exceeds the quota. The storage past the end of the file is freed by
+    /// [`truncate`](VfsNodeOps::truncate), and that not written yet may be by
+    /// `compress` with the `compress` feature.
+    pub fn reserve(&self, offset: u64, len: u64) -> VfsResult {
+        let offset = usize::try_from(offset).map_err(|_| VfsError::InvalidInput)?;
+        let len = usize::try_from(len).map_err(|_| VfsError::InvalidInput)?;
+        let end = offset.checked_add(len).ok_or(VfsError::InvalidInput)?;
+        let ctx = self.meta.ctx();
+        ctx.check_writable()?;
+        let mut content = self.content.write();
+        let mut new_bytes = 0;
+        Content::for_each_span(offset, end, |idx, _, _| {
+            new_bytes += content.missing_bytes(idx);
+        });
+        ctx.alloc_bytes(new_bytes)?;
+        Content::for_each_span(offset, end, |idx, _, _| {
+            content.chunk_mut(idx, ctx);
+        });
+        Ok(())
+    }
+
+    /// Pins the page holding `offset` in memory and returns where it is, so
+    /// that it can be mapped instead of copied by
+    /// [`read_at`](VfsNodeOps::read_at). The page is allocated if it is a
+    /// hole.
+    ///
+    /// Until it is [unpinned](Self::unpin_page), the page stays at the same
+    /// address and holds the data of the file: writes to the file go to it,
+    /// and writes through the mapping are seen by the readers. Truncating the
+    /// file zeroes it instead of freeing it. The caller must keep a reference
+    /// to the node while its pages are pinned.
+    ///
+    /// Returns [`VfsError::InvalidInput`] if `offset` is past the last page
+    /// of the file.
+    pub fn pin_page(&self, offset: u64) -> VfsResult<FilePage> {
+        let idx = usize::try_from(offset).map_err(|_| VfsError::InvalidInput)? / CHUNK_SIZE;
+        let mut content = self.content.write();
+        if idx >= content.size.div_ceil(CHUNK_SIZE) {
+            return Err(VfsError::InvalidInput);
+        }
+        let ctx = self.meta.ctx();
+        ctx.alloc_bytes(content.missing_bytes(idx))?;
+        // 和快照共享的块要先复制, 之后写文件时它就不会再被换掉
+        let vaddr = content.chunk_mut(idx, ctx).as_ptr() as usize;
+        *content.pins.entry(idx).or_default() += 1;
+        Ok(FilePage {
+            vaddr,
+            offset: (idx * CHUNK_SIZE) as u64,
+            len: CHUNK_SIZE.min(content.size - idx * CHUNK_SIZE),
+        })
+    }
+
+    /// Unpins the page holding `offset`, pinned by
+    /// [`pin_page`](Self::pin_page). It is freed if the file was truncated
+    /// before it in the meantime.
+    ///
+    /// Returns [`VfsError::InvalidInput`] if the page is not pinned.
+    pub fn unpin_page(&self, offset: u64) -> VfsResult {
+        let idx = usize::try_from(offset).map_err(|_| VfsError::InvalidInput)? / CHUNK_SIZE;
+        let mut content = self.content.write();
+        let pins = content.pins.get_mut(&idx).ok_or(VfsError::InvalidInput)?;
+        *pins -= 1;
+        if *pins == 0 {
+            content.pins.remove(&idx);
+            if idx >= content.size.div_ceil(CHUNK_SIZE) {
+                content.chunks.remove(&idx);
+                self.meta.ctx().free_bytes(CHUNK_SIZE);
+            }
+            if content.pins.is_empty() {
+                drop(content);
+                self.free_if_unused();
+            }
+        }
+        Ok(())
+    }
+
+    /// Compresses the chunks of the file that are not
+    /// [pinned](Self::pin_page), those that get at least twice smaller are
+    /// kept compressed until they are written. Returns the number of bytes
+    /// saved.
+    ///
+    /// It does nothing if the file opted out, see
+    /// [`set_compressible`](Self::set_compressible).
+    #[cfg(feature = "compress")]
+    pub fn compress(&self) -> usize {
+        if !self.compressible.load(Ordering::Relaxed) {
+            return 0;
+        }
+        let mut content = self.content.write();
+        let unpinned = content
+            .chunks
+            .keys()
+            .filter(|idx| !content.pins.contains_key(idx));
+        let unpinned: alloc::vec::Vec<_> = unpinned.copied().collect();
+        let ctx = self.meta.ctx();
+        unpinned.into_iter().map(|idx| content.pack(idx, ctx)).sum()
+    }
+
+    /// Sets whether [`compress`](Self::compress) compresses the file, e.g.,
+    /// to opt out for a file often written. The data already compressed is
+    /// kept so.
+    #[cfg(feature = "compress")]
+    pub fn set_compressible(&self, compressible: bool) {
+        self.compressible.store(compressible, Ordering::Relaxed);
+    }
+
+    /// Adds a name to the file, or returns [`VfsError::NotFound`] if its data
+    /// has been freed, see [`free_if_unused`](Self::free_if_unused).
+    pub(super) fn inc_nlink(&self) -> VfsResult {
+        // 持有锁, 不和 free_if_unused 同时进行
+        let content = self.content.read();
+        let unused = self.nlink() == 0 && self.opens.load(Ordering::Acquire) == 0;
+        if unused && content.pins.is_empty() {
+            return Err(VfsError::NotFound);
+        }
+        self.nlink.fetch_add(1, Ordering::AcqRel);
+        self.meta.changed();
+        Ok(())
+    }
+
+    pub(super) fn dec_nlink(&self) {
+        if self.nlink.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.free_if_unused();
+        }
+        self.meta.changed();
+    }
+
+    /// Frees the data once the file has no name, is not open and has no pinned
+    /// page anymore.
+    fn free_if_unused(&self) {
+        let mut content = self.content.write();
+        let unused = self.nlink() == 0 && self.opens.load(Ordering::Acquire) == 0;
+        if unused && content.pins.is_empty() {
+            let ctx = self.meta.ctx();
+            ctx.free_bytes(content.alloc_size());
+            ctx.resize_file(content.size, 0);
+            #[cfg(feature = "compress")]
+            ctx.sub_packed(content.packed.len(), content.packed_size);
+            *content = Content::default();
         }
     }
 }
 
+impl Drop for FileNode {
+    fn drop(&mut self) {
+        let ctx = self.meta.ctx();
+        let content = self.content.get_mut();
+        ctx.free_bytes(content.alloc_size());
+        ctx.resize_file(content.size, 0);
+        #[cfg(feature = "compress")]
+        ctx.sub_packed(content.packed.len(), content.packed_size);
+        ctx.free_inode();
+    }
+}
+
 impl VfsNodeOps for FileNode {
+    fn open(&self) -> VfsResult {
+        self.opens.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn release(&self) -> VfsResult {
+        let opens = self
+            .opens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+        if opens == Ok(1) {
+            self.free_if_unused();
+        }
+        Ok(())
+    }
+
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+        let content = self.content.read();
+        let size = content.size as u64;
+        let blocks = (content.alloc_size() / BLOCK_SIZE) as u64;
+        let perm = self.meta.perm();
+        let mut attr = VfsNodeAttr::new(perm, VfsNodeType::File, size, blocks);
+        attr.set_nlink(self.nlink() as u64);
+        Ok(attr)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
+        let size = usize::try_from(size).map_err(|_| VfsError::InvalidInput)?;
+        self.meta.ctx().check_writable()?;
         let mut content = self.content.write();
-        if size < content.len() as u64 {
-            content.truncate(size as _);
-        } else {
-            content.resize(size as _, 0);
+        if size < content.size {
+            let ctx = self.meta.ctx();
+            // 截断处的压缩块要先解压才能清零
+            #[cfg(feature = "compress")]
+            if size % CHUNK_SIZE != 0 && content.packed.contains_key(&(size / CHUNK_SIZE)) {
+                ctx.alloc_bytes(content.missing_bytes(size / CHUNK_SIZE))?;
+                content.unpack(size / CHUNK_SIZE, ctx);
+            }
+            content.shrink(size, ctx);
         }
+        self.meta.ctx().resize_file(content.size, size);
+        content.size = size;
+        self.meta.modified();
+        self.meta.notify(WatchEventKind::Modify, None);
         Ok(())
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         let content = self.content.read();
-        let start = content.len().min(offset as usize);
-        let end = content.len().min(offset as usize + buf.len());
-        let src = &content[start..end];
-        buf[..src.len()].copy_from_slice(src);
-        Ok(src.len())
+        let start = content.size.min(offset.try_into().unwrap_or(usize::MAX));
+        let end = content.size.min(start.saturating_add(buf.len()));
+        let mut buf = &mut buf[..end - start];
+        let n = buf.len();
+        Content::for_each_span(start, end, |idx, from, to| {
+            let (dst, rest) = core::mem::take(&mut buf).split_at_mut(to - from);
+            content.read(idx, from, dst);
+            buf = rest;
+        });
+        self.meta.accessed();
+        Ok(n)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let offset = offset as usize;
+        let offset = usize::try_from(offset).map_err(|_| VfsError::InvalidInput)?;
+        let end = offset
+            .checked_add(buf.len())
+            .ok_or(VfsError::InvalidInput)?;
+        self.meta.ctx().check_writable()?;
         let mut content = self.content.write();
-        if offset + buf.len() > content.len() {
-            content.resize(offset + buf.len(), 0);
+        // Accounts for the new chunks first, so that a write is not partial.
+        let mut new_bytes = 0;
+        Content::for_each_span(offset, end, |idx, _, _| {
+            new_bytes += content.missing_bytes(idx);
+        });
+        let ctx = self.meta.ctx();
+        ctx.alloc_bytes(new_bytes)?;
+        let mut buf = buf;
+        Content::for_each_span(offset, end, |idx, from, to| {
+            let (src, rest) = buf.split_at(to - from);
+            content.chunk_mut(idx, ctx)[from..to].copy_from_slice(src);
+            buf = rest;
+        });
+        if end > content.size {
+            self.meta.ctx().resize_file(content.size, end);
+            content.size = end;
         }
-        let dst = &mut content[offset..offset + buf.len()];
-        dst.copy_from_slice(&buf[..dst.len()]);
-        Ok(buf.len())
+        self.meta.modified();
+        self.meta.notify(WatchEventKind::Modify, None);
+        Ok(end - offset)
     }
 
     impl_vfs_non_dir_default! {}
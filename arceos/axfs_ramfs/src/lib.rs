@@ -6,19 +6,45 @@
 
 extern crate alloc;
 
+mod archive;
+mod cache;
+#[cfg(feature = "compress")]
+mod compress;
+mod device;
 mod dir;
+mod entries;
+mod fifo;
 mod file;
+mod lock;
+mod meta;
+mod snapshot;
+mod symlink;
+mod watch;
 
 #[cfg(test)]
 mod tests;
 
-pub use self::dir::DirNode;
-pub use self::file::FileNode;
+pub use self::device::{DeviceId, DeviceNode};
+pub use self::dir::{DirNode, RemoveError};
+pub use self::fifo::FifoNode;
+pub use self::file::{FileNode, FilePage, PAGE_SIZE};
+pub use self::lock::FlockOp;
+pub use self::meta::{NodeTimes, RamFsOptions, RamFsStats, LOOKUP_CACHE_SIZE, NAME_MAX, PATH_MAX};
+pub use self::snapshot::Snapshot;
+pub use self::symlink::SymlinkNode;
+pub use self::watch::{WatchEvent, WatchEventKind, Watcher};
 
+use alloc::string::String;
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use axfs_vfs::{
+    FileSystemInfo, VfsError, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsOps, VfsResult,
+};
+use core::time::Duration;
 use spin::once::Once;
 
+use self::device::is_device;
+use self::meta::{FsContext, NodeMeta};
+
 /// A RAM filesystem that implements [`axfs_vfs::VfsOps`].
 pub struct RamFileSystem {
     parent: Once<VfsNodeRef>,
@@ -26,11 +52,32 @@ pub struct RamFileSystem {
 }
 
 impl RamFileSystem {
-    /// Create a new instance.
+    /// Create a new instance, with the default [`RamFsOptions`].
+    ///
+    /// The timestamps of its nodes are all zero, see
+    /// [`with_clock`](Self::with_clock).
     pub fn new() -> Self {
+        Self::with_options(RamFsOptions::default())
+    }
+
+    /// Create a new instance, whose node timestamps are given by `clock`,
+    /// e.g., the wall time of the platform.
+    pub fn with_clock(clock: fn() -> Duration) -> Self {
+        Self::with_options(RamFsOptions {
+            clock: Some(clock),
+            ..Default::default()
+        })
+    }
+
+    /// Create a new instance with the given options.
+    pub fn with_options(options: RamFsOptions) -> Self {
+        Self::with_root(DirNode::new(None, FsContext::new(options)))
+    }
+
+    fn with_root(root: Arc<DirNode>) -> Self {
         Self {
             parent: Once::new(),
-            root: DirNode::new(None),
+            root,
         }
     }
 
@@ -38,6 +85,58 @@ impl RamFileSystem {
     pub fn root_dir_node(&self) -> Arc<DirNode> {
         self.root.clone()
     }
+
+    /// Returns the statistics of the filesystem, kept up to date by the
+    /// operations on its nodes.
+    ///
+    /// [`statfs`](VfsOps::statfs) reports them in pages, as far as
+    /// [`FileSystemInfo`] can hold them.
+    pub fn stats(&self) -> RamFsStats {
+        self.root.meta().ctx().stats()
+    }
+
+    /// Compresses the file data not written since the last call, see
+    /// [`FileNode::compress`]. Returns the number of bytes saved.
+    ///
+    /// The data is decompressed on reads, and a compressed page is kept
+    /// decompressed once written.
+    #[cfg(feature = "compress")]
+    pub fn compress(&self) -> usize {
+        compress::compress_dir(&self.root)
+    }
+
+    /// Creates a file not linked into any directory, e.g., to write it
+    /// before publishing it atomically with [`link_into`], or as a scratch
+    /// buffer.
+    ///
+    /// The file is returned open, as by [`open`](VfsNodeOps::open). Its data
+    /// is freed once it is [released](VfsNodeOps::release), unless it has
+    /// been linked in the meantime.
+    pub fn create_anonymous_file(&self) -> VfsResult<VfsNodeRef> {
+        let ctx = self.root.meta().ctx();
+        ctx.check_writable()?;
+        ctx.alloc_inode()?;
+        Ok(Arc::new(FileNode::new_anonymous(ctx.clone())))
+    }
+
+    /// Registers `dev` as the device of the device nodes with the given type
+    /// and ID, see [`DirNode::create_device`]. Their operations are passed to
+    /// it, e.g., [`read_at`](VfsNodeOps::read_at).
+    ///
+    /// Returns [`VfsError::InvalidInput`] if `ty` is not a device type, and
+    /// [`VfsError::AlreadyExists`] if a device is already registered.
+    pub fn register_device(&self, ty: VfsNodeType, id: DeviceId, dev: VfsNodeRef) -> VfsResult {
+        if !is_device(ty) {
+            return Err(VfsError::InvalidInput);
+        }
+        self.root.meta().ctx().register_device(ty, id, dev)
+    }
+
+    /// Unregisters the device with the given type and ID. The device nodes
+    /// are kept, but their operations fail until another one is registered.
+    pub fn unregister_device(&self, ty: VfsNodeType, id: DeviceId) -> VfsResult {
+        self.root.meta().ctx().unregister_device(ty, id)
+    }
 }
 
 impl VfsOps for RamFileSystem {
@@ -50,11 +149,151 @@ impl VfsOps for RamFileSystem {
         Ok(())
     }
 
+    /// Returns the numbers of [`stats`](Self::stats), with one block per
+    /// page. The totals are zero without a quota.
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        let stats = self.stats();
+        let pages = |bytes: usize| (bytes / PAGE_SIZE) as u64;
+        let (blocks, blocks_free) = match stats.max_bytes {
+            Some(max) => (pages(max), pages(max.saturating_sub(stats.used_bytes))),
+            None => (0, 0),
+        };
+        let (files, files_free) = match stats.max_inodes {
+            Some(max) => (max as u64, max.saturating_sub(stats.used_inodes) as u64),
+            None => (0, 0),
+        };
+        Ok(FileSystemInfo::new(
+            PAGE_SIZE as u64,
+            blocks,
+            blocks_free,
+            files,
+            files_free,
+            stats.max_name_len.unwrap_or(0) as u64,
+        ))
+    }
+
     fn root_dir(&self) -> VfsNodeRef {
         self.root.clone()
     }
 }
 
+/// Returns the metadata of a node of a RAM filesystem.
+pub(crate) fn node_meta(node: &dyn VfsNodeOps) -> VfsResult<&NodeMeta> {
+    let any = node.as_any();
+    if let Some(file) = any.downcast_ref::<FileNode>() {
+        Ok(file.meta())
+    } else if let Some(dir) = any.downcast_ref::<DirNode>() {
+        Ok(dir.meta())
+    } else if let Some(dev) = any.downcast_ref::<DeviceNode>() {
+        Ok(dev.meta())
+    } else if let Some(fifo) = any.downcast_ref::<FifoNode>() {
+        Ok(fifo.meta())
+    } else if let Some(link) = any.downcast_ref::<SymlinkNode>() {
+        Ok(link.meta())
+    } else {
+        Err(VfsError::Unsupported)
+    }
+}
+
+/// Changes the permissions of a node, returned by its
+/// [`get_attr`](VfsNodeOps::get_attr).
+///
+/// [`axfs_vfs`] has no such operation, so it only works on nodes of a RAM
+/// filesystem, others return [`VfsError::Unsupported`].
+pub fn chmod(node: &VfsNodeRef, perm: VfsNodePerm) -> VfsResult {
+    let meta = node_meta(node.as_ref())?;
+    meta.ctx().check_writable()?;
+    meta.set_perm(perm);
+    Ok(())
+}
+
+/// Changes the user and group IDs of the owner of a node, those that are
+/// `None` are kept.
+///
+/// It only works on nodes of a RAM filesystem, like [`chmod`].
+pub fn chown(node: &VfsNodeRef, uid: Option<u32>, gid: Option<u32>) -> VfsResult {
+    let meta = node_meta(node.as_ref())?;
+    meta.ctx().check_writable()?;
+    meta.set_owner(uid, gid);
+    Ok(())
+}
+
+/// Returns the user and group IDs of the owner of a node.
+///
+/// It only works on nodes of a RAM filesystem, like [`chmod`].
+pub fn owner(node: &VfsNodeRef) -> VfsResult<(u32, u32)> {
+    Ok(node_meta(node.as_ref())?.owner())
+}
+
+/// Queues the events on a node to `watcher`, see [`Watcher`].
+///
+/// It only works on nodes of a RAM filesystem, like [`chmod`].
+pub fn watch(node: &VfsNodeRef, watcher: &Watcher) -> VfsResult {
+    node_meta(node.as_ref())?.watch(watcher);
+    Ok(())
+}
+
+/// Removes the entry at `path`, relative to the directory `dir`, and
+/// everything below it, like `rm -r`. See [`DirNode::remove_recursive`].
+///
+/// It only works on directories of a RAM filesystem, like [`chmod`].
+pub fn remove_all(dir: &VfsNodeRef, path: &str) -> Result<(), RemoveError> {
+    match dir.as_any().downcast_ref::<DirNode>() {
+        Some(dir) => dir.remove_all(path),
+        None => Err(RemoveError {
+            path: path.into(),
+            error: VfsError::Unsupported,
+        }),
+    }
+}
+
+/// Links `file`, e.g., created by
+/// [`create_anonymous_file`](RamFileSystem::create_anonymous_file), into the
+/// directory `dir` under the given name, see [`DirNode::link`].
+///
+/// It only works on directories of a RAM filesystem, like [`chmod`].
+pub fn link_into(file: &VfsNodeRef, dir: &VfsNodeRef, name: &str) -> VfsResult {
+    match dir.as_any().downcast_ref::<DirNode>() {
+        Some(dir) => dir.link(name, file.clone()),
+        None => Err(VfsError::Unsupported),
+    }
+}
+
+/// Returns the target of the symbolic link at `path`, relative to the
+/// directory `dir`, like `readlink`. See [`DirNode::read_link`].
+///
+/// It only works on directories of a RAM filesystem, like [`chmod`].
+pub fn read_link(dir: &VfsNodeRef, path: &str) -> VfsResult<String> {
+    match dir.as_any().downcast_ref::<DirNode>() {
+        Some(dir) => dir.read_link(path),
+        None => Err(VfsError::Unsupported),
+    }
+}
+
+/// Takes, converts or releases the advisory lock of a file for `owner`, see
+/// [`FileNode::flock`].
+///
+/// [`axfs_vfs`] has no such operation, so it only works on files of a RAM
+/// filesystem, others return [`VfsError::Unsupported`].
+pub fn flock(node: &VfsNodeRef, owner: u64, op: FlockOp) -> VfsResult {
+    match node.as_any().downcast_ref::<FileNode>() {
+        Some(file) => file.flock(owner, op),
+        None => Err(VfsError::Unsupported),
+    }
+}
+
+/// Allocates the storage of a range of a file without changing its size, see
+/// [`FileNode::reserve`].
+///
+/// [`axfs_vfs`] has no such operation, so it only works on files of a RAM
+/// filesystem, like [`flock`].
+pub fn fallocate(node: &VfsNodeRef, offset: u64, len: u64) -> VfsResult {
+    match node.as_any().downcast_ref::<FileNode>() {
+        Some(file) => file.reserve(offset, len),
+        None => Err(VfsError::Unsupported),
+    }
+}
+
 impl Default for RamFileSystem {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,61 @@
+use alloc::collections::BTreeSet;
+use axfs_vfs::{VfsError, VfsResult};
+use spin::Mutex;
+
+/// An operation on an advisory lock, see [`flock`](crate::flock).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlockOp {
+    /// Takes a shared lock, which several owners can hold at once.
+    Shared,
+    /// Takes an exclusive lock, which excludes all the other owners.
+    Exclusive,
+    /// Releases the lock held by the owner, if any.
+    Unlock,
+}
+
+#[derive(Default)]
+struct LockState {
+    exclusive: Option<u64>,
+    shared: BTreeSet<u64>,
+}
+
+/// A whole-file advisory lock, with the semantics of `flock(2)`.
+#[derive(Default)]
+pub(crate) struct FileLock {
+    state: Mutex<LockState>,
+}
+
+impl FileLock {
+    /// Applies `op` for `owner`. An owner holding the lock converts it to the
+    /// new kind.
+    ///
+    /// Returns [`VfsError::WouldBlock`] if the lock is held by another owner
+    /// in a conflicting way, the lock of `owner` is then unchanged.
+    pub(crate) fn apply(&self, owner: u64, op: FlockOp) -> VfsResult {
+        let mut state = self.state.lock();
+        match op {
+            FlockOp::Shared => {
+                if state.exclusive.is_some_and(|o| o != owner) {
+                    return Err(VfsError::WouldBlock);
+                }
+                state.exclusive = None;
+                state.shared.insert(owner);
+            }
+            FlockOp::Exclusive => {
+                let others_shared = state.shared.iter().any(|&o| o != owner);
+                if others_shared || state.exclusive.is_some_and(|o| o != owner) {
+                    return Err(VfsError::WouldBlock);
+                }
+                state.shared.remove(&owner);
+                state.exclusive = Some(owner);
+            }
+            FlockOp::Unlock => {
+                if state.exclusive == Some(owner) {
+                    state.exclusive = None;
+                }
+                state.shared.remove(&owner);
+            }
+        }
+        Ok(())
+    }
+}
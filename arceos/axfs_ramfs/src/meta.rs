@@ -0,0 +1,419 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+use spin::{Mutex, MutexGuard, RwLock};
+
+use crate::cache::LookupCache;
+use crate::device::DeviceId;
+use crate::watch::{WatchEventKind, WatchList, Watcher};
+
+/// Default maximum length of a name, in bytes.
+pub const NAME_MAX: usize = 255;
+/// Default maximum length of a path, in bytes.
+pub const PATH_MAX: usize = 4096;
+/// Default number of path lookups cached.
+pub const LOOKUP_CACHE_SIZE: usize = 64;
+
+/// Options of a [`RamFileSystem`](crate::RamFileSystem), see
+/// [`with_options`](crate::RamFileSystem::with_options).
+#[derive(Debug, Clone, Copy)]
+pub struct RamFsOptions {
+    /// Clock giving the timestamps of the nodes, which are zero without it.
+    pub clock: Option<fn() -> Duration>,
+    /// Maximum number of bytes of file data, unlimited if `None`. The holes
+    /// of sparse files are not counted.
+    pub max_bytes: Option<usize>,
+    /// Maximum number of nodes, including the root directory, unlimited if
+    /// `None`.
+    pub max_inodes: Option<usize>,
+    /// Whether names that only differ by case are the same, e.g., like in a
+    /// FAT filesystem. The names keep the case they were created with.
+    pub case_insensitive: bool,
+    /// Whether the entries of the directories are ordered by the hash of
+    /// their name, to speed up the directories with many entries. They are
+    /// then listed in that order instead of by name.
+    pub hashed_dirs: bool,
+    /// Maximum length of a name in bytes, [`NAME_MAX`] by default.
+    pub name_max: usize,
+    /// Maximum length of the paths given to the nodes in bytes, [`PATH_MAX`]
+    /// by default.
+    pub path_max: usize,
+    /// Number of recent path lookups whose results are cached, including
+    /// those that found nothing, [`LOOKUP_CACHE_SIZE`] by default. Zero
+    /// disables the cache.
+    pub lookup_cache: usize,
+}
+
+impl Default for RamFsOptions {
+    fn default() -> Self {
+        Self {
+            clock: None,
+            max_bytes: None,
+            max_inodes: None,
+            case_insensitive: false,
+            hashed_dirs: false,
+            name_max: NAME_MAX,
+            path_max: PATH_MAX,
+            lookup_cache: LOOKUP_CACHE_SIZE,
+        }
+    }
+}
+
+/// Statistics of a [`RamFileSystem`](crate::RamFileSystem), see
+/// [`stats`](crate::RamFileSystem::stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RamFsStats {
+    /// Bytes allocated for file data, in whole pages except the compressed
+    /// ones.
+    pub used_bytes: usize,
+    /// Quota of `used_bytes`, `None` if unlimited.
+    pub max_bytes: Option<usize>,
+    /// Sum of the sizes of the files, including their holes.
+    pub file_size: u64,
+    /// Number of nodes, including the root directory and the removed nodes
+    /// still in use.
+    pub used_inodes: usize,
+    /// Quota of `used_inodes`, `None` if unlimited.
+    pub max_inodes: Option<usize>,
+    /// Maximum length of a name in bytes, `None` if unlimited.
+    pub max_name_len: Option<usize>,
+    /// Number of compressed pages of file data, see
+    /// [`compress`](crate::RamFileSystem::compress).
+    #[cfg(feature = "compress")]
+    pub packed_pages: usize,
+    /// Bytes used by the compressed pages, included in `used_bytes`. The
+    /// compression ratio is `packed_pages * PAGE_SIZE / packed_bytes`.
+    #[cfg(feature = "compress")]
+    pub packed_bytes: usize,
+    /// Number of path lookups answered by the cache, see
+    /// [`RamFsOptions::lookup_cache`].
+    pub lookup_hits: u64,
+    /// Number of path lookups that could be cached but were not.
+    pub lookup_misses: u64,
+}
+
+/// The state shared by all nodes of a [`RamFileSystem`](crate::RamFileSystem).
+pub(crate) struct FsContext {
+    options: RamFsOptions,
+    /// Whether the filesystem is a [`Snapshot`](crate::Snapshot).
+    read_only: bool,
+    used_bytes: AtomicUsize,
+    used_inodes: AtomicUsize,
+    file_size: AtomicU64,
+    #[cfg(feature = "compress")]
+    packed_pages: AtomicUsize,
+    #[cfg(feature = "compress")]
+    packed_bytes: AtomicUsize,
+    /// Devices of the device nodes, by node type and ID.
+    devices: RwLock<BTreeMap<(u8, DeviceId), VfsNodeRef>>,
+    lookups: Mutex<LookupCache>,
+}
+
+impl FsContext {
+    pub(crate) fn new(options: RamFsOptions) -> Arc<Self> {
+        Arc::new(Self {
+            options,
+            read_only: false,
+            used_bytes: AtomicUsize::new(0),
+            // 根目录
+            used_inodes: AtomicUsize::new(1),
+            file_size: AtomicU64::new(0),
+            #[cfg(feature = "compress")]
+            packed_pages: AtomicUsize::new(0),
+            #[cfg(feature = "compress")]
+            packed_bytes: AtomicUsize::new(0),
+            devices: RwLock::new(BTreeMap::new()),
+            lookups: Mutex::new(LookupCache::new(options.lookup_cache)),
+        })
+    }
+
+    /// Returns a read-only context with the same options and devices, and no
+    /// quota.
+    pub(crate) fn frozen(&self) -> Arc<Self> {
+        Arc::new(Self {
+            options: RamFsOptions {
+                max_bytes: None,
+                max_inodes: None,
+                ..self.options
+            },
+            read_only: true,
+            used_bytes: AtomicUsize::new(0),
+            used_inodes: AtomicUsize::new(1),
+            file_size: AtomicU64::new(0),
+            #[cfg(feature = "compress")]
+            packed_pages: AtomicUsize::new(0),
+            #[cfg(feature = "compress")]
+            packed_bytes: AtomicUsize::new(0),
+            devices: RwLock::new(self.devices.read().clone()),
+            lookups: Mutex::new(LookupCache::new(self.options.lookup_cache)),
+        })
+    }
+
+    /// Returns [`VfsError::InvalidInput`] if `name` cannot be the name of a
+    /// node: empty, `.`, `..`, with a `/` or a NUL, or too long.
+    pub(crate) fn check_name(&self, name: &str) -> VfsResult {
+        let illegal = name.is_empty() || name == "." || name == "..";
+        if illegal || name.len() > self.options.name_max || name.contains(['/', '\0']) {
+            return Err(VfsError::InvalidInput);
+        }
+        Ok(())
+    }
+
+    /// Returns [`VfsError::InvalidInput`] if `path` is too long.
+    pub(crate) fn check_path(&self, path: &str) -> VfsResult {
+        if path.len() > self.options.path_max {
+            return Err(VfsError::InvalidInput);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the names are case-insensitive.
+    pub(crate) fn fold_case(&self) -> bool {
+        self.options.case_insensitive
+    }
+
+    /// Returns whether the directory entries are ordered by hash.
+    pub(crate) fn hashed_dirs(&self) -> bool {
+        self.options.hashed_dirs
+    }
+
+    /// Returns [`VfsError::PermissionDenied`] if the filesystem is read-only.
+    pub(crate) fn check_writable(&self) -> VfsResult {
+        if self.read_only {
+            return Err(VfsError::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    /// Returns the current time, or zero if the filesystem has no clock.
+    pub(crate) fn now(&self) -> Duration {
+        self.options.clock.map_or(Duration::ZERO, |clock| clock())
+    }
+
+    /// Accounts for `n` more bytes of file data, or returns
+    /// [`VfsError::StorageFull`] if it exceeds the quota.
+    pub(crate) fn alloc_bytes(&self, n: usize) -> VfsResult {
+        alloc_within(&self.used_bytes, n, self.options.max_bytes)
+    }
+
+    /// Accounts for a node and its `bytes` of file data copied from another
+    /// filesystem, without checking the quota.
+    pub(crate) fn charge(&self, bytes: usize) {
+        self.used_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.used_inodes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn free_bytes(&self, n: usize) {
+        self.used_bytes.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    /// Accounts for a file whose size changes from `old` to `new`.
+    pub(crate) fn resize_file(&self, old: usize, new: usize) {
+        if new > old {
+            self.file_size
+                .fetch_add((new - old) as u64, Ordering::Relaxed);
+        } else {
+            self.file_size
+                .fetch_sub((old - new) as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Accounts for a new node, or returns [`VfsError::StorageFull`] if it
+    /// exceeds the quota.
+    pub(crate) fn alloc_inode(&self) -> VfsResult {
+        alloc_within(&self.used_inodes, 1, self.options.max_inodes)
+    }
+
+    pub(crate) fn free_inode(&self) {
+        self.used_inodes.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Accounts for `pages` pages of file data compressed into `bytes`.
+    #[cfg(feature = "compress")]
+    pub(crate) fn add_packed(&self, pages: usize, bytes: usize) {
+        self.packed_pages.fetch_add(pages, Ordering::Relaxed);
+        self.packed_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "compress")]
+    pub(crate) fn sub_packed(&self, pages: usize, bytes: usize) {
+        self.packed_pages.fetch_sub(pages, Ordering::Relaxed);
+        self.packed_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the cache of the path lookups.
+    pub(crate) fn lookups(&self) -> MutexGuard<'_, LookupCache> {
+        self.lookups.lock()
+    }
+
+    pub(crate) fn stats(&self) -> RamFsStats {
+        let (lookup_hits, lookup_misses) = self.lookups().stats();
+        RamFsStats {
+            used_bytes: self.used_bytes.load(Ordering::Relaxed),
+            max_bytes: self.options.max_bytes,
+            file_size: self.file_size.load(Ordering::Relaxed),
+            used_inodes: self.used_inodes.load(Ordering::Relaxed),
+            max_inodes: self.options.max_inodes,
+            max_name_len: Some(self.options.name_max),
+            #[cfg(feature = "compress")]
+            packed_pages: self.packed_pages.load(Ordering::Relaxed),
+            #[cfg(feature = "compress")]
+            packed_bytes: self.packed_bytes.load(Ordering::Relaxed),
+            lookup_hits,
+            lookup_misses,
+        }
+    }
+
+    pub(crate) fn register_device(
+        &self,
+        ty: VfsNodeType,
+        id: DeviceId,
+        dev: VfsNodeRef,
+    ) -> VfsResult {
+        let mut devices = self.devices.write();
+        if devices.contains_key(&(ty as u8, id)) {
+            return Err(VfsError::AlreadyExists);
+        }
+        devices.insert((ty as u8, id), dev);
+        Ok(())
+    }
+
+    pub(crate) fn unregister_device(&self, ty: VfsNodeType, id: DeviceId) -> VfsResult {
+        let dev = self.devices.write().remove(&(ty as u8, id));
+        dev.map(|_| ()).ok_or(VfsError::NotFound)
+    }
+
+    /// Returns the device registered with the given type and ID.
+    pub(crate) fn device(&self, ty: VfsNodeType, id: DeviceId) -> VfsResult<VfsNodeRef> {
+        let devices = self.devices.read();
+        devices
+            .get(&(ty as u8, id))
+            .cloned()
+            .ok_or(VfsError::NotFound)
+    }
+}
+
+/// Adds `n` to `used` if it stays within `max`.
+fn alloc_within(used: &AtomicUsize, n: usize, max: Option<usize>) -> VfsResult {
+    let max = max.unwrap_or(usize::MAX);
+    used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+        used.checked_add(n).filter(|&new| new <= max)
+    })
+    .map(|_| ())
+    .map_err(|_| VfsError::StorageFull)
+}
+
+/// Timestamps of a node, as returned by the clock of the filesystem.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeTimes {
+    /// Last access of the content.
+    pub atime: Duration,
+    /// Last modification of the content.
+    pub mtime: Duration,
+    /// Last change of the content or the metadata.
+    pub ctime: Duration,
+}
+
+/// Metadata common to all nodes.
+pub(crate) struct NodeMeta {
+    ctx: Arc<FsContext>,
+    times: RwLock<NodeTimes>,
+    perm: RwLock<VfsNodePerm>,
+    /// User and group IDs of the owner.
+    owner: RwLock<(u32, u32)>,
+    watchers: WatchList,
+}
+
+impl NodeMeta {
+    pub(crate) fn new(ctx: Arc<FsContext>, perm: VfsNodePerm) -> Self {
+        let now = ctx.now();
+        Self {
+            ctx,
+            times: RwLock::new(NodeTimes {
+                atime: now,
+                mtime: now,
+                ctime: now,
+            }),
+            perm: RwLock::new(perm),
+            owner: RwLock::new((0, 0)),
+            watchers: WatchList::default(),
+        }
+    }
+
+    /// Returns a copy of the metadata for a node of the filesystem `ctx`.
+    pub(crate) fn clone_in(&self, ctx: Arc<FsContext>) -> Self {
+        Self {
+            ctx,
+            times: RwLock::new(self.times()),
+            perm: RwLock::new(self.perm()),
+            owner: RwLock::new(self.owner()),
+            watchers: WatchList::default(),
+        }
+    }
+
+    pub(crate) fn ctx(&self) -> &Arc<FsContext> {
+        &self.ctx
+    }
+
+    pub(crate) fn times(&self) -> NodeTimes {
+        *self.times.read()
+    }
+
+    /// Updates the access time.
+    pub(crate) fn accessed(&self) {
+        self.times.write().atime = self.ctx.now();
+    }
+
+    /// Updates the modification and change times.
+    pub(crate) fn modified(&self) {
+        let now = self.ctx.now();
+        let mut times = self.times.write();
+        times.mtime = now;
+        times.ctime = now;
+    }
+
+    /// Sets the modification time, e.g., the one read from an archive.
+    pub(crate) fn set_mtime(&self, mtime: Duration) {
+        self.times.write().mtime = mtime;
+    }
+
+    /// Updates the change time.
+    pub(crate) fn changed(&self) {
+        self.times.write().ctime = self.ctx.now();
+    }
+
+    pub(crate) fn perm(&self) -> VfsNodePerm {
+        *self.perm.read()
+    }
+
+    pub(crate) fn set_perm(&self, perm: VfsNodePerm) {
+        *self.perm.write() = perm;
+        self.changed();
+        self.notify(WatchEventKind::Attrib, None);
+    }
+
+    pub(crate) fn owner(&self) -> (u32, u32) {
+        *self.owner.read()
+    }
+
+    /// Changes the user and group IDs of the owner, those that are `None`
+    /// are kept.
+    pub(crate) fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) {
+        let mut owner = self.owner.write();
+        *owner = (uid.unwrap_or(owner.0), gid.unwrap_or(owner.1));
+        drop(owner);
+        self.changed();
+        self.notify(WatchEventKind::Attrib, None);
+    }
+
+    pub(crate) fn watch(&self, watcher: &Watcher) {
+        self.watchers.add(watcher);
+    }
+
+    /// Queues an event to the watchers of the node.
+    pub(crate) fn notify(&self, kind: WatchEventKind, name: Option<&str>) {
+        self.watchers.notify(kind, name);
+    }
+}
@@ -0,0 +1,55 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axfs_vfs::VfsResult;
+
+use crate::{DirNode, RamFileSystem};
+
+/// A copy-on-write snapshot of a [`RamFileSystem`], see
+/// [`RamFileSystem::snapshot`].
+///
+/// The files share their data with the filesystem, the chunks are only
+/// copied when one side writes them.
+pub struct Snapshot {
+    pub(crate) root: Arc<DirNode>,
+}
+
+impl Snapshot {
+    /// Returns a read-only filesystem with the content of the snapshot, e.g.,
+    /// to be mounted. Creating, removing, renaming or writing its nodes
+    /// returns [`PermissionDenied`](axfs_vfs::VfsError::PermissionDenied).
+    ///
+    /// All the filesystems returned share the nodes of the snapshot.
+    pub fn read_only_fs(&self) -> RamFileSystem {
+        RamFileSystem::with_root(self.root.clone())
+    }
+}
+
+impl RamFileSystem {
+    /// Takes a copy-on-write snapshot of the whole filesystem.
+    ///
+    /// It costs a copy of the metadata of the nodes, and is not subject to
+    /// the quota. The FIFOs of the snapshot are empty.
+    pub fn snapshot(&self) -> Snapshot {
+        let ctx = self.root.meta().ctx().frozen();
+        let root = self.root.clone_in(None, ctx, &mut BTreeMap::new());
+        Snapshot { root }
+    }
+
+    /// Restores the content of the filesystem to that of `snap`, copying it
+    /// on write like [`snapshot`](Self::snapshot).
+    ///
+    /// The nodes still in use keep their content, but are no longer reachable
+    /// from the root. The restored content may exceed the quota, new data is
+    /// then refused until enough is removed.
+    pub fn rollback(&self, snap: &Snapshot) -> VfsResult {
+        let meta = self.root.meta();
+        meta.ctx().check_writable()?;
+        snap.root
+            .clone_children_into(&self.root, &mut BTreeMap::new());
+        let (uid, gid) = snap.root.meta().owner();
+        meta.set_perm(snap.root.meta().perm());
+        meta.set_owner(Some(uid), Some(gid));
+        meta.modified();
+        Ok(())
+    }
+}
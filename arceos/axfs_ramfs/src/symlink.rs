@@ -0,0 +1,99 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm};
+use axfs_vfs::{VfsNodeType, VfsResult};
+use spin::RwLock;
+
+use crate::meta::{FsContext, NodeMeta, NodeTimes};
+
+/// The symbolic link node in the RAM filesystem.
+///
+/// Its data is the path of its target, read by [`read_at`](VfsNodeOps::read_at)
+/// like `readlink`. The lookups through it continue at the target, relative
+/// to the directory containing the link unless the target is absolute.
+///
+/// A link created by [`create`](VfsNodeOps::create) has no target and is not
+/// followed, its target is set by the first [`write_at`](VfsNodeOps::write_at)
+/// at offset 0 and can't be changed afterwards.
+pub struct SymlinkNode {
+    target: RwLock<String>,
+    meta: NodeMeta,
+}
+
+impl SymlinkNode {
+    pub(super) fn new(ctx: Arc<FsContext>, target: &str) -> Self {
+        Self {
+            target: RwLock::new(String::from(target)),
+            meta: NodeMeta::new(ctx, VfsNodePerm::from_bits_truncate(0o777)),
+        }
+    }
+
+    /// Returns a link with the same target and metadata for the filesystem
+    /// `ctx`.
+    pub(super) fn clone_in(&self, ctx: Arc<FsContext>) -> Self {
+        ctx.charge(0);
+        Self {
+            target: RwLock::new(self.target()),
+            meta: self.meta.clone_in(ctx),
+        }
+    }
+
+    pub(super) fn meta(&self) -> &NodeMeta {
+        &self.meta
+    }
+
+    /// Returns the target of the link, empty if it is not set yet.
+    pub fn target(&self) -> String {
+        self.target.read().clone()
+    }
+
+    /// Returns the timestamps of the link.
+    pub fn times(&self) -> NodeTimes {
+        self.meta.times()
+    }
+}
+
+impl Drop for SymlinkNode {
+    fn drop(&mut self) {
+        self.meta.ctx().free_inode();
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let size = self.target.read().len() as u64;
+        let perm = self.meta.perm();
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::SymLink, size, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let target = self.target.read();
+        let start = target.len().min(offset as usize);
+        let n = buf.len().min(target.len() - start);
+        buf[..n].copy_from_slice(&target.as_bytes()[start..start + n]);
+        drop(target);
+        self.meta.accessed();
+        Ok(n)
+    }
+
+    /// Sets the target of a link that has none, the whole target must be
+    /// written at once. Others return [`VfsError::InvalidInput`].
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let ctx = self.meta.ctx();
+        ctx.check_writable()?;
+        let path = core::str::from_utf8(buf).map_err(|_| VfsError::InvalidInput)?;
+        ctx.check_path(path)?;
+        let mut target = self.target.write();
+        if offset != 0 || path.is_empty() || !target.is_empty() {
+            return Err(VfsError::InvalidInput);
+        }
+        target.push_str(path);
+        drop(target);
+        // 之前的查找没有跟随这个链接
+        ctx.lookups().invalidate();
+        self.meta.modified();
+        Ok(buf.len())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
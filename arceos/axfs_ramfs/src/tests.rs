@@ -1,6 +1,10 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+use axfs_vfs::{
+    FileSystemInfo, VfsDirEntry, VfsError, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult,
+};
 
 use crate::*;
 
@@ -134,3 +138,1426 @@ fn test_ramfs() {
     assert_eq!(root.remove("./foo"), Ok(()));
     assert!(ramfs.root_dir_node().get_entries().is_empty());
 }
+
+#[test]
+fn test_hard_link() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File).unwrap();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+
+    let f1 = root.clone().lookup("f1").unwrap();
+    let foo_node = root.clone().lookup("foo").unwrap();
+    let foo = foo_node.as_any().downcast_ref::<DirNode>().unwrap();
+    foo.link("f2", f1.clone()).unwrap();
+    assert_eq!(foo.link("f2", f1.clone()), Err(VfsError::AlreadyExists));
+    assert_eq!(
+        foo.link("d", foo_node.clone()),
+        Err(VfsError::PermissionDenied)
+    );
+
+    let file = f1.as_any().downcast_ref::<FileNode>().unwrap();
+    assert_eq!(file.nlink(), 2);
+    assert_eq!(f1.get_attr().unwrap().nlink(), 2);
+    f1.write_at(0, b"hello").unwrap();
+    let mut buf = [0; 5];
+    let f2 = root.clone().lookup("foo/f2").unwrap();
+    assert!(Arc::ptr_eq(&f1, &f2));
+    assert_eq!(f2.read_at(0, &mut buf), Ok(5));
+    assert_eq!(&buf, b"hello");
+
+    root.remove("f1").unwrap();
+    assert_eq!(file.nlink(), 1);
+    let f2 = root.clone().lookup("foo/f2").unwrap();
+    assert_eq!(f2.get_attr().unwrap().size(), 5);
+    assert_eq!(f2.get_attr().unwrap().nlink(), 1);
+    root.remove("foo/f2").unwrap();
+    assert_eq!(file.nlink(), 0);
+}
+
+#[test]
+fn test_symlink() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    let root_dir = ramfs.root_dir_node();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.create("foo/f1", VfsNodeType::File).unwrap();
+    root.clone()
+        .lookup("foo/f1")
+        .unwrap()
+        .write_at(0, b"hello")
+        .unwrap();
+
+    // 相对路径从链接所在的目录开始, 绝对路径从根目录开始
+    let foo_node = root.clone().lookup("foo").unwrap();
+    let foo = foo_node.as_any().downcast_ref::<DirNode>().unwrap();
+    foo.symlink("rel", "f1").unwrap();
+    root_dir.symlink("abs", "/foo/f1").unwrap();
+    root_dir.symlink("dir", "foo").unwrap();
+    assert_eq!(root_dir.symlink("abs", "f1"), Err(VfsError::AlreadyExists));
+    assert_eq!(root_dir.symlink("empty", ""), Err(VfsError::InvalidInput));
+    let f1 = root.clone().lookup("foo/f1").unwrap();
+    for path in ["foo/rel", "abs", "dir/f1", "dir/rel", "dir/../abs"] {
+        let node = root.clone().lookup(path).unwrap();
+        assert!(Arc::ptr_eq(&node, &f1), "{}", path);
+    }
+    root.create("dir/f2", VfsNodeType::File).unwrap();
+    assert!(foo.exist("f2"));
+
+    let link = foo.child("rel").unwrap();
+    let attr = link.get_attr().unwrap();
+    assert_eq!(attr.file_type(), VfsNodeType::SymLink);
+    assert_eq!(attr.size(), 2);
+    let mut buf = [0; 8];
+    assert_eq!(link.read_at(0, &mut buf), Ok(2));
+    assert_eq!(&buf[..2], b"f1");
+    assert_eq!(read_link(&root, "foo/rel").as_deref(), Ok("f1"));
+    assert_eq!(read_link(&root, "dir/rel").as_deref(), Ok("f1"));
+    assert_eq!(read_link(&root, "foo/f1"), Err(VfsError::InvalidInput));
+    assert_eq!(root_dir.read_link("missing"), Err(VfsError::NotFound));
+
+    // 通过 VFS 创建的链接在第一次写入时设置目标
+    root.create("vfs", VfsNodeType::SymLink).unwrap();
+    let vfs = root.clone().lookup("vfs").unwrap();
+    assert_eq!(vfs.get_attr().unwrap().file_type(), VfsNodeType::SymLink);
+    assert_eq!(vfs.write_at(0, b"dir/f1"), Ok(6));
+    assert_eq!(vfs.write_at(0, b"abs"), Err(VfsError::InvalidInput));
+    assert!(Arc::ptr_eq(&root.clone().lookup("vfs").unwrap(), &f1));
+
+    root_dir.symlink("dangling", "nowhere").unwrap();
+    root_dir.symlink("loop", "loop").unwrap();
+    let lookup = |path| root.clone().lookup(path).err();
+    assert_eq!(lookup("dangling"), Some(VfsError::NotFound));
+    assert_eq!(lookup("loop"), Some(VfsError::InvalidInput));
+    assert_eq!(lookup("abs/x"), Some(VfsError::NotADirectory));
+
+    // 删除链接不影响目标, 链接随快照和归档一起复制
+    let snap = ramfs.snapshot().read_only_fs();
+    let mut cpio = Vec::new();
+    ramfs
+        .export_cpio(|data| {
+            cpio.extend_from_slice(data);
+            Ok(())
+        })
+        .unwrap();
+    root.remove("abs").unwrap();
+    assert!(root.clone().lookup("foo/f1").is_ok());
+    assert_eq!(lookup("abs"), Some(VfsError::NotFound));
+    for fs in [snap, RamFileSystem::from_cpio(&cpio).unwrap()] {
+        let mut buf = [0; 5];
+        let node = fs.root_dir().lookup("abs").unwrap();
+        assert_eq!(node.read_at(0, &mut buf), Ok(5));
+        assert_eq!(&buf, b"hello");
+        assert_eq!(read_link(&fs.root_dir(), "dir").as_deref(), Ok("foo"));
+    }
+}
+
+#[test]
+fn test_times() {
+    /// A clock ticking one second on every call.
+    fn clock() -> Duration {
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        Duration::from_secs(NOW.fetch_add(1, Ordering::Relaxed))
+    }
+
+    let ramfs = RamFileSystem::with_clock(clock);
+    let root = ramfs.root_dir_node();
+    let created = root.times();
+    root.create("f1", VfsNodeType::File).unwrap();
+    let dir_times = root.times();
+    assert!(dir_times.mtime > created.mtime);
+    assert_eq!(dir_times.atime, created.atime);
+
+    let node = root.clone().lookup("f1").unwrap();
+    assert!(root.times().atime > dir_times.atime);
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+    let t0 = file.times();
+    assert_eq!(t0.atime, t0.mtime);
+
+    node.write_at(0, b"hello").unwrap();
+    let t1 = file.times();
+    assert!(t1.mtime > t0.mtime);
+    assert_eq!(t1.ctime, t1.mtime);
+    assert_eq!(t1.atime, t0.atime);
+
+    node.read_at(0, &mut [0; 5]).unwrap();
+    let t2 = file.times();
+    assert!(t2.atime > t1.mtime);
+    assert_eq!(t2.mtime, t1.mtime);
+
+    root.link("f2", node.clone()).unwrap();
+    let t3 = file.times();
+    assert!(t3.ctime > t2.atime);
+    assert_eq!(t3.mtime, t1.mtime);
+}
+
+#[test]
+fn test_perm_and_owner() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File).unwrap();
+    let node = root.clone().lookup("f1").unwrap();
+    assert_eq!(node.get_attr().unwrap().perm(), VfsNodePerm::default_file());
+    assert_eq!(root.get_attr().unwrap().perm(), VfsNodePerm::default_dir());
+    assert_eq!(owner(&node), Ok((0, 0)));
+
+    let perm = VfsNodePerm::OWNER_READ | VfsNodePerm::OWNER_WRITE;
+    chmod(&node, perm).unwrap();
+    let attr = node.get_attr().unwrap();
+    assert_eq!(attr.perm(), perm);
+    assert_eq!(attr.file_type(), VfsNodeType::File);
+
+    chown(&node, Some(1000), None).unwrap();
+    assert_eq!(owner(&node), Ok((1000, 0)));
+    chown(&root, None, Some(100)).unwrap();
+    assert_eq!(owner(&root), Ok((0, 100)));
+    assert_eq!(owner(&node), Ok((1000, 0)));
+}
+
+#[test]
+fn test_rename() {
+    // .
+    // ├── foo
+    // │   ├── bar
+    // │   │   └── f4
+    // │   └── f3
+    // └── f1
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File).unwrap();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.create("foo/f3", VfsNodeType::File).unwrap();
+    root.create("foo/bar", VfsNodeType::Dir).unwrap();
+    root.create("foo/bar/f4", VfsNodeType::File).unwrap();
+    let f1 = root.clone().lookup("f1").unwrap();
+
+    // In the same directory.
+    root.rename("f1", "f2").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("f2").unwrap(), &f1));
+    assert_eq!(root.clone().lookup("f1").err(), Some(VfsError::NotFound));
+
+    // Across nested directories, with absolute paths.
+    root.rename("/f2", "/foo/bar/f5").unwrap();
+    assert!(Arc::ptr_eq(
+        &root.clone().lookup("foo/bar/f5").unwrap(),
+        &f1
+    ));
+    assert_eq!(root.clone().lookup("f2").err(), Some(VfsError::NotFound));
+
+    // Into `..`, relative to a subdirectory.
+    let foo = root.clone().lookup("foo").unwrap();
+    foo.rename("bar/f5", "../f1").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("f1").unwrap(), &f1));
+
+    // A directory moves with its parent.
+    let bar = root.clone().lookup("foo/bar").unwrap();
+    root.rename("foo/bar/", "baz").unwrap();
+    assert!(Arc::ptr_eq(&bar.parent().unwrap(), &root));
+    assert!(root.clone().lookup("baz/f4").is_ok());
+    assert!(Arc::ptr_eq(&root.clone().lookup("baz/..").unwrap(), &root));
+
+    assert_eq!(root.rename("f9", "f8"), Err(VfsError::NotFound));
+    assert_eq!(root.rename("f1", "nodir/f1"), Err(VfsError::NotFound));
+    assert_eq!(root.rename("f1", "foo/f3/f1"), Err(VfsError::NotADirectory));
+    assert_eq!(root.rename("foo", "foo/sub"), Err(VfsError::InvalidInput));
+    assert_eq!(root.rename("foo", "."), Err(VfsError::InvalidInput));
+    assert_eq!(root.rename("..", "f9"), Err(VfsError::InvalidInput));
+    let mut entries = ramfs.root_dir_node().get_entries();
+    entries.sort();
+    assert_eq!(entries, ["baz", "f1", "foo"]);
+}
+
+#[test]
+fn test_rename_replace() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    for path in ["d1", "d1/sub", "d2", "d3", "d3/sub"] {
+        root.create(path, VfsNodeType::Dir).unwrap();
+    }
+    for path in ["f1", "f2", "d1/sub/f3"] {
+        root.create(path, VfsNodeType::File).unwrap();
+    }
+    let f1 = root.clone().lookup("f1").unwrap();
+    let f2 = root.clone().lookup("f2").unwrap();
+    f1.open().unwrap();
+
+    // A file replaces a file, which loses its name.
+    root.rename("f2", "f1").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("f1").unwrap(), &f2));
+    let file = f1.as_any().downcast_ref::<FileNode>().unwrap();
+    assert_eq!(file.nlink(), 0);
+    f1.release().unwrap();
+
+    // Hard links of the same file are left alone.
+    let root_dir = ramfs.root_dir_node();
+    root_dir.link("f3", f2.clone()).unwrap();
+    root.rename("f3", "f1").unwrap();
+    assert_eq!(root_dir.get_entries(), ["d1", "d2", "d3", "f1", "f3"]);
+
+    assert_eq!(root.rename("f1", "d2"), Err(VfsError::IsADirectory));
+    assert_eq!(root.rename("d2", "f1"), Err(VfsError::NotADirectory));
+    assert_eq!(root.rename("d2", "d1"), Err(VfsError::DirectoryNotEmpty));
+    // The destination is the parent of the source.
+    assert_eq!(
+        root.rename("d1/sub", "d1"),
+        Err(VfsError::DirectoryNotEmpty)
+    );
+    assert_eq!(
+        root.rename("d1/sub/f3", "d1/sub"),
+        Err(VfsError::IsADirectory)
+    );
+
+    // A directory replaces an empty directory.
+    let d1 = root.clone().lookup("d1").unwrap();
+    root.rename("d1", "d2").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("d2").unwrap(), &d1));
+    assert_eq!(
+        root.rename("d3/sub", "d2/sub/f3"),
+        Err(VfsError::NotADirectory)
+    );
+    root.remove("d2/sub/f3").unwrap();
+    root.rename("d3/sub", "d2/sub").unwrap();
+    assert_eq!(root_dir.get_entries(), ["d2", "d3", "f1", "f3"]);
+}
+
+#[test]
+fn test_read_dir() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    for i in 0..10 {
+        root.create(&format!("f{}", i), VfsNodeType::File).unwrap();
+    }
+    root.create("sub", VfsNodeType::Dir).unwrap();
+
+    // Listed 4 entries at a time.
+    let mut entries = Vec::new();
+    let mut dirents: [VfsDirEntry; 4] = core::array::from_fn(|_| VfsDirEntry::default());
+    loop {
+        let n = root.read_dir(entries.len(), &mut dirents).unwrap();
+        if n == 0 {
+            break;
+        }
+        for ent in &dirents[..n] {
+            let name = String::from_utf8(ent.name_as_bytes().to_vec()).unwrap();
+            entries.push((name, ent.entry_type()));
+        }
+    }
+    assert_eq!(entries.len(), 13);
+    assert_eq!(entries[0], (".".into(), VfsNodeType::Dir));
+    assert_eq!(entries[1], ("..".into(), VfsNodeType::Dir));
+    assert_eq!(entries[2], ("f0".into(), VfsNodeType::File));
+    assert_eq!(entries[12], ("sub".into(), VfsNodeType::Dir));
+
+    assert_eq!(root.read_dir(13, &mut dirents), Ok(0));
+    assert_eq!(root.read_dir(100, &mut dirents), Ok(0));
+    let f0 = root.lookup("f0").unwrap();
+    assert_eq!(f0.read_dir(0, &mut dirents), Err(VfsError::NotADirectory));
+}
+
+#[test]
+fn test_truncate() {
+    const GB: u64 = 1 << 30;
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File).unwrap();
+    let node = root.lookup("f1").unwrap();
+    node.write_at(0, b"hello world").unwrap();
+
+    node.truncate(5).unwrap();
+    let mut buf = [0xff; 16];
+    assert_eq!(node.read_at(0, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+
+    // Extended with a hole, without allocating it.
+    node.truncate(GB).unwrap();
+    let attr = node.get_attr().unwrap();
+    assert_eq!(attr.size(), GB);
+    // One page is allocated.
+    assert_eq!(attr.blocks(), 8);
+    assert_eq!(node.read_at(3, &mut buf), Ok(16));
+    assert_eq!(&buf[..2], b"lo");
+    assert_eq!(buf[2..], [0; 14]);
+    buf.fill(0xff);
+    assert_eq!(node.read_at(GB - 4, &mut buf), Ok(4));
+    assert_eq!(buf[..4], [0; 4]);
+    assert_eq!(node.read_at(GB, &mut buf), Ok(0));
+    assert_eq!(node.read_at(u64::MAX, &mut buf), Ok(0));
+
+    // Writing past the end of the file.
+    node.truncate(2).unwrap();
+    node.write_at(8, b"!").unwrap();
+    assert_eq!(node.get_attr().unwrap().size(), 9);
+    assert_eq!(node.read_at(0, &mut buf), Ok(9));
+    assert_eq!(&buf[..9], b"he\0\0\0\0\0\0!");
+    assert_eq!(node.write_at(u64::MAX, b"!"), Err(VfsError::InvalidInput));
+
+    // Data spanning several pages.
+    let data: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+    node.write_at(4000, &data).unwrap();
+    let mut buf = [0xff; 10010];
+    assert_eq!(node.read_at(3995, &mut buf), Ok(10005));
+    assert_eq!(buf[..5], [0; 5]);
+    assert_eq!(buf[5..10005], data[..]);
+    node.truncate(4100).unwrap();
+    node.truncate(8000).unwrap();
+    assert_eq!(node.get_attr().unwrap().blocks(), 16);
+    assert_eq!(node.read_at(4000, &mut buf), Ok(4000));
+    assert_eq!(buf[..100], data[..100]);
+    assert!(buf[100..4000].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_quota() {
+    let ramfs = RamFileSystem::with_options(RamFsOptions {
+        max_bytes: Some(8192),
+        max_inodes: Some(4),
+        ..Default::default()
+    });
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File).unwrap();
+    root.create("f2", VfsNodeType::File).unwrap();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    assert_eq!(
+        root.create("f3", VfsNodeType::File),
+        Err(VfsError::StorageFull)
+    );
+    assert_eq!(
+        root.create("foo/bar", VfsNodeType::Dir),
+        Err(VfsError::StorageFull)
+    );
+    root.remove("foo").unwrap();
+    root.create("f3", VfsNodeType::File).unwrap();
+
+    // Space is counted in whole pages.
+    let f1 = root.clone().lookup("f1").unwrap();
+    let f2 = root.clone().lookup("f2").unwrap();
+    assert_eq!(f1.write_at(0, &[1; 10]), Ok(10));
+    assert_eq!(f2.write_at(4095, &[2; 2]), Err(VfsError::StorageFull));
+    assert_eq!(f2.get_attr().unwrap().size(), 0);
+    // Holes are not counted.
+    f2.truncate(1 << 20).unwrap();
+    assert_eq!(f2.write_at(1 << 19, &[2; 6]), Ok(6));
+    assert_eq!(f2.write_at(0, &[2; 1]), Err(VfsError::StorageFull));
+
+    // The space is given back when a file shrinks or is freed.
+    f1.truncate(4).unwrap();
+    assert_eq!(f2.write_at(0, &[2; 1]), Err(VfsError::StorageFull));
+    f1.truncate(0).unwrap();
+    assert_eq!(f2.write_at(0, &[2; 1]), Ok(1));
+    f2.truncate(0).unwrap();
+    f1.write_at(0, &[1; 8192]).unwrap();
+    f1.open().unwrap();
+    root.remove("f1").unwrap();
+    assert_eq!(f2.write_at(0, &[2; 1]), Err(VfsError::StorageFull));
+    f1.release().unwrap();
+    assert_eq!(f2.write_at(0, &[2; 1]), Ok(1));
+    // The node is counted until it is dropped.
+    assert_eq!(
+        root.create("f1", VfsNodeType::File),
+        Err(VfsError::StorageFull)
+    );
+    drop(f1);
+    root.create("f1", VfsNodeType::File).unwrap();
+}
+
+/// A device like `/dev/zero`, counting the opens.
+struct ZeroDev(AtomicU64);
+
+impl VfsNodeOps for ZeroDev {
+    fn open(&self) -> VfsResult {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        Ok(buf.len())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+#[test]
+fn test_device() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("dev", VfsNodeType::Dir).unwrap();
+    let dev = ramfs.root_dir_node().lookup("dev").unwrap();
+    let dev = dev.as_any().downcast_ref::<DirNode>().unwrap();
+    let id = DeviceId::new(1, 5);
+    dev.create_device("zero", VfsNodeType::CharDevice, id)
+        .unwrap();
+    assert_eq!(
+        dev.create_device("foo", VfsNodeType::File, id),
+        Err(VfsError::InvalidInput)
+    );
+
+    // Not registered yet.
+    let node = root.clone().lookup("dev/zero").unwrap();
+    assert_eq!(
+        node.get_attr().unwrap().file_type(),
+        VfsNodeType::CharDevice
+    );
+    assert_eq!(node.open(), Err(VfsError::NotFound));
+
+    let zero = Arc::new(ZeroDev(AtomicU64::new(0)));
+    ramfs
+        .register_device(VfsNodeType::CharDevice, id, zero.clone())
+        .unwrap();
+    assert_eq!(
+        ramfs.register_device(VfsNodeType::CharDevice, id, zero.clone()),
+        Err(VfsError::AlreadyExists)
+    );
+    // Block devices have their own IDs.
+    root.create("dev/blk", VfsNodeType::BlockDevice).unwrap();
+    let blk = root.clone().lookup("dev/blk").unwrap();
+    assert_eq!(blk.open(), Err(VfsError::NotFound));
+
+    node.open().unwrap();
+    assert_eq!(zero.0.load(Ordering::Relaxed), 1);
+    let mut buf = [0xff; 8];
+    assert_eq!(node.read_at(0, &mut buf), Ok(8));
+    assert_eq!(buf, [0; 8]);
+    assert_eq!(node.write_at(0, b"hello"), Ok(5));
+    assert_eq!(node.get_attr().unwrap().size(), 0);
+
+    ramfs
+        .unregister_device(VfsNodeType::CharDevice, id)
+        .unwrap();
+    assert_eq!(node.read_at(0, &mut buf), Err(VfsError::NotFound));
+    root.remove("dev/zero").unwrap();
+}
+
+#[test]
+fn test_fifo() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("fifo", VfsNodeType::Fifo).unwrap();
+    let fifo = root.clone().lookup("fifo").unwrap();
+    assert_eq!(fifo.get_attr().unwrap().file_type(), VfsNodeType::Fifo);
+
+    let mut buf = [0; 8];
+    assert_eq!(fifo.read_at(0, &mut buf), Err(VfsError::WouldBlock));
+    assert_eq!(fifo.write_at(100, b"hello"), Ok(5));
+    assert_eq!(fifo.write_at(0, b" world"), Ok(6));
+    assert_eq!(fifo.get_attr().unwrap().size(), 11);
+    assert_eq!(fifo.read_at(0, &mut buf), Ok(8));
+    assert_eq!(&buf, b"hello wo");
+    assert_eq!(fifo.read_at(0, &mut buf), Ok(3));
+    assert_eq!(&buf[..3], b"rld");
+    assert_eq!(fifo.read_at(0, &mut buf), Err(VfsError::WouldBlock));
+
+    // Writes stop when the buffer is full.
+    let data = [7; 3000];
+    assert_eq!(fifo.write_at(0, &data), Ok(3000));
+    assert_eq!(fifo.write_at(0, &data), Ok(1096));
+    assert_eq!(fifo.write_at(0, &data), Err(VfsError::WouldBlock));
+    assert_eq!(fifo.read_at(0, &mut buf), Ok(8));
+    assert_eq!(fifo.write_at(0, &data), Ok(8));
+    root.remove("fifo").unwrap();
+}
+
+#[test]
+fn test_snapshot() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.create("foo/bar", VfsNodeType::File).unwrap();
+    let bar = root.clone().lookup("foo/bar").unwrap();
+    bar.write_at(0, b"hello").unwrap();
+    let dir = ramfs.root_dir_node();
+    dir.link("baz", bar.clone()).unwrap();
+
+    let snap = ramfs.snapshot();
+    bar.write_at(0, b"HE").unwrap();
+    root.create("new", VfsNodeType::File).unwrap();
+
+    // The snapshot keeps the old content and the hard links.
+    let fs = snap.read_only_fs();
+    let snap_root = fs.root_dir();
+    let snap_bar = snap_root.clone().lookup("foo/bar").unwrap();
+    let snap_baz = snap_root.clone().lookup("baz").unwrap();
+    assert!(Arc::ptr_eq(&snap_bar, &snap_baz));
+    let mut buf = [0; 8];
+    assert_eq!(snap_bar.read_at(0, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+    assert_eq!(
+        snap_root.clone().lookup("new").err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(snap_bar.write_at(0, b"x"), Err(VfsError::PermissionDenied));
+    assert_eq!(
+        snap_root.create("x", VfsNodeType::File),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(snap_root.remove("baz"), Err(VfsError::PermissionDenied));
+
+    ramfs.rollback(&snap).unwrap();
+    assert_eq!(root.clone().lookup("new").err(), Some(VfsError::NotFound));
+    let bar = root.clone().lookup("foo/bar").unwrap();
+    assert_eq!(bar.read_at(0, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+    let foo = root.clone().lookup("foo").unwrap();
+    assert!(Arc::ptr_eq(&foo.parent().unwrap(), &root));
+
+    // The restored files are copied on write again.
+    bar.write_at(0, b"J").unwrap();
+    let baz = root.clone().lookup("baz").unwrap();
+    assert_eq!(baz.read_at(0, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"Jello");
+    assert_eq!(snap_bar.read_at(0, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+}
+
+#[test]
+fn test_export_cpio() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("a", VfsNodeType::File).unwrap();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    let a = root.clone().lookup("a").unwrap();
+    a.write_at(0, b"hi").unwrap();
+    let d = ramfs.root_dir_node().lookup("d").unwrap();
+    let d = d.as_any().downcast_ref::<DirNode>().unwrap();
+    d.link("b", a).unwrap();
+
+    let mut out = Vec::new();
+    ramfs
+        .export_cpio(|buf| {
+            out.extend_from_slice(buf);
+            Ok(())
+        })
+        .unwrap();
+    // ino, mode, uid, gid, nlink, mtime, filesize, dev and rdev, namesize
+    let header = |fields: [u32; 12]| {
+        let fields: Vec<String> = fields.iter().map(|f| format!("{:08X}", f)).collect();
+        format!("070701{}00000000", fields.concat())
+    };
+    let expected = [
+        header([1, 0o100666, 0, 0, 2, 0, 2, 0, 0, 0, 0, 2]) + "a\0" + "hi\0\0",
+        header([2, 0o40755, 0, 0, 2, 0, 0, 0, 0, 0, 0, 2]) + "d\0",
+        // The data of hard links is only written once.
+        header([1, 0o100666, 0, 0, 2, 0, 0, 0, 0, 0, 0, 4]) + "d/b\0\0\0",
+        header([0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 11]) + "TRAILER!!!\0\0\0\0",
+    ];
+    assert_eq!(String::from_utf8(out).unwrap(), expected.concat());
+}
+
+#[test]
+fn test_from_cpio() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("etc", VfsNodeType::Dir).unwrap();
+    root.create("etc/passwd", VfsNodeType::File).unwrap();
+    root.create("dev", VfsNodeType::Dir).unwrap();
+    root.create("dev/fifo", VfsNodeType::Fifo).unwrap();
+    let passwd = root.clone().lookup("etc/passwd").unwrap();
+    passwd.write_at(0, b"root:x:0:0").unwrap();
+    chmod(&passwd, VfsNodePerm::from_bits_truncate(0o600)).unwrap();
+    chown(&passwd, Some(1), Some(2)).unwrap();
+    let dev = ramfs.root_dir_node().lookup("dev").unwrap();
+    let dev = dev.as_any().downcast_ref::<DirNode>().unwrap();
+    let id = DeviceId::new(1, 3);
+    dev.create_device("null", VfsNodeType::CharDevice, id)
+        .unwrap();
+    ramfs.root_dir_node().link("passwd", passwd).unwrap();
+
+    let mut image = Vec::new();
+    ramfs
+        .export_cpio(|buf| {
+            image.extend_from_slice(buf);
+            Ok(())
+        })
+        .unwrap();
+    let ramfs = RamFileSystem::from_cpio(&image).unwrap();
+    let root = ramfs.root_dir();
+    let passwd = root.clone().lookup("etc/passwd").unwrap();
+    assert!(Arc::ptr_eq(
+        &passwd,
+        &root.clone().lookup("passwd").unwrap()
+    ));
+    let mut buf = [0; 16];
+    assert_eq!(passwd.read_at(0, &mut buf), Ok(10));
+    assert_eq!(&buf[..10], b"root:x:0:0");
+    assert_eq!(passwd.get_attr().unwrap().perm().bits(), 0o600);
+    assert_eq!(owner(&passwd), Ok((1, 2)));
+    let null = root.clone().lookup("dev/null").unwrap();
+    let null = null.as_any().downcast_ref::<DeviceNode>().unwrap();
+    assert_eq!(null.id(), id);
+    let fifo = root.clone().lookup("dev/fifo").unwrap();
+    assert_eq!(fifo.get_attr().unwrap().file_type(), VfsNodeType::Fifo);
+
+    assert_eq!(
+        RamFileSystem::from_cpio(&image[..image.len() - 8]).err(),
+        Some(VfsError::InvalidData)
+    );
+}
+
+/// Returns a ustar header.
+fn tar_header(name: &str, typeflag: u8, size: usize, linkname: &str) -> [u8; 512] {
+    let mut hdr = [0; 512];
+    let mut put =
+        |offset: usize, s: &str| hdr[offset..offset + s.len()].copy_from_slice(s.as_bytes());
+    put(0, name);
+    put(100, "0000644");
+    put(108, "0000000");
+    put(116, "0000000");
+    put(124, &format!("{:011o}", size));
+    put(136, "00000000001");
+    put(157, linkname);
+    put(257, "ustar");
+    put(263, "00");
+    hdr[156] = typeflag;
+    hdr[148..156].fill(b' ');
+    let sum: u32 = hdr.iter().map(|&b| b as u32).sum();
+    hdr[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+    hdr
+}
+
+#[test]
+fn test_from_tar() {
+    let mut image = Vec::new();
+    image.extend_from_slice(&tar_header("./bin/", b'5', 0, ""));
+    image.extend_from_slice(&tar_header("./bin/hello", b'0', 5, ""));
+    image.extend_from_slice(b"hello");
+    image.resize(3 * 512, 0);
+    // The parent directory is created.
+    image.extend_from_slice(&tar_header("usr/bin/hi", b'1', 0, "./bin/hello"));
+    image.resize(6 * 512, 0);
+
+    let ramfs = RamFileSystem::from_tar(&image).unwrap();
+    let root = ramfs.root_dir();
+    let hello = root.clone().lookup("bin/hello").unwrap();
+    assert!(Arc::ptr_eq(
+        &hello,
+        &root.clone().lookup("usr/bin/hi").unwrap()
+    ));
+    let mut buf = [0; 8];
+    assert_eq!(hello.read_at(0, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+    let bin = root.clone().lookup("bin").unwrap();
+    let bin = bin.as_any().downcast_ref::<DirNode>().unwrap();
+    assert_eq!(bin.times().mtime, Duration::from_secs(1));
+    assert_eq!(bin.get_attr().unwrap().perm().bits(), 0o644);
+
+    image[100] = b'1'; // bad checksum
+    assert_eq!(
+        RamFileSystem::from_tar(&image).err(),
+        Some(VfsError::InvalidData)
+    );
+}
+
+#[test]
+fn test_flock() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("log", VfsNodeType::File).unwrap();
+    let log = root.clone().lookup("log").unwrap();
+
+    flock(&log, 1, FlockOp::Shared).unwrap();
+    flock(&log, 2, FlockOp::Shared).unwrap();
+    assert_eq!(
+        flock(&log, 1, FlockOp::Exclusive),
+        Err(VfsError::WouldBlock)
+    );
+    flock(&log, 2, FlockOp::Unlock).unwrap();
+    // The shared lock is converted.
+    flock(&log, 1, FlockOp::Exclusive).unwrap();
+    assert_eq!(flock(&log, 2, FlockOp::Shared), Err(VfsError::WouldBlock));
+    assert_eq!(
+        flock(&log, 2, FlockOp::Exclusive),
+        Err(VfsError::WouldBlock)
+    );
+    flock(&log, 1, FlockOp::Shared).unwrap();
+    flock(&log, 2, FlockOp::Shared).unwrap();
+    flock(&log, 1, FlockOp::Unlock).unwrap();
+    flock(&log, 2, FlockOp::Unlock).unwrap();
+    flock(&log, 2, FlockOp::Exclusive).unwrap();
+
+    assert_eq!(flock(&root, 1, FlockOp::Shared), Err(VfsError::Unsupported));
+}
+
+#[test]
+fn test_unlink_while_open() {
+    let ramfs = RamFileSystem::with_options(RamFsOptions {
+        max_bytes: Some(4096),
+        ..Default::default()
+    });
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File).unwrap();
+    let f1 = root.clone().lookup("f1").unwrap();
+    f1.open().unwrap();
+    f1.write_at(0, b"hello").unwrap();
+    root.remove("f1").unwrap();
+
+    // The data is kept while the file is open.
+    root.create("f1", VfsNodeType::File).unwrap();
+    let new = root.clone().lookup("f1").unwrap();
+    assert!(!Arc::ptr_eq(&f1, &new));
+    assert_eq!(new.write_at(0, b"x"), Err(VfsError::StorageFull));
+    let mut buf = [0; 8];
+    assert_eq!(f1.read_at(0, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+
+    // And freed by the last release.
+    f1.open().unwrap();
+    f1.release().unwrap();
+    assert_eq!(new.write_at(0, b"x"), Err(VfsError::StorageFull));
+    f1.release().unwrap();
+    assert_eq!(new.write_at(0, b"x"), Ok(1));
+    assert_eq!(f1.get_attr().unwrap().size(), 0);
+
+    // A file that is not open is freed when removed.
+    new.write_at(0, b"x").unwrap();
+    root.remove("f1").unwrap();
+    root.create("f2", VfsNodeType::File).unwrap();
+    let f2 = root.clone().lookup("f2").unwrap();
+    assert_eq!(f2.write_at(0, b"x"), Ok(1));
+    drop(new);
+}
+
+#[test]
+fn test_stats() {
+    let ramfs = RamFileSystem::with_options(RamFsOptions {
+        max_bytes: Some(1 << 20),
+        ..Default::default()
+    });
+    let root = ramfs.root_dir();
+    assert_eq!(
+        ramfs.stats(),
+        RamFsStats {
+            used_bytes: 0,
+            max_bytes: Some(1 << 20),
+            file_size: 0,
+            used_inodes: 1,
+            max_inodes: None,
+            max_name_len: Some(NAME_MAX),
+            #[cfg(feature = "compress")]
+            packed_pages: 0,
+            #[cfg(feature = "compress")]
+            packed_bytes: 0,
+            lookup_hits: 0,
+            lookup_misses: 0,
+        }
+    );
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.create("foo/f1", VfsNodeType::File).unwrap();
+    root.create("f2", VfsNodeType::File).unwrap();
+    let f1 = root.clone().lookup("foo/f1").unwrap();
+    let f2 = root.clone().lookup("f2").unwrap();
+    f1.write_at(0, &[1; 5000]).unwrap();
+    f2.truncate(1 << 30).unwrap();
+    let stats = ramfs.stats();
+    assert_eq!(stats.used_bytes, 8192);
+    assert_eq!(stats.file_size, 5000 + (1 << 30));
+    assert_eq!(stats.used_inodes, 4);
+
+    f2.truncate(10).unwrap();
+    root.remove("foo/f1").unwrap();
+    drop(f1);
+    let stats = ramfs.stats();
+    assert_eq!((stats.used_bytes, stats.file_size), (0, 10));
+    assert_eq!(stats.used_inodes, 3);
+}
+
+#[test]
+fn test_statfs() {
+    let ramfs = RamFileSystem::with_options(RamFsOptions {
+        max_bytes: Some(64 * PAGE_SIZE),
+        max_inodes: Some(10),
+        ..Default::default()
+    });
+    let root = ramfs.root_dir();
+    root.create("f", VfsNodeType::File).unwrap();
+    let f = root.clone().lookup("f").unwrap();
+    f.write_at(0, &[1; PAGE_SIZE + 1]).unwrap();
+    assert_eq!(
+        ramfs.statfs().unwrap(),
+        FileSystemInfo::new(PAGE_SIZE as u64, 64, 62, 10, 8, NAME_MAX as u64)
+    );
+
+    // No limits, the totals are zero.
+    let info = RamFileSystem::new().statfs().unwrap();
+    assert_eq!((info.blocks(), info.blocks_free()), (0, 0));
+    assert_eq!((info.files(), info.files_free()), (0, 0));
+    assert_eq!(info.block_size(), PAGE_SIZE as u64);
+}
+
+#[test]
+fn test_watch() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("bin", VfsNodeType::Dir).unwrap();
+    root.create("bin/app", VfsNodeType::File).unwrap();
+    let bin = root.clone().lookup("bin").unwrap();
+    let app = root.clone().lookup("bin/app").unwrap();
+
+    let watcher = Watcher::new(4);
+    let event = |kind, name: Option<&str>| {
+        Some(WatchEvent {
+            kind,
+            name: name.map(String::from),
+        })
+    };
+    watch(&bin, &watcher).unwrap();
+    watch(&app, &watcher).unwrap();
+    bin.create("new", VfsNodeType::File).unwrap();
+    app.write_at(0, b"v2").unwrap();
+    chmod(&app, VfsNodePerm::from_bits_truncate(0o755)).unwrap();
+    root.rename("bin/new", "new").unwrap();
+    assert_eq!(
+        watcher.next_event(),
+        event(WatchEventKind::Create, Some("new"))
+    );
+    assert_eq!(watcher.next_event(), event(WatchEventKind::Modify, None));
+    assert_eq!(watcher.next_event(), event(WatchEventKind::Attrib, None));
+    assert_eq!(
+        watcher.next_event(),
+        event(WatchEventKind::MovedFrom, Some("new"))
+    );
+    assert_eq!(watcher.next_event(), None);
+    assert!(!watcher.take_overflow());
+
+    // Events are dropped when the queue is full.
+    for _ in 0..5 {
+        app.truncate(0).unwrap();
+    }
+    root.remove("bin/app").unwrap();
+    assert!(watcher.take_overflow());
+    assert!(!watcher.take_overflow());
+    for _ in 0..4 {
+        assert_eq!(watcher.next_event(), event(WatchEventKind::Modify, None));
+    }
+    root.create("bin/app", VfsNodeType::File).unwrap();
+    assert_eq!(
+        watcher.next_event(),
+        event(WatchEventKind::Create, Some("app"))
+    );
+    drop(watcher);
+    root.remove("bin/app").unwrap();
+}
+
+#[test]
+fn test_case_insensitive() {
+    let ramfs = RamFileSystem::with_options(RamFsOptions {
+        case_insensitive: true,
+        ..Default::default()
+    });
+    let root = ramfs.root_dir();
+    root.create("Boot", VfsNodeType::Dir).unwrap();
+    root.create("boot/Kernel.ELF", VfsNodeType::File).unwrap();
+    assert_eq!(
+        root.create("BOOT/kernel.elf", VfsNodeType::File),
+        Err(VfsError::AlreadyExists)
+    );
+    let kernel = root.clone().lookup("BOOT/KERNEL.elf").unwrap();
+    let dir = ramfs.root_dir_node().lookup("boot").unwrap();
+    let dir = dir.as_any().downcast_ref::<DirNode>().unwrap();
+    assert!(dir.exist("kernel.elf"));
+    assert_eq!(dir.get_entries(), ["Kernel.ELF"]);
+    assert!(Arc::ptr_eq(
+        &kernel,
+        &root.clone().lookup("boot/kernel.elf").unwrap()
+    ));
+
+    // Renaming to another case of the same name.
+    root.rename("boot/kernel.elf", "boot/KERNEL.ELF").unwrap();
+    assert_eq!(dir.get_entries(), ["KERNEL.ELF"]);
+    // Replacing another entry, which keeps the new name.
+    root.create("boot/other", VfsNodeType::File).unwrap();
+    root.rename("boot/Other", "boot/kernel.elf").unwrap();
+    assert_eq!(dir.get_entries(), ["kernel.elf"]);
+    root.remove("BOOT/Kernel.ELF").unwrap();
+    assert!(dir.get_entries().is_empty());
+
+    // Case-sensitive by default.
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("a", VfsNodeType::File).unwrap();
+    root.create("A", VfsNodeType::File).unwrap();
+    assert_eq!(ramfs.root_dir_node().get_entries(), ["A", "a"]);
+}
+
+#[test]
+fn test_names() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for name in ["", ".", "..", "a/b", "/a", "a\0b"] {
+        assert_eq!(
+            root.create_node(name, VfsNodeType::File),
+            Err(VfsError::InvalidInput),
+            "{:?}",
+            name
+        );
+    }
+    let long = "x".repeat(NAME_MAX + 1);
+    assert_eq!(
+        root.create_node(&long, VfsNodeType::File),
+        Err(VfsError::InvalidInput)
+    );
+    root.create_node(&long[1..], VfsNodeType::File).unwrap();
+    let file = root.clone().lookup(&long[1..]).unwrap();
+    assert_eq!(root.link("a\0", file.clone()), Err(VfsError::InvalidInput));
+    assert_eq!(root.rename(&long[1..], &long), Err(VfsError::InvalidInput));
+    assert_eq!(root.get_entries(), [&long[1..]]);
+
+    // Configurable limits.
+    let ramfs = RamFileSystem::with_options(RamFsOptions {
+        name_max: 4,
+        path_max: 8,
+        ..Default::default()
+    });
+    let root = ramfs.root_dir();
+    root.create("abcd", VfsNodeType::Dir).unwrap();
+    assert_eq!(
+        root.create("abcde", VfsNodeType::Dir),
+        Err(VfsError::InvalidInput)
+    );
+    root.create("abcd/abc", VfsNodeType::File).unwrap();
+    assert_eq!(
+        root.create("abcd/abcd", VfsNodeType::File),
+        Err(VfsError::InvalidInput)
+    );
+    assert_eq!(
+        root.clone().lookup("abcd/abc/").err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert_eq!(ramfs.stats().max_name_len, Some(4));
+}
+
+#[test]
+fn test_mount() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("mnt", VfsNodeType::Dir).unwrap();
+    root.create("mnt/hidden", VfsNodeType::File).unwrap();
+    root.create("f", VfsNodeType::File).unwrap();
+
+    let other = Arc::new(RamFileSystem::new());
+    other.root_dir().create("hello", VfsNodeType::File).unwrap();
+    let mnt = root.clone().lookup("mnt").unwrap();
+    let mnt_dir = mnt.as_any().downcast_ref::<DirNode>().unwrap();
+    mnt_dir.mount(other.clone()).unwrap();
+    assert!(mnt_dir.is_mount_point());
+    assert_eq!(mnt_dir.mount(other.clone()), Err(VfsError::ResourceBusy));
+
+    // Lookups and changes go to the mounted filesystem.
+    assert!(root.clone().lookup("mnt/hello").is_ok());
+    assert_eq!(
+        root.clone().lookup("mnt/hidden").err(),
+        Some(VfsError::NotFound)
+    );
+    root.create("mnt/new", VfsNodeType::Dir).unwrap();
+    root.rename("mnt/new", "mnt/renamed").unwrap();
+    assert_eq!(other.root_dir_node().get_entries(), ["hello", "renamed"]);
+    assert!(Arc::ptr_eq(
+        &root.clone().lookup("mnt/renamed/../..").unwrap(),
+        &root
+    ));
+
+    // The mount point is busy.
+    assert_eq!(root.remove("mnt"), Err(VfsError::ResourceBusy));
+    assert_eq!(root.rename("mnt", "mnt2"), Err(VfsError::ResourceBusy));
+    assert_eq!(root.rename("f", "mnt"), Err(VfsError::ResourceBusy));
+    assert_eq!(root.rename("f", "mnt/f"), Err(VfsError::Unsupported));
+    let cwd = root.clone().lookup("mnt").unwrap();
+    assert_eq!(mnt_dir.umount(), Err(VfsError::ResourceBusy));
+    drop(cwd);
+    mnt_dir.umount().unwrap();
+    assert_eq!(mnt_dir.umount(), Err(VfsError::InvalidInput));
+    assert!(root.clone().lookup("mnt/hidden").is_ok());
+    assert_eq!(
+        root.clone().lookup("mnt/hello").err(),
+        Some(VfsError::NotFound)
+    );
+}
+
+#[test]
+fn test_pin_page() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f", VfsNodeType::File).unwrap();
+    let node = root.clone().lookup("f").unwrap();
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+    assert_eq!(file.pin_page(0), Err(VfsError::InvalidInput));
+    file.truncate(PAGE_SIZE as u64 + 5).unwrap();
+    assert_eq!(ramfs.stats().used_bytes, 0);
+
+    // A hole is allocated when pinned.
+    let page = file.pin_page(PAGE_SIZE as u64 + 3).unwrap();
+    assert_eq!(page.vaddr % PAGE_SIZE, 0);
+    assert_eq!((page.offset, page.len), (PAGE_SIZE as u64, 5));
+    assert_eq!(ramfs.stats().used_bytes, PAGE_SIZE);
+    let mapped = unsafe { core::slice::from_raw_parts_mut(page.vaddr as *mut u8, PAGE_SIZE) };
+
+    // The page is shared by the file and the mapping.
+    file.write_at(PAGE_SIZE as u64, b"hello").unwrap();
+    assert_eq!(&mapped[..5], b"hello");
+    mapped[..5].copy_from_slice(b"world");
+    let mut buf = [0; 5];
+    file.read_at(PAGE_SIZE as u64, &mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    // It stays where it is mapped when a snapshot is taken.
+    let snap = ramfs.snapshot();
+    file.write_at(PAGE_SIZE as u64, b"HELLO").unwrap();
+    assert_eq!(&mapped[..5], b"HELLO");
+    let snap_fs = snap.read_only_fs();
+    let old = snap_fs.root_dir().lookup("f").unwrap();
+    old.read_at(PAGE_SIZE as u64, &mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    // Truncating zeroes it, it is freed when unpinned.
+    file.truncate(0).unwrap();
+    assert!(mapped.iter().all(|&b| b == 0));
+    assert_eq!(ramfs.stats().used_bytes, PAGE_SIZE);
+    file.unpin_page(PAGE_SIZE as u64).unwrap();
+    assert_eq!(ramfs.stats().used_bytes, 0);
+    assert_eq!(file.unpin_page(0), Err(VfsError::InvalidInput));
+
+    // A removed file keeps its pinned pages.
+    file.write_at(0, b"data").unwrap();
+    let page = file.pin_page(0).unwrap();
+    root.remove("f").unwrap();
+    assert_eq!(ramfs.stats().used_bytes, PAGE_SIZE);
+    let mapped = unsafe { core::slice::from_raw_parts(page.vaddr as *const u8, 4) };
+    assert_eq!(mapped, b"data");
+    file.unpin_page(0).unwrap();
+    assert_eq!(ramfs.stats().used_bytes, 0);
+}
+
+#[test]
+fn test_for_each_entry() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for name in ["a", "c", "e", "g"] {
+        root.create_node(name, VfsNodeType::File).unwrap();
+    }
+    let mut all = Vec::new();
+    root.for_each_entry(None, |name, node| {
+        all.push((name.to_string(), node.get_attr().unwrap().file_type()));
+        true
+    });
+    assert_eq!(all.len(), 4);
+    assert!(all.iter().all(|(_, ty)| *ty == VfsNodeType::File));
+
+    // Listed in batches of 2, with changes in between.
+    let batch = |last: Option<&str>| {
+        let mut names = Vec::new();
+        root.for_each_entry(last, |name, _| {
+            names.push(name.to_string());
+            names.len() < 2
+        });
+        names
+    };
+    assert_eq!(batch(None), ["a", "c"]);
+    root.create_node("b", VfsNodeType::File).unwrap();
+    root.create_node("d", VfsNodeType::File).unwrap();
+    root.remove_node("e").unwrap();
+    assert_eq!(batch(Some("c")), ["d", "g"]);
+    assert_eq!(batch(Some("g")), [] as [String; 0]);
+    // The last entry may have been removed.
+    assert_eq!(batch(Some("e")), ["g"]);
+}
+
+#[test]
+fn test_remove_all() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    for (path, ty) in [
+        ("a", VfsNodeType::Dir),
+        ("a/b", VfsNodeType::Dir),
+        ("a/b/c", VfsNodeType::File),
+        ("a/d", VfsNodeType::File),
+        ("a/e", VfsNodeType::Fifo),
+    ] {
+        root.create(path, ty).unwrap();
+    }
+    let inodes = ramfs.stats().used_inodes;
+    assert_eq!(root.remove("a"), Err(VfsError::DirectoryNotEmpty));
+    remove_all(&root, "a/b/").unwrap();
+    remove_all(&root, "a").unwrap();
+    assert_eq!(ramfs.stats().used_inodes, inodes - 5);
+    assert_eq!(
+        remove_all(&root, "a"),
+        Err(RemoveError {
+            path: "a".into(),
+            error: VfsError::NotFound,
+        })
+    );
+
+    // The failing entry is reported, the others are removed.
+    for path in ["x", "x/y", "x/y/a", "x/y/mnt"] {
+        root.create(path, VfsNodeType::Dir).unwrap();
+    }
+    let mnt = root.clone().lookup("x/y/mnt").unwrap();
+    let mnt = mnt.as_any().downcast_ref::<DirNode>().unwrap();
+    mnt.mount(Arc::new(RamFileSystem::new())).unwrap();
+    let err = remove_all(&root, "x").unwrap_err();
+    assert_eq!(err.path, "x/y/mnt");
+    assert_eq!(VfsError::from(err), VfsError::ResourceBusy);
+    assert_eq!(remove_all(&root, "/x/y").unwrap_err().path, "/x/y/mnt");
+    assert!(root.clone().lookup("x/y/a").is_err());
+    mnt.umount().unwrap();
+    remove_all(&root, "x").unwrap();
+    assert!(ramfs.root_dir_node().get_entries().is_empty());
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_compress() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("zeros", VfsNodeType::File).unwrap();
+    root.create("text", VfsNodeType::File).unwrap();
+    root.create("noise", VfsNodeType::File).unwrap();
+    root.create("hot", VfsNodeType::File).unwrap();
+    let zeros = root.clone().lookup("zeros").unwrap();
+    zeros.write_at(0, &[0; 3 * 4096]).unwrap();
+    let text: Vec<u8> = (0..6000)
+        .map(|i| b"aaaaaaaabbbbbbbbbbbbcd"[i % 22])
+        .collect();
+    let text_file = root.clone().lookup("text").unwrap();
+    text_file.write_at(100, &text).unwrap();
+    let noise: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+    let noise_file = root.clone().lookup("noise").unwrap();
+    noise_file.write_at(0, &noise).unwrap();
+    let hot = root.clone().lookup("hot").unwrap();
+    hot.write_at(0, &[1; 4096]).unwrap();
+    let hot = hot.as_any().downcast_ref::<FileNode>().unwrap();
+    hot.set_compressible(false);
+    assert_eq!(ramfs.stats().used_bytes, 7 * 4096);
+
+    let saved = ramfs.compress();
+    let stats = ramfs.stats();
+    assert_eq!(stats.packed_pages, 5);
+    assert_eq!(stats.used_bytes, 7 * 4096 - saved);
+    assert_eq!(stats.used_bytes, 2 * 4096 + stats.packed_bytes);
+    assert!(stats.packed_bytes < 4096);
+    assert_eq!(ramfs.compress(), 0);
+
+    // The data is the same, read in pieces.
+    let mut buf = vec![0; 6100];
+    for (off, len) in [(0, 6100), (97, 1), (4000, 300), (5000, 1100)] {
+        let n = text_file.read_at(off, &mut buf[..len]).unwrap();
+        let expected: Vec<u8> = (off as usize..off as usize + n)
+            .map(|i| if i < 100 { 0 } else { text[i - 100] })
+            .collect();
+        assert_eq!(buf[..n], expected);
+    }
+    zeros.read_at(5000, &mut buf[..10]).unwrap();
+    assert_eq!(buf[..10], [0; 10]);
+
+    // Written pages are decompressed, truncated ones freed.
+    text_file.write_at(4096, b"xyz").unwrap();
+    text_file.read_at(4090, &mut buf[..10]).unwrap();
+    assert_eq!(buf[..6], text[3990..3996]);
+    assert_eq!(&buf[6..9], b"xyz");
+    assert_eq!(buf[9], text[3999]);
+    zeros.truncate(5000).unwrap();
+    zeros.read_at(0, &mut buf[..5000]).unwrap();
+    assert!(buf[..5000].iter().all(|&b| b == 0));
+    assert_eq!(ramfs.stats().packed_pages, 2);
+    assert_eq!(zeros.get_attr().unwrap().size(), 5000);
+
+    // Snapshots share the compressed pages, the counters follow.
+    let snap = ramfs.snapshot();
+    let snap_fs = snap.read_only_fs();
+    assert_eq!(snap_fs.stats().packed_pages, 2);
+    root.remove("zeros").unwrap();
+    root.remove("text").unwrap();
+    assert_eq!(ramfs.stats().packed_pages, 0);
+    assert_eq!(ramfs.stats().used_bytes, 2 * 4096);
+}
+
+#[test]
+fn test_anonymous_file() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    let tmp = ramfs.create_anonymous_file().unwrap();
+    assert_eq!(ramfs.stats().used_inodes, 2);
+    tmp.write_at(0, b"published").unwrap();
+    assert!(root.clone().lookup("pub").is_err());
+    link_into(&tmp, &root, "pub").unwrap();
+    tmp.release().unwrap();
+    let mut buf = [0; 9];
+    let node = root.clone().lookup("pub").unwrap();
+    node.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"published");
+    assert_eq!(node.as_any().downcast_ref::<FileNode>().unwrap().nlink(), 1);
+
+    // Freed once released if it has no name.
+    let scratch = ramfs.create_anonymous_file().unwrap();
+    scratch.write_at(0, &[1; 100]).unwrap();
+    assert_eq!(ramfs.stats().used_bytes, 2 * 4096);
+    scratch.release().unwrap();
+    assert_eq!(ramfs.stats().used_bytes, 4096);
+    assert_eq!(link_into(&scratch, &root, "late"), Err(VfsError::NotFound));
+    drop(scratch);
+    assert_eq!(ramfs.stats().used_inodes, 2);
+
+    // Only linked into the same filesystem.
+    let other = RamFileSystem::new();
+    let tmp = other.create_anonymous_file().unwrap();
+    assert_eq!(link_into(&tmp, &root, "x"), Err(VfsError::Unsupported));
+    let snap = ramfs.snapshot();
+    assert_eq!(
+        snap.read_only_fs().create_anonymous_file().err(),
+        Some(VfsError::PermissionDenied)
+    );
+}
+
+#[test]
+fn test_reserve() {
+    let ramfs = RamFileSystem::with_options(RamFsOptions {
+        max_bytes: Some(4 * 4096),
+        ..Default::default()
+    });
+    let root = ramfs.root_dir();
+    root.create("download", VfsNodeType::File).unwrap();
+    root.create("other", VfsNodeType::File).unwrap();
+    let file = root.clone().lookup("download").unwrap();
+    let other = root.clone().lookup("other").unwrap();
+
+    fallocate(&file, 0, 3 * 4096).unwrap();
+    assert_eq!(ramfs.stats().used_bytes, 3 * 4096);
+    assert_eq!(file.get_attr().unwrap().size(), 0);
+    assert_eq!(fallocate(&file, 0, 5 * 4096), Err(VfsError::StorageFull));
+    assert_eq!(ramfs.stats().used_bytes, 3 * 4096);
+
+    // The reserved storage is only used by the file.
+    assert_eq!(
+        other.write_at(0, &[1; 2 * 4096]),
+        Err(VfsError::StorageFull)
+    );
+    other.write_at(0, &[1; 4096]).unwrap();
+    file.write_at(0, &[2; 3 * 4096]).unwrap();
+    assert_eq!(ramfs.stats().used_bytes, 4 * 4096);
+    assert_eq!(file.get_attr().unwrap().size(), 3 * 4096);
+
+    // Freed past the end of the file by a truncate.
+    other.truncate(0).unwrap();
+    fallocate(&file, 3 * 4096, 100).unwrap();
+    assert_eq!(file.get_attr().unwrap().size(), 3 * 4096);
+    file.truncate(4096).unwrap();
+    assert_eq!(ramfs.stats().used_bytes, 4096);
+    assert_eq!(fallocate(&root, 0, 1), Err(VfsError::Unsupported));
+}
+
+#[test]
+fn test_hashed_dirs() {
+    let ramfs = RamFileSystem::with_options(RamFsOptions {
+        hashed_dirs: true,
+        case_insensitive: true,
+        ..Default::default()
+    });
+    let root = ramfs.root_dir_node();
+    for i in 0..100 {
+        root.create_node(&format!("File{}", i), VfsNodeType::File)
+            .unwrap();
+    }
+    assert_eq!(
+        root.create_node("file7", VfsNodeType::File),
+        Err(VfsError::AlreadyExists)
+    );
+    assert!(root.clone().lookup("FILE42").is_ok());
+    root.clone().rename("file42", "moved").unwrap();
+    root.remove_node("FILE43").unwrap();
+    assert!(!root.exist("File42") && !root.exist("File43"));
+    assert!(root.exist("Moved"));
+
+    // Listed in a stable order, also by cursor.
+    let names = root.get_entries();
+    assert_eq!(names.len(), 99);
+    assert!(names.contains(&"File0".to_string()));
+    let mut listed = Vec::new();
+    let mut last = None;
+    loop {
+        let mut batch = Vec::new();
+        root.for_each_entry(last.as_deref(), |name, _| {
+            batch.push(name.to_string());
+            batch.len() < 10
+        });
+        if batch.is_empty() {
+            break;
+        }
+        last = batch.last().cloned();
+        listed.extend(batch);
+    }
+    assert_eq!(listed, names);
+    let mut dirents: Vec<_> = (0..128).map(|_| VfsDirEntry::default()).collect();
+    assert_eq!(root.read_dir(0, &mut dirents).unwrap(), 101);
+    assert_eq!(dirents[2].name_as_bytes(), names[0].as_bytes());
+}
+
+#[test]
+fn test_lookup_cache() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("a", VfsNodeType::Dir).unwrap();
+    root.create("a/b", VfsNodeType::File).unwrap();
+    let lookups = |ramfs: &RamFileSystem| {
+        let stats = ramfs.stats();
+        (stats.lookup_hits, stats.lookup_misses)
+    };
+    let (hits, misses) = lookups(&ramfs);
+
+    // The same path, however written, is walked once.
+    let b = root.clone().lookup("a/b").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("/a//./b").unwrap(), &b));
+    assert_eq!(lookups(&ramfs), (hits + 1, misses + 1));
+    // Also when it is missing.
+    for _ in 0..2 {
+        let err = root.clone().lookup("a/c").err();
+        assert_eq!(err, Some(VfsError::NotFound));
+    }
+    assert_eq!(lookups(&ramfs), (hits + 2, misses + 2));
+    // Paths with `..` or that must name a directory are not cached.
+    assert!(root.clone().lookup("a/../a/b").is_ok());
+    assert!(root.clone().lookup("a/").is_ok());
+    assert_eq!(lookups(&ramfs), (hits + 2, misses + 2));
+
+    // Changes of the names are seen at once.
+    root.create("a/c", VfsNodeType::File).unwrap();
+    let c = root.clone().lookup("a/c").unwrap();
+    root.rename("a/c", "a/d").unwrap();
+    assert_eq!(root.clone().lookup("a/c").err(), Some(VfsError::NotFound));
+    assert!(Arc::ptr_eq(&root.clone().lookup("a/d").unwrap(), &c));
+    root.remove("a/b").unwrap();
+    assert_eq!(root.clone().lookup("a/b").err(), Some(VfsError::NotFound));
+    let a = root.clone().lookup("a").unwrap();
+    a.as_any()
+        .downcast_ref::<DirNode>()
+        .unwrap()
+        .link("b", c.clone())
+        .unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("a/b").unwrap(), &c));
+
+    // And the filesystems mounted in this one.
+    let other = Arc::new(RamFileSystem::new());
+    other.root_dir().create("b", VfsNodeType::Dir).unwrap();
+    let a_dir = a.as_any().downcast_ref::<DirNode>().unwrap();
+    a_dir.mount(other.clone()).unwrap();
+    let b = root.clone().lookup("a/b").unwrap();
+    assert_eq!(b.get_attr().unwrap().file_type(), VfsNodeType::Dir);
+    other.root_dir().remove("b").unwrap();
+    assert_eq!(root.clone().lookup("a/b").err(), Some(VfsError::NotFound));
+    drop(b);
+    a_dir.umount().unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("a/b").unwrap(), &c));
+
+    // Without the cache, nothing is counted.
+    let ramfs = RamFileSystem::with_options(RamFsOptions {
+        lookup_cache: 0,
+        ..Default::default()
+    });
+    ramfs.root_dir().create("f", VfsNodeType::File).unwrap();
+    for _ in 0..2 {
+        assert!(ramfs.root_dir().lookup("f").is_ok());
+    }
+    assert_eq!(lookups(&ramfs), (0, 0));
+}
@@ -0,0 +1,112 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+/// The kind of a [`WatchEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEventKind {
+    /// A node was created or linked in the watched directory.
+    Create,
+    /// A node was removed from the watched directory.
+    Remove,
+    /// A node was renamed or moved out of the watched directory.
+    MovedFrom,
+    /// A node was renamed or moved into the watched directory.
+    MovedTo,
+    /// The content of the watched file was written or truncated.
+    Modify,
+    /// The permissions or the owner of the watched node changed.
+    Attrib,
+}
+
+/// An event on a watched node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    /// What happened.
+    pub kind: WatchEventKind,
+    /// The name of the node in the watched directory, `None` for events on
+    /// the watched node itself.
+    pub name: Option<String>,
+}
+
+struct WatchQueue {
+    events: Mutex<VecDeque<WatchEvent>>,
+    capacity: usize,
+    overflowed: AtomicBool,
+}
+
+/// A queue of the events on the nodes it watches, like an inotify instance.
+///
+/// Nodes are watched with [`DirNode::watch`](crate::DirNode::watch),
+/// [`FileNode::watch`](crate::FileNode::watch) or [`watch`](crate::watch),
+/// until the watcher and its clones are dropped. The events are consumed with
+/// [`next_event`](Self::next_event), there is no blocking wait.
+#[derive(Clone)]
+pub struct Watcher {
+    queue: Arc<WatchQueue>,
+}
+
+impl Watcher {
+    /// Creates a watcher that keeps at most `capacity` events, the newer
+    /// ones are dropped until events are consumed.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queue: Arc::new(WatchQueue {
+                events: Mutex::new(VecDeque::new()),
+                capacity,
+                overflowed: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Removes and returns the oldest event, if any.
+    pub fn next_event(&self) -> Option<WatchEvent> {
+        self.queue.events.lock().pop_front()
+    }
+
+    /// Returns whether events were dropped because the queue was full since
+    /// the last call, and clears it.
+    pub fn take_overflow(&self) -> bool {
+        self.queue.overflowed.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// The watchers of a node.
+#[derive(Default)]
+pub(crate) struct WatchList {
+    queues: Mutex<Vec<Weak<WatchQueue>>>,
+}
+
+impl WatchList {
+    pub(crate) fn add(&self, watcher: &Watcher) {
+        let mut queues = self.queues.lock();
+        let new = Arc::downgrade(&watcher.queue);
+        if !queues.iter().any(|q| q.ptr_eq(&new)) {
+            queues.push(new);
+        }
+    }
+
+    /// Queues an event to all the watchers, and forgets the dropped ones.
+    pub(crate) fn notify(&self, kind: WatchEventKind, name: Option<&str>) {
+        let mut queues = self.queues.lock();
+        if queues.is_empty() {
+            return;
+        }
+        queues.retain(|queue| {
+            let Some(queue) = queue.upgrade() else {
+                return false;
+            };
+            let mut events = queue.events.lock();
+            if events.len() < queue.capacity {
+                let name = name.map(String::from);
+                events.push_back(WatchEvent { kind, name });
+            } else {
+                queue.overflowed.store(true, Ordering::Release);
+            }
+            true
+        });
+    }
+}
@@ -0,0 +1,156 @@
+//! A first-fit byte allocator keeping its free blocks in a linked list.
+
+use core::alloc::Layout;
+use core::mem::size_of;
+use core::ptr::NonNull;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
+
+/// Header written at the start of each free block.
+#[derive(Clone, Copy)]
+struct FreeBlock {
+    size: usize,
+    next: Option<NonNull<FreeBlock>>,
+}
+
+/// Allocation granularity, so that any free block can hold its header.
+const UNIT: usize = size_of::<FreeBlock>();
+
+const fn align_up(pos: usize, align: usize) -> usize {
+    (pos + align - 1) & !(align - 1)
+}
+
+/// Links its free blocks in a list sorted by address, stored in the free
+/// blocks themselves, so that it needs no other memory.
+///
+/// Allocation takes the first block that fits, freed blocks are merged with
+/// the adjacent free blocks. Sizes are rounded up to the size of the block
+/// header.
+pub struct FreeListByteAllocator {
+    head: Option<NonNull<FreeBlock>>,
+    total_bytes: usize,
+    used_bytes: usize,
+}
+
+impl FreeListByteAllocator {
+    pub const fn new() -> Self {
+        Self {
+            head: None,
+            total_bytes: 0,
+            used_bytes: 0,
+        }
+    }
+
+    /// Adds the block `[start, start + size)` to the list, merged with its
+    /// neighbours. Both must be multiples of [`UNIT`].
+    fn insert_free(&mut self, start: usize, size: usize) {
+        let mut prev: Option<NonNull<FreeBlock>> = None;
+        let mut next = self.head;
+        while let Some(block) = next {
+            if block.as_ptr() as usize > start {
+                break;
+            }
+            prev = Some(block);
+            next = unsafe { block.as_ref().next };
+        }
+
+        let mut new = FreeBlock { size, next };
+        if let Some(next) = next {
+            if start + size == next.as_ptr() as usize {
+                let next = unsafe { *next.as_ptr() };
+                new.size += next.size;
+                new.next = next.next;
+            }
+        }
+        match prev {
+            Some(mut prev) if prev.as_ptr() as usize + unsafe { prev.as_ref().size } == start => {
+                // 与前一个空闲块相邻，直接扩展它
+                let prev = unsafe { prev.as_mut() };
+                prev.size += new.size;
+                prev.next = new.next;
+            }
+            _ => {
+                let block = NonNull::new(start as *mut FreeBlock).unwrap();
+                unsafe { block.as_ptr().write(new) };
+                match prev {
+                    Some(mut prev) => unsafe { prev.as_mut().next = Some(block) },
+                    None => self.head = Some(block),
+                }
+            }
+        }
+    }
+}
+
+impl BaseAllocator for FreeListByteAllocator {
+    fn init(&mut self, start: usize, size: usize) {
+        self.head = None;
+        self.total_bytes = 0;
+        self.used_bytes = 0;
+        // 新建的分配器不会失败
+        let _ = self.add_memory(start, size);
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)? & !(UNIT - 1);
+        let start = align_up(start, UNIT);
+        if end <= start {
+            return Err(AllocError::InvalidParam);
+        }
+        self.insert_free(start, end - start);
+        self.total_bytes += end - start;
+        Ok(())
+    }
+}
+
+impl ByteAllocator for FreeListByteAllocator {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let size = align_up(layout.size().max(1), UNIT);
+        let align = layout.align().max(UNIT);
+        let mut prev: Option<NonNull<FreeBlock>> = None;
+        let mut cur = self.head;
+        while let Some(block) = cur {
+            let start = block.as_ptr() as usize;
+            let FreeBlock {
+                size: free_size,
+                next,
+            } = unsafe { *block.as_ptr() };
+            let pos = align_up(start, align);
+            if pos + size <= start + free_size {
+                // 取出这个空闲块，前后剩余的部分放回链表
+                match prev {
+                    Some(mut prev) => unsafe { prev.as_mut().next = next },
+                    None => self.head = next,
+                }
+                if pos > start {
+                    self.insert_free(start, pos - start);
+                }
+                if pos + size < start + free_size {
+                    self.insert_free(pos + size, start + free_size - pos - size);
+                }
+                self.used_bytes += size;
+                return Ok(NonNull::new(pos as *mut u8).unwrap());
+            }
+            prev = cur;
+            cur = next;
+        }
+        Err(AllocError::NoMemory)
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        let size = align_up(layout.size().max(1), UNIT);
+        self.insert_free(pos.as_ptr() as usize, size);
+        self.used_bytes -= size;
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.total_bytes - self.used_bytes
+    }
+}
@@ -0,0 +1,133 @@
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+extern crate alloc;
+
+mod free_list;
+mod map;
+
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use std::time::{Duration, Instant};
+
+use allocator::{BuddyByteAllocator, ByteAllocator, SlabByteAllocator, TlsfByteAllocator};
+use bump_allocator::EarlyAllocator;
+use free_list::FreeListByteAllocator;
+use map::MapByteAllocator;
+
+/// Size of the memory region handed to each backend.
+///
+/// Must be a multiple of the slab allocator's minimum heap size (32 KiB).
+const ARENA_SIZE: usize = 8 * 1024 * 1024;
+const ARENA_ALIGN: usize = 4096;
+
+/// Smallest and largest block of one allocation round, as in the lab1 app.
+const MIN_BLOCK: usize = 32;
+const MAX_BLOCK: usize = 512 * 1024;
+
+struct BenchResult {
+    indicator: usize,
+    peak_bytes: usize,
+    elapsed: Duration,
+}
+
+/// Runs the lab1 workload on `alloc` until it reports out of memory.
+///
+/// Every round allocates blocks of `base + delta` bytes, with `base` doubling
+/// from [`MIN_BLOCK`] to [`MAX_BLOCK`] and `delta` being the round number,
+/// then frees every other block. Since more is allocated than freed, the
+/// arena eventually runs out. The number of completed rounds is the
+/// "Indicator" score of the lab grader.
+fn run_workload(alloc: &mut dyn ByteAllocator) -> BenchResult {
+    let mut pool: Vec<(NonNull<u8>, Layout)> = Vec::new();
+    let mut indicator = 0;
+    let mut peak_bytes = 0;
+    let start = Instant::now();
+
+    'rounds: loop {
+        let delta = indicator;
+        let fill = (delta % 256) as u8;
+        let mut items = Vec::new();
+        let mut base = MIN_BLOCK;
+        loop {
+            let layout = Layout::array::<u8>(base + delta).unwrap();
+            match alloc.alloc(layout) {
+                Ok(ptr) => {
+                    unsafe { ptr.as_ptr().write_bytes(fill, layout.size()) };
+                    items.push((ptr, layout));
+                }
+                Err(_) => {
+                    pool.extend(items);
+                    break 'rounds;
+                }
+            }
+            peak_bytes = peak_bytes.max(alloc.used_bytes());
+            if base >= MAX_BLOCK {
+                break;
+            }
+            base *= 2;
+        }
+
+        for (i, (ptr, layout)) in items.into_iter().enumerate() {
+            if i % 2 == 0 {
+                let block = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), layout.size()) };
+                assert!(block.iter().all(|&b| b == fill), "block corrupted");
+                alloc.dealloc(ptr, layout);
+            } else {
+                pool.push((ptr, layout));
+            }
+        }
+        indicator += 1;
+    }
+
+    let elapsed = start.elapsed();
+    // Give everything back so that the arena can be reused by the next backend.
+    for (ptr, layout) in pool {
+        alloc.dealloc(ptr, layout);
+    }
+    BenchResult {
+        indicator,
+        peak_bytes,
+        elapsed,
+    }
+}
+
+fn bench(name: &str, alloc: &mut dyn ByteAllocator, arena: usize) {
+    alloc.init(arena, ARENA_SIZE);
+    let res = run_workload(alloc);
+    println!(
+        "{:<8} {:>10} {:>12} {:>10}",
+        name,
+        res.indicator,
+        res.peak_bytes / 1024,
+        res.elapsed.as_micros(),
+    );
+}
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    println!("Running allocator benchmarks...");
+
+    let arena_layout = Layout::from_size_align(ARENA_SIZE, ARENA_ALIGN).unwrap();
+    let arena = unsafe { alloc::alloc::alloc(arena_layout) };
+    assert!(!arena.is_null(), "failed to reserve the benchmark arena");
+    let arena = arena as usize;
+
+    println!(
+        "{:<8} {:>10} {:>12} {:>10}",
+        "backend", "indicator", "peak(KiB)", "time(us)"
+    );
+    bench("bump", &mut EarlyAllocator::<4096>::new(), arena);
+    bench("map", &mut MapByteAllocator::new(), arena);
+    bench("freelist", &mut FreeListByteAllocator::new(), arena);
+    bench("buddy", &mut BuddyByteAllocator::new(), arena);
+    bench("slab", &mut SlabByteAllocator::new(), arena);
+    bench("tlsf", &mut TlsfByteAllocator::new(), arena);
+
+    unsafe { alloc::alloc::dealloc(arena as *mut u8, arena_layout) };
+    println!("\n[AllocBench]: ok!");
+}
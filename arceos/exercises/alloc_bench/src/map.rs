@@ -0,0 +1,104 @@
+//! A first-fit byte allocator keeping its free ranges in a map.
+
+use alloc::collections::BTreeMap;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
+
+/// Tracks the free ranges of its memory in a [`BTreeMap`] from their start to
+/// their size, allocated from the global heap.
+///
+/// Allocation takes the first range that fits, freed blocks are merged with
+/// the adjacent free ranges.
+pub struct MapByteAllocator {
+    free: BTreeMap<usize, usize>,
+    total_bytes: usize,
+    used_bytes: usize,
+}
+
+impl MapByteAllocator {
+    pub const fn new() -> Self {
+        Self {
+            free: BTreeMap::new(),
+            total_bytes: 0,
+            used_bytes: 0,
+        }
+    }
+
+    /// Adds the range `[start, start + size)` to the free ranges, merged with
+    /// its neighbours.
+    fn insert_free(&mut self, mut start: usize, mut size: usize) {
+        if let Some((&prev, &prev_size)) = self.free.range(..start).next_back() {
+            if prev + prev_size == start {
+                self.free.remove(&prev);
+                start = prev;
+                size += prev_size;
+            }
+        }
+        if let Some(next_size) = self.free.remove(&(start + size)) {
+            size += next_size;
+        }
+        self.free.insert(start, size);
+    }
+}
+
+impl BaseAllocator for MapByteAllocator {
+    fn init(&mut self, start: usize, size: usize) {
+        self.free.clear();
+        self.total_bytes = 0;
+        self.used_bytes = 0;
+        // 新建的分配器不会失败
+        let _ = self.add_memory(start, size);
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        self.insert_free(start, size);
+        self.total_bytes += size;
+        Ok(())
+    }
+}
+
+impl ByteAllocator for MapByteAllocator {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let size = layout.size().max(1);
+        let (start, free_size, pos) = self
+            .free
+            .iter()
+            .find_map(|(&start, &free_size)| {
+                let pos = (start + layout.align() - 1) & !(layout.align() - 1);
+                (pos + size <= start + free_size).then_some((start, free_size, pos))
+            })
+            .ok_or(AllocError::NoMemory)?;
+
+        // 切出所需的部分，前后剩余的部分仍然空闲
+        self.free.remove(&start);
+        if pos > start {
+            self.free.insert(start, pos - start);
+        }
+        if pos + size < start + free_size {
+            self.free.insert(pos + size, start + free_size - pos - size);
+        }
+        self.used_bytes += size;
+        Ok(NonNull::new(pos as *mut u8).unwrap())
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        let size = layout.size().max(1);
+        self.insert_free(pos.as_ptr() as usize, size);
+        self.used_bytes -= size;
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.total_bytes - self.used_bytes
+    }
+}
@@ -0,0 +1,349 @@
+use std::io::{self, prelude::*};
+use std::{string::String, vec::Vec};
+
+use axfs_ramfs::{DirNode, RamFileSystem};
+use axfs_vfs::path::canonicalize;
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+
+macro_rules! print_err {
+    ($cmd: literal, $msg: expr) => {
+        println!("{}: {}", $cmd, $msg);
+    };
+    ($cmd: literal, $arg: expr, $err: expr) => {
+        println!("{}: {}: {:?}", $cmd, $arg, $err);
+    };
+}
+
+type CmdHandler = fn(&mut Shell, &str);
+
+const CMD_TABLE: &[(&str, CmdHandler)] = &[
+    ("cat", do_cat),
+    ("cd", do_cd),
+    ("df", do_df),
+    ("echo", do_echo),
+    ("exit", do_exit),
+    ("help", do_help),
+    ("ln", do_ln),
+    ("ls", do_ls),
+    ("mkdir", do_mkdir),
+    ("mv", do_mv),
+    ("pwd", do_pwd),
+    ("rm", do_rm),
+];
+
+/// A tiny shell working directly on the VFS interface of a [`RamFileSystem`].
+pub struct Shell {
+    fs: RamFileSystem,
+    cwd: String,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Self {
+            fs: RamFileSystem::new(),
+            cwd: String::from("/"),
+        }
+    }
+
+    pub fn print_prompt(&self) {
+        print!("ramfs:{}$ ", self.cwd);
+        io::stdout().flush().unwrap();
+    }
+
+    pub fn run_cmd(&mut self, line: &str) {
+        let (cmd, args) = split_whitespace(line);
+        if !cmd.is_empty() {
+            for (name, func) in CMD_TABLE {
+                if cmd == *name {
+                    func(self, args);
+                    return;
+                }
+            }
+            println!("{}: command not found", cmd);
+        }
+    }
+
+    /// Converts `path` to an absolute, canonical path.
+    fn abs_path(&self, path: &str) -> String {
+        if path.starts_with('/') {
+            canonicalize(path)
+        } else {
+            canonicalize(&format!("{}/{}", self.cwd, path))
+        }
+    }
+
+    fn root(&self) -> VfsNodeRef {
+        self.fs.root_dir()
+    }
+
+    fn lookup(&self, path: &str) -> VfsResult<VfsNodeRef> {
+        self.root().lookup(&self.abs_path(path))
+    }
+}
+
+/// Lists all entries of `dir` through repeated `read_dir` calls, excluding
+/// `.` and `..`.
+fn read_dir_all(dir: &VfsNodeRef) -> VfsResult<Vec<(String, VfsNodeType)>> {
+    let mut entries = Vec::new();
+    let mut dirents: [VfsDirEntry; 8] = core::array::from_fn(|_| VfsDirEntry::default());
+    let mut start_idx = 0;
+    loop {
+        let n = dir.read_dir(start_idx, &mut dirents)?;
+        if n == 0 {
+            return Ok(entries);
+        }
+        for ent in &dirents[..n] {
+            let name = String::from_utf8_lossy(ent.name_as_bytes());
+            if name != "." && name != ".." {
+                entries.push((name.into_owned(), ent.entry_type()));
+            }
+        }
+        start_idx += n;
+    }
+}
+
+fn do_ls(sh: &mut Shell, args: &str) {
+    let args = if args.is_empty() { "." } else { args };
+    let name_count = args.split_whitespace().count();
+
+    fn list_one(sh: &Shell, name: &str, print_name: bool) -> VfsResult {
+        let node = sh.lookup(name)?;
+        let attr = node.get_attr()?;
+        if !attr.is_dir() {
+            println!(
+                "{} {:>2} {:>8} {}",
+                attr.file_type().as_char(),
+                attr.nlink(),
+                attr.size(),
+                name
+            );
+            return Ok(());
+        }
+
+        if print_name {
+            println!("{}:", name);
+        }
+        for (entry, ty) in read_dir_all(&node)? {
+            // 符号链接不跟随, 显示它的目标
+            if ty == VfsNodeType::SymLink {
+                let target = axfs_ramfs::read_link(&node, &entry)?;
+                println!("l {:>2} {:>8} {} -> {}", 1, target.len(), entry, target);
+                continue;
+            }
+            let attr = node.clone().lookup(&entry)?.get_attr()?;
+            println!(
+                "{} {:>2} {:>8} {}",
+                attr.file_type().as_char(),
+                attr.nlink(),
+                attr.size(),
+                entry
+            );
+        }
+        Ok(())
+    }
+
+    for (i, name) in args.split_whitespace().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        if let Err(e) = list_one(sh, name, name_count > 1) {
+            print_err!("ls", name, e);
+        }
+    }
+}
+
+fn do_cat(sh: &mut Shell, args: &str) {
+    if args.is_empty() {
+        print_err!("cat", "no file specified");
+        return;
+    }
+
+    fn cat_one(sh: &Shell, fname: &str) -> VfsResult {
+        let node = sh.lookup(fname)?;
+        let mut buf = [0; 1024];
+        let mut offset = 0;
+        loop {
+            let n = node.read_at(offset, &mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            io::stdout().write_all(&buf[..n]).unwrap();
+            offset += n as u64;
+        }
+    }
+
+    for fname in args.split_whitespace() {
+        if let Err(e) = cat_one(sh, fname) {
+            print_err!("cat", fname, e);
+        }
+    }
+}
+
+fn do_echo(sh: &mut Shell, args: &str) {
+    fn echo_file(sh: &Shell, fname: &str, text: &str) -> VfsResult {
+        let path = sh.abs_path(fname);
+        let node = match sh.root().lookup(&path) {
+            Ok(node) => node,
+            Err(_) => {
+                sh.root().create(&path, VfsNodeType::File)?;
+                sh.root().lookup(&path)?
+            }
+        };
+        node.truncate(0)?;
+        node.write_at(0, text.as_bytes())?;
+        node.write_at(text.len() as u64, b"\n")?;
+        Ok(())
+    }
+
+    if let Some(pos) = args.rfind('>') {
+        let text = args[..pos].trim();
+        let fname = args[pos + 1..].trim();
+        if fname.is_empty() {
+            print_err!("echo", "no file specified");
+            return;
+        }
+        if let Err(e) = echo_file(sh, fname, text) {
+            print_err!("echo", fname, e);
+        }
+    } else {
+        println!("{}", args)
+    }
+}
+
+fn do_mkdir(sh: &mut Shell, args: &str) {
+    if args.is_empty() {
+        print_err!("mkdir", "missing operand");
+        return;
+    }
+
+    for path in args.split_whitespace() {
+        if let Err(e) = sh.root().create(&sh.abs_path(path), VfsNodeType::Dir) {
+            print_err!("mkdir", format_args!("cannot create directory '{path}'"), e);
+        }
+    }
+}
+
+fn do_mv(sh: &mut Shell, args: &str) {
+    let (src, dst) = split_whitespace(args);
+    if src.is_empty() || dst.is_empty() {
+        print_err!("mv", format_args!("args error: [{}]", args));
+        return;
+    }
+
+    let src_path = sh.abs_path(src);
+    let mut dst_path = sh.abs_path(dst);
+    // Moving into an existing directory keeps the file name.
+    if let Ok(node) = sh.root().lookup(&dst_path) {
+        if node.get_attr().is_ok_and(|attr| attr.is_dir()) {
+            let name = src_path.rsplit('/').next().unwrap_or_default();
+            dst_path = format!("{}/{}", dst_path.trim_end_matches('/'), name);
+        }
+    }
+
+    if let Err(e) = sh.root().rename(&src_path, &dst_path) {
+        print_err!("mv", format_args!("cannot move '{src}' to '{dst}'"), e);
+    }
+}
+
+fn do_rm(sh: &mut Shell, args: &str) {
+    if args.is_empty() {
+        print_err!("rm", "missing operand");
+        return;
+    }
+    let recursive = args.split_whitespace().any(|arg| arg == "-r");
+
+    for path in args.split_whitespace() {
+        if path == "-r" {
+            continue;
+        }
+        let abs_path = sh.abs_path(path);
+        if recursive {
+            if let Err(e) = axfs_ramfs::remove_all(&sh.root(), &abs_path) {
+                print_err!("rm", format_args!("cannot remove '{}'", e.path), e.error);
+            }
+        } else if let Err(e) = sh.root().remove(&abs_path) {
+            print_err!("rm", format_args!("cannot remove '{path}'"), e);
+        }
+    }
+}
+
+fn do_ln(sh: &mut Shell, args: &str) {
+    let (opt, rest) = split_whitespace(args);
+    let (symbolic, args) = if opt == "-s" { (true, rest) } else { (false, args) };
+    let (target, link) = split_whitespace(args);
+    if target.is_empty() || link.is_empty() {
+        print_err!("ln", "usage: ln [-s] TARGET LINK_NAME");
+        return;
+    }
+
+    fn symlink(sh: &Shell, target: &str, link: &str) -> VfsResult {
+        let path = sh.abs_path(link);
+        sh.root().create(&path, VfsNodeType::SymLink)?;
+        sh.root().lookup(&path)?.write_at(0, target.as_bytes())?;
+        Ok(())
+    }
+
+    fn hard_link(sh: &Shell, target: &str, link: &str) -> VfsResult {
+        let node = sh.lookup(target)?;
+        let path = sh.abs_path(link);
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+        let dir = sh.lookup(if dir.is_empty() { "/" } else { dir })?;
+        let dir = dir
+            .as_any()
+            .downcast_ref::<DirNode>()
+            .ok_or(VfsError::NotADirectory)?;
+        dir.link(name, node)
+    }
+
+    if symbolic {
+        if let Err(e) = symlink(sh, target, link) {
+            print_err!("ln", format_args!("cannot create symlink '{link}'"), e);
+        }
+    } else if let Err(e) = hard_link(sh, target, link) {
+        print_err!("ln", format_args!("cannot create link '{link}'"), e);
+    }
+}
+
+fn do_df(sh: &mut Shell, _args: &str) {
+    let stats = sh.fs.stats();
+    println!("Filesystem   Inodes  Size(bytes)  Used(bytes) Mounted on");
+    println!(
+        "ramfs      {:>8} {:>12} {:>12} /",
+        stats.used_inodes, stats.file_size, stats.used_bytes
+    );
+}
+
+fn do_cd(sh: &mut Shell, args: &str) {
+    let path = sh.abs_path(if args.is_empty() { "/" } else { args });
+    match sh.root().lookup(&path).and_then(|node| node.get_attr()) {
+        Ok(attr) if attr.is_dir() => sh.cwd = path,
+        Ok(_) => {
+            print_err!("cd", format_args!("{}: not a directory", args));
+        }
+        Err(e) => {
+            print_err!("cd", args, e);
+        }
+    }
+}
+
+fn do_pwd(sh: &mut Shell, _args: &str) {
+    println!("{}", sh.cwd);
+}
+
+fn do_help(_sh: &mut Shell, _args: &str) {
+    println!("Available commands:");
+    for (name, _) in CMD_TABLE {
+        println!("  {}", name);
+    }
+}
+
+fn do_exit(_sh: &mut Shell, _args: &str) {
+    println!("Bye~");
+    std::process::exit(0);
+}
+
+fn split_whitespace(str: &str) -> (&str, &str) {
+    let str = str.trim();
+    str.find(char::is_whitespace)
+        .map_or((str, ""), |n| (&str[..n], str[n + 1..].trim()))
+}
@@ -0,0 +1,93 @@
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+mod cmd;
+
+use cmd::Shell;
+
+/// Commands run at startup, covering every filesystem feature the shell uses.
+const SCRIPT: &[&str] = &[
+    "mkdir /tmp /tmp/dir",
+    "echo hello > /tmp/f1",
+    "cat /tmp/f1",
+    "mv /tmp/f1 /tmp/dir",
+    "ls /tmp/dir",
+    "ln -s /tmp/dir/f1 /tmp/link",
+    "cat /tmp/link",
+    "ln /tmp/dir/f1 /tmp/f2",
+    "ls /tmp",
+    "df",
+    "rm -r /tmp/dir",
+    "ls /tmp",
+    "cat /tmp/f2",
+    "df",
+];
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    let mut shell = Shell::new();
+    for line in SCRIPT {
+        shell.print_prompt();
+        println!("{}", line);
+        shell.run_cmd(line);
+    }
+    println!("\n[Ramfs-Shell]: ok!");
+
+    #[cfg(feature = "interactive")]
+    interactive(&mut shell);
+}
+
+#[cfg(feature = "interactive")]
+fn interactive(shell: &mut Shell) {
+    use std::io::prelude::*;
+
+    const LF: u8 = b'\n';
+    const CR: u8 = b'\r';
+    const DL: u8 = b'\x7f';
+    const BS: u8 = b'\x08';
+    const SPACE: u8 = b' ';
+
+    const MAX_CMD_LEN: usize = 256;
+
+    let mut stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+
+    let mut buf = [0; MAX_CMD_LEN];
+    let mut cursor = 0;
+    shell.run_cmd("help");
+    shell.print_prompt();
+
+    loop {
+        if stdin.read(&mut buf[cursor..cursor + 1]).ok() != Some(1) {
+            continue;
+        }
+        match buf[cursor] {
+            CR | LF => {
+                println!();
+                if cursor > 0 {
+                    let line = unsafe { core::str::from_utf8_unchecked(&buf[..cursor]) };
+                    shell.run_cmd(line);
+                    cursor = 0;
+                }
+                shell.print_prompt();
+            }
+            BS | DL => {
+                if cursor > 0 {
+                    stdout.write_all(&[BS, SPACE, BS]).unwrap();
+                    cursor -= 1;
+                }
+            }
+            0..=31 => {}
+            c => {
+                if cursor < MAX_CMD_LEN - 1 {
+                    stdout.write_all(&[c]).unwrap();
+                    cursor += 1;
+                }
+            }
+        }
+    }
+}
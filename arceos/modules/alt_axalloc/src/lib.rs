@@ -12,16 +12,32 @@ use kspin::SpinNoIrq;
 
 const PAGE_SIZE: usize = 0x1000;
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "trace-ops")] {
+        type MaybeTraced<A> = axlog::TracedAllocator<A>;
+
+        const fn maybe_traced<A>(inner: A) -> MaybeTraced<A> {
+            axlog::TracedAllocator::new(inner)
+        }
+    } else {
+        type MaybeTraced<A> = A;
+
+        const fn maybe_traced<A>(inner: A) -> MaybeTraced<A> {
+            inner
+        }
+    }
+}
+
 /// The global allocator used by ArceOS.
 pub struct GlobalAllocator {
-    inner: SpinNoIrq<EarlyAllocator<PAGE_SIZE>>,
+    inner: SpinNoIrq<MaybeTraced<EarlyAllocator<PAGE_SIZE>>>,
 }
 
 impl GlobalAllocator {
     /// Creates an empty [`GlobalAllocator`].
     pub const fn new() -> Self {
         Self {
-            inner: SpinNoIrq::new(EarlyAllocator::new()),
+            inner: SpinNoIrq::new(maybe_traced(EarlyAllocator::new())),
         }
     }
 
@@ -36,8 +52,8 @@ impl GlobalAllocator {
     }
 
     /// Add the given region to the allocator.
-    pub fn add_memory(&self, _start_vaddr: usize, _size: usize) -> AllocResult {
-        unimplemented!()
+    pub fn add_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        self.inner.lock().add_memory(start_vaddr, size)
     }
 
     /// Allocate arbitrary number of bytes. Returns the left bound of the
@@ -116,6 +132,11 @@ pub fn global_init(start_vaddr: usize, size: usize) {
 }
 
 /// Add the given memory region to the global allocator.
-pub fn global_add_memory(_start_vaddr: usize, _size: usize) -> AllocResult {
-    unimplemented!()
+pub fn global_add_memory(start_vaddr: usize, size: usize) -> AllocResult {
+    debug!(
+        "add a memory region to global allocator: [{:#x}, {:#x})",
+        start_vaddr,
+        start_vaddr + size
+    );
+    GLOBAL_ALLOCATOR.add_memory(start_vaddr, size)
 }
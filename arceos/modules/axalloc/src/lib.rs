@@ -36,6 +36,22 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "trace-ops")] {
+        type MaybeTraced<A> = axlog::TracedAllocator<A>;
+
+        const fn maybe_traced<A>(inner: A) -> MaybeTraced<A> {
+            axlog::TracedAllocator::new(inner)
+        }
+    } else {
+        type MaybeTraced<A> = A;
+
+        const fn maybe_traced<A>(inner: A) -> MaybeTraced<A> {
+            inner
+        }
+    }
+}
+
 /// The global allocator used by ArceOS.
 ///
 /// It combines a [`ByteAllocator`] and a [`PageAllocator`] into a simple
@@ -48,16 +64,16 @@ cfg_if::cfg_if! {
 ///
 /// [`TlsfByteAllocator`]: allocator::TlsfByteAllocator
 pub struct GlobalAllocator {
-    balloc: SpinNoIrq<DefaultByteAllocator>,
-    palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
+    balloc: SpinNoIrq<MaybeTraced<DefaultByteAllocator>>,
+    palloc: SpinNoIrq<MaybeTraced<BitmapPageAllocator<PAGE_SIZE>>>,
 }
 
 impl GlobalAllocator {
     /// Creates an empty [`GlobalAllocator`].
     pub const fn new() -> Self {
         Self {
-            balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
-            palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
+            balloc: SpinNoIrq::new(maybe_traced(DefaultByteAllocator::new())),
+            palloc: SpinNoIrq::new(maybe_traced(BitmapPageAllocator::new())),
         }
     }
 
@@ -15,6 +15,12 @@
 //!    to create and initialize other filesystems. This feature is **disabled** by
 //!    by default, but it will override other filesystem selection features if
 //!    both are enabled.
+//! - `trace-ops`: Log every VFS node operation (arguments, result and
+//!    duration) at the `trace` level through [`axlog`]. This feature is
+//!    **disabled** by default.
+//! - `logfile`: Mount another [`axfs_ramfs::RamFileSystem`] on `/var` and save
+//!    the log records printed to the console in `/var/log/kernel.log`, see
+//!    [`logfile::LogFile`]. This feature is **disabled** by default.
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
@@ -28,8 +34,12 @@ extern crate alloc;
 
 mod dev;
 mod fs;
+#[cfg(feature = "logfile")]
+pub mod logfile;
 mod mounts;
 mod root;
+#[cfg(feature = "trace-ops")]
+mod trace;
 
 pub mod api;
 pub mod fops;
@@ -0,0 +1,254 @@
+//! Log records saved to a file, enabled by the `logfile` feature.
+//!
+//! At boot, a RAM filesystem is mounted on `/var`, and records printed to the
+//! console are appended to `/var/log/kernel.log` by a [`LogFile`], so that
+//! tasks can read them back as a normal file. The records are written to the
+//! file when the log is [flushed](axlog::flush), e.g., by the idle task.
+
+use alloc::format;
+use core::fmt::{self, Write};
+
+use axfs_vfs::{VfsNodeRef, VfsNodeType, VfsResult};
+use axlog::{Level, LogSink};
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+
+/// Path of the directory of the kernel log.
+pub const KERNEL_LOG_DIR: &str = "/var/log";
+
+/// Name of the kernel log file in [`KERNEL_LOG_DIR`].
+pub const KERNEL_LOG_NAME: &str = "kernel.log";
+
+/// Maximum size of the kernel log file before it is rotated.
+const KERNEL_LOG_MAX_SIZE: u64 = 64 * 1024;
+
+/// Number of rotated kernel log files kept.
+const KERNEL_LOG_MAX_FILES: usize = 3;
+
+/// Size of the buffer of the records not written to the file yet.
+pub const LOG_FILE_BUFFER_SIZE: usize = 4096;
+
+/// Size of the chunks in which the buffered records are written.
+const WRITE_CHUNK_SIZE: usize = 256;
+
+/// The records not written to the file yet, without their color escape
+/// sequences.
+struct Pending {
+    buf: [u8; LOG_FILE_BUFFER_SIZE],
+    len: usize,
+    /// Number of records dropped because the buffer was full.
+    dropped: usize,
+}
+
+impl Pending {
+    /// Moves the oldest records to `out`, as many whole records as fit if
+    /// `out` can't hold them all, and returns their length.
+    fn take(&mut self, out: &mut [u8]) -> usize {
+        let mut len = out.len().min(self.len);
+        if len < self.len {
+            if let Some(end) = self.buf[..len].iter().rposition(|&b| b == b'\n') {
+                len = end + 1;
+            }
+        }
+        out[..len].copy_from_slice(&self.buf[..len]);
+        self.buf.copy_within(len..self.len, 0);
+        self.len -= len;
+        len
+    }
+}
+
+struct LogFileInner {
+    /// The current log file, [`None`] if it could not be created.
+    file: Option<VfsNodeRef>,
+    size: u64,
+    /// Number of records lost because the file could not be written.
+    dropped: usize,
+}
+
+/// A [`LogSink`] that appends log records to a file in a directory, with
+/// size-based rotation.
+///
+/// When the file `name` would grow beyond `max_size` bytes, it is renamed to
+/// `name.1`, the former `name.1` to `name.2`, and so on up to `name.N` with
+/// `N = max_files`, and a new empty `name` is created.
+///
+/// Records are logged while the global allocator is locked, so they are only
+/// copied to a buffer of [`LOG_FILE_BUFFER_SIZE`] bytes without allocating,
+/// and written to the file by [`flush`](LogSink::flush). Records that don't
+/// fit in the buffer are dropped. Color escape sequences are dropped from the
+/// records.
+pub struct LogFile {
+    dir: VfsNodeRef,
+    name: &'static str,
+    max_size: u64,
+    max_files: usize,
+    pending: SpinNoIrq<Pending>,
+    inner: SpinNoIrq<LogFileInner>,
+}
+
+impl LogFile {
+    /// Creates a log file named `name` in the directory `dir`, or appends to
+    /// it if it already exists.
+    pub fn new(
+        dir: VfsNodeRef,
+        name: &'static str,
+        max_size: u64,
+        max_files: usize,
+    ) -> VfsResult<Self> {
+        let file = open_or_create(&dir, name)?;
+        let size = file.get_attr()?.size();
+        Ok(Self {
+            dir,
+            name,
+            max_size,
+            max_files,
+            pending: SpinNoIrq::new(Pending {
+                buf: [0; LOG_FILE_BUFFER_SIZE],
+                len: 0,
+                dropped: 0,
+            }),
+            inner: SpinNoIrq::new(LogFileInner {
+                file: Some(file),
+                size,
+                dropped: 0,
+            }),
+        })
+    }
+
+    /// Returns the number of records that could not be written.
+    pub fn dropped_records(&self) -> usize {
+        self.pending.lock().dropped + self.inner.lock().dropped
+    }
+
+    /// Appends `bytes`, made of whole records, to the file, rotating it first
+    /// if it would grow too large.
+    fn write_chunk(&self, inner: &mut LogFileInner, bytes: &[u8]) {
+        if inner.size > 0 && inner.size + bytes.len() as u64 > self.max_size {
+            inner.file = self.rotate().ok();
+            inner.size = 0;
+        }
+        let records = bytes.iter().filter(|&&b| b == b'\n').count();
+        let Some(file) = inner.file.clone() else {
+            inner.dropped += records;
+            return;
+        };
+        match file.write_at(inner.size, bytes) {
+            Ok(n) => inner.size += n as u64,
+            Err(_) => inner.dropped += records,
+        }
+    }
+
+    /// Shifts `name.i` to `name.{i+1}`, dropping the oldest file, then moves
+    /// the current file to `name.1` and creates a new one.
+    fn rotate(&self) -> VfsResult<VfsNodeRef> {
+        if self.max_files > 0 {
+            let oldest = format!("{}.{}", self.name, self.max_files);
+            if self.dir.clone().lookup(&oldest).is_ok() {
+                self.dir.remove(&oldest)?;
+            }
+            for i in (1..self.max_files).rev() {
+                let src = format!("{}.{}", self.name, i);
+                if self.dir.clone().lookup(&src).is_ok() {
+                    self.dir.rename(&src, &format!("{}.{}", self.name, i + 1))?;
+                }
+            }
+            self.dir.rename(self.name, &format!("{}.1", self.name))?;
+        } else {
+            self.dir.remove(self.name)?;
+        }
+        open_or_create(&self.dir, self.name)
+    }
+}
+
+fn open_or_create(dir: &VfsNodeRef, name: &str) -> VfsResult<VfsNodeRef> {
+    match dir.clone().lookup(name) {
+        Ok(file) => Ok(file),
+        Err(_) => {
+            dir.create(name, VfsNodeType::File)?;
+            dir.clone().lookup(name)
+        }
+    }
+}
+
+/// Appends a record to the buffer without its color escape sequences, or
+/// fails if it does not fit.
+struct PlainText<'a> {
+    pending: &'a mut Pending,
+    len: usize,
+    in_escape: bool,
+}
+
+impl Write for PlainText<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // 转义序列只由 ASCII 字符组成，可以逐字节处理
+        for &b in s.as_bytes() {
+            if self.in_escape {
+                self.in_escape = b == b'[' || !(b'@'..=b'~').contains(&b);
+            } else if b == 0x1B {
+                self.in_escape = true;
+            } else {
+                *self.pending.buf.get_mut(self.len).ok_or(fmt::Error)? = b;
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LogSink for LogFile {
+    fn write_record(&self, _level: Level, record: fmt::Arguments) {
+        let mut pending = self.pending.lock();
+        let len = pending.len;
+        let mut w = PlainText {
+            pending: &mut pending,
+            len,
+            in_escape: false,
+        };
+        let res = w.write_fmt(record);
+        let end = w.len;
+        match res {
+            Ok(()) => pending.len = end,
+            Err(_) => pending.dropped += 1,
+        }
+    }
+
+    fn flush(&self) {
+        // Another CPU is writing the file.
+        let Some(mut inner) = self.inner.try_lock() else {
+            return;
+        };
+        // Records logged while writing, e.g., by the filesystem itself, are
+        // left to the next flush.
+        let mut remaining = self.pending.lock().len;
+        let mut chunk = [0; WRITE_CHUNK_SIZE];
+        while remaining > 0 {
+            let max = remaining.min(WRITE_CHUNK_SIZE);
+            let len = self.pending.lock().take(&mut chunk[..max]);
+            remaining -= len;
+            self.write_chunk(&mut inner, &chunk[..len]);
+        }
+    }
+}
+
+static KERNEL_LOG: LazyInit<LogFile> = LazyInit::new();
+
+/// Creates `/var/log/kernel.log` and starts saving log records in it.
+pub(crate) fn init_kernel_log(root: VfsNodeRef) {
+    let res = root.lookup(KERNEL_LOG_DIR).and_then(|dir| {
+        LogFile::new(
+            dir,
+            KERNEL_LOG_NAME,
+            KERNEL_LOG_MAX_SIZE,
+            KERNEL_LOG_MAX_FILES,
+        )
+    });
+    match res {
+        Ok(file) => {
+            KERNEL_LOG.init_once(file);
+            if axlog::register_sink(&*KERNEL_LOG).is_err() {
+                warn!("failed to register the kernel log file: too many log sinks");
+            }
+        }
+        Err(e) => warn!("failed to create the kernel log file: {:?}", e),
+    }
+}
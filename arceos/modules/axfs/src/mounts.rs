@@ -78,3 +78,14 @@ pub(crate) fn sysfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
 
     Ok(Arc::new(sysfs))
 }
+
+#[cfg(feature = "logfile")]
+pub(crate) fn varfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
+    let varfs = fs::ramfs::RamFileSystem::new();
+    let var_root = varfs.root_dir();
+
+    // Create /var/log for the kernel log
+    var_root.create("log", VfsNodeType::Dir)?;
+
+    Ok(Arc::new(varfs))
+}
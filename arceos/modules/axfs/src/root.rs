@@ -55,6 +55,8 @@ impl RootDirectory {
         if self.mounts.iter().any(|mp| mp.path == path) {
             return ax_err!(InvalidInput, "mount point already exists");
         }
+        #[cfg(feature = "trace-ops")]
+        let fs = crate::trace::TracedFs::wrap(fs);
         // create the mount point in the main filesystem if it does not exist
         self.main_fs.root_dir().create(path, FileType::Dir)?;
         fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
@@ -135,10 +137,16 @@ impl VfsNodeOps for RootDirectory {
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
         self.lookup_mounted_fs(src_path, |fs, rest_path| {
             if rest_path.is_empty() {
-                ax_err!(PermissionDenied) // cannot rename mount points
-            } else {
-                fs.root_dir().rename(rest_path, dst_path)
+                return ax_err!(PermissionDenied); // cannot rename mount points
             }
+            // both paths are given relative to the root of the filesystem
+            self.lookup_mounted_fs(dst_path, |dst_fs, dst_rest_path| {
+                if !Arc::ptr_eq(&fs, &dst_fs) {
+                    ax_err!(Unsupported) // cannot move across filesystems
+                } else {
+                    fs.root_dir().rename(rest_path, dst_rest_path)
+                }
+            })
         })
     }
 }
@@ -155,6 +163,8 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
         }
     }
 
+    #[cfg(feature = "trace-ops")]
+    let main_fs = crate::trace::TracedFs::wrap(main_fs);
     let mut root_dir = RootDirectory::new(main_fs);
 
     #[cfg(feature = "devfs")]
@@ -179,9 +189,18 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
         .mount("/sys", mounts::sysfs().unwrap())
         .expect("fail to mount sysfs at /sys");
 
+    // Mount another ramfs for the kernel log
+    #[cfg(feature = "logfile")]
+    root_dir // should not fail
+        .mount("/var", mounts::varfs().unwrap())
+        .expect("fail to mount varfs at /var");
+
     ROOT_DIR.init_once(Arc::new(root_dir));
     CURRENT_DIR.init_once(Mutex::new(ROOT_DIR.clone()));
     *CURRENT_DIR_PATH.lock() = "/".into();
+
+    #[cfg(feature = "logfile")]
+    crate::logfile::init_kernel_log(ROOT_DIR.clone());
 }
 
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
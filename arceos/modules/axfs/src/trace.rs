@@ -0,0 +1,155 @@
+//! Call tracing of VFS operations, enabled by the `trace-ops` feature.
+//!
+//! Every filesystem mounted in the root directory is wrapped in a
+//! [`TracedFs`], whose nodes are in turn wrapped in [`TracedNode`]s, so that
+//! each [`VfsNodeOps`] call runs in a `trace` level [`axlog::span!`], logged
+//! with its arguments, result and duration.
+
+use alloc::sync::Arc;
+use axfs_vfs::{
+    FileSystemInfo, VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps,
+    VfsResult,
+};
+use axlog::trace_op;
+
+/// A filesystem whose nodes are traced.
+pub(crate) struct TracedFs(Arc<dyn VfsOps>);
+
+/// A node whose operations are traced.
+pub(crate) struct TracedNode(VfsNodeRef);
+
+impl TracedFs {
+    pub fn wrap(fs: Arc<dyn VfsOps>) -> Arc<dyn VfsOps> {
+        Arc::new(Self(fs))
+    }
+}
+
+impl TracedNode {
+    fn wrap(node: VfsNodeRef) -> VfsNodeRef {
+        Arc::new(Self(node))
+    }
+}
+
+/// Strips a result down to its outcome, for values that are not
+/// [`Debug`](core::fmt::Debug).
+fn outcome<T>(res: &VfsResult<T>) -> VfsResult<()> {
+    res.as_ref().map(|_| ()).map_err(|e| *e)
+}
+
+impl VfsOps for TracedFs {
+    fn mount(&self, path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        trace_op!("mount", || self.0.mount(path, mount_point), "{:?}", path)
+    }
+
+    fn umount(&self) -> VfsResult {
+        trace_op!("umount", || self.0.umount())
+    }
+
+    fn format(&self) -> VfsResult {
+        trace_op!("format", || self.0.format())
+    }
+
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        let span = axlog::span!(axlog::Level::Trace, "statfs");
+        let ret = self.0.statfs();
+        span.exit_with(format_args!("() -> {:?}", outcome(&ret)));
+        ret
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        TracedNode::wrap(self.0.root_dir())
+    }
+}
+
+impl VfsNodeOps for TracedNode {
+    fn open(&self) -> VfsResult {
+        trace_op!("open", || self.0.open())
+    }
+
+    fn release(&self) -> VfsResult {
+        trace_op!("release", || self.0.release())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        trace_op!("get_attr", || self.0.get_attr())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let len = buf.len();
+        trace_op!(
+            "read_at",
+            || self.0.read_at(offset, buf),
+            "offset={}, len={}",
+            offset,
+            len
+        )
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        trace_op!(
+            "write_at",
+            || self.0.write_at(offset, buf),
+            "offset={}, len={}",
+            offset,
+            buf.len()
+        )
+    }
+
+    fn fsync(&self) -> VfsResult {
+        trace_op!("fsync", || self.0.fsync())
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        trace_op!("truncate", || self.0.truncate(size), "size={}", size)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        let parent = self.0.parent();
+        axlog::trace!(
+            "parent() -> {}",
+            if parent.is_some() { "Some" } else { "None" }
+        );
+        parent.map(TracedNode::wrap)
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let span = axlog::span!(axlog::Level::Trace, "lookup");
+        let ret = self.0.clone().lookup(path);
+        span.exit_with(format_args!("({:?}) -> {:?}", path, outcome(&ret)));
+        ret.map(TracedNode::wrap)
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        trace_op!("create", || self.0.create(path, ty), "{:?}, {:?}", path, ty)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        trace_op!("remove", || self.0.remove(path), "{:?}", path)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let len = dirents.len();
+        trace_op!(
+            "read_dir",
+            || self.0.read_dir(start_idx, dirents),
+            "start_idx={}, len={}",
+            start_idx,
+            len
+        )
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        trace_op!(
+            "rename",
+            || self.0.rename(src_path, dst_path),
+            "{:?}, {:?}",
+            src_path,
+            dst_path
+        )
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        // Keep downcasting to the concrete node type working.
+        self.0.as_any()
+    }
+}
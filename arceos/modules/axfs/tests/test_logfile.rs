@@ -0,0 +1,108 @@
+#![cfg(feature = "logfile")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::OnceLock;
+
+use axfs::logfile::{LogFile, LOG_FILE_BUFFER_SIZE};
+use axfs_ramfs::RamFileSystem;
+use axfs_vfs::{VfsNodeRef, VfsOps};
+use axlog::{Level, LogSink};
+
+static LOG: OnceLock<LogFile> = OnceLock::new();
+
+thread_local! {
+    /// Whether the allocations of this thread are logged to [`LOG`].
+    static LOGGING: Cell<bool> = const { Cell::new(false) };
+    /// Whether the allocator of this thread is "locked", while logging.
+    static LOCKED: Cell<bool> = const { Cell::new(false) };
+    /// Number of allocations made while the allocator was locked.
+    static REENTERED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Logs every allocation while it is locked, like the global allocator of
+/// ArceOS with `LOG=debug`.
+struct LoggingAllocator;
+
+unsafe impl GlobalAlloc for LoggingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if LOCKED.get() {
+            REENTERED.set(REENTERED.get() + 1);
+        } else if LOGGING.get() {
+            if let Some(log) = LOG.get() {
+                LOCKED.set(true);
+                log.write_record(Level::Debug, format_args!("alloc {:?}\n", layout));
+                LOCKED.set(false);
+            }
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: LoggingAllocator = LoggingAllocator;
+
+fn read_file(dir: &VfsNodeRef, name: &str) -> String {
+    let file = dir.clone().lookup(name).unwrap();
+    let mut buf = vec![0; file.get_attr().unwrap().size() as usize];
+    file.read_at(0, &mut buf).unwrap();
+    String::from_utf8(buf).unwrap()
+}
+
+#[test]
+fn test_log_from_allocator() {
+    let fs = RamFileSystem::new();
+    let log = LOG.get_or_init(|| LogFile::new(fs.root_dir(), "kernel.log", 1 << 20, 1).unwrap());
+
+    LOGGING.set(true);
+    let boxes: Vec<Box<[u8]>> = (1..=8).map(|len| vec![0; len].into_boxed_slice()).collect();
+    LOGGING.set(false);
+    assert_eq!(REENTERED.get(), 0);
+
+    log.flush();
+    let text = read_file(&fs.root_dir(), "kernel.log");
+    for b in &boxes {
+        let layout = Layout::for_value(&**b);
+        assert!(text.contains(&format!("alloc {:?}\n", layout)));
+    }
+    assert_eq!(log.dropped_records(), 0);
+}
+
+#[test]
+fn test_log_buffer_full() {
+    let fs = RamFileSystem::new();
+    let dir = fs.root_dir();
+    let log = LogFile::new(dir.clone(), "kernel.log", 1024, 2).unwrap();
+
+    // 10 bytes per record, without the color.
+    let count = LOG_FILE_BUFFER_SIZE / 10 + 5;
+    for i in 0..count {
+        log.write_record(
+            Level::Info,
+            format_args!("\u{1B}[32mrec {:05}\u{1B}[m\n", i),
+        );
+    }
+    assert_eq!(log.dropped_records(), 5);
+    log.flush();
+
+    // The records are written whole, and the files rotated.
+    let text = read_file(&dir, "kernel.log");
+    assert!(text.len() <= 1024);
+    assert!(text.ends_with(&format!("rec {:05}\n", count - 6)));
+    for name in ["kernel.log.1", "kernel.log.2"] {
+        let text = read_file(&dir, name);
+        assert!(text.len() <= 1024 && text.ends_with('\n'));
+        assert!(text.lines().all(|l| l.len() == 9 && l.starts_with("rec ")));
+    }
+    assert!(dir.clone().lookup("kernel.log.3").is_err());
+
+    // The buffer is empty again.
+    log.write_record(Level::Info, format_args!("last\n"));
+    log.flush();
+    assert!(read_file(&dir, "kernel.log").ends_with("last\n"));
+    assert_eq!(log.dropped_records(), 5);
+}
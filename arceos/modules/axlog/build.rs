@@ -0,0 +1,56 @@
+use std::io::{Result, Write};
+use std::path::PathBuf;
+
+/// Environment variable with the per-module static maximum levels, e.g.,
+/// `axfs*=trace,axtask=debug,warn`.
+const STATIC_LEVELS_VAR: &str = "AX_LOG_STATIC";
+
+const LEVELS: &[(&str, &str)] = &[
+    ("off", "Off"),
+    ("error", "Error"),
+    ("warn", "Warn"),
+    ("info", "Info"),
+    ("debug", "Debug"),
+    ("trace", "Trace"),
+];
+
+fn parse_level(level: &str) -> &'static str {
+    LEVELS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(level.trim()))
+        .map(|(_, variant)| *variant)
+        .unwrap_or_else(|| panic!("{STATIC_LEVELS_VAR}: invalid log level {level:?}"))
+}
+
+fn gen_static_levels(spec: &str, out: &mut impl Write) -> Result<()> {
+    let mut default = "Trace";
+    let mut patterns = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=') {
+            Some((pattern, level)) => patterns.push((pattern.trim(), parse_level(level))),
+            None => default = parse_level(entry),
+        }
+    }
+
+    writeln!(
+        out,
+        "// Generated by build.rs from ${STATIC_LEVELS_VAR}={spec:?}."
+    )?;
+    writeln!(out, "const STATIC_LEVELS: &[(&str, LevelFilter)] = &[")?;
+    for (pattern, level) in patterns {
+        writeln!(out, "    ({pattern:?}, LevelFilter::{level}),")?;
+    }
+    writeln!(out, "];")?;
+    writeln!(
+        out,
+        "const STATIC_DEFAULT_LEVEL: LevelFilter = LevelFilter::{default};"
+    )?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    println!("cargo:rerun-if-env-changed={STATIC_LEVELS_VAR}");
+    let spec = std::env::var(STATIC_LEVELS_VAR).unwrap_or_default();
+    let out_path = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("static_levels.rs");
+    gen_static_levels(&spec, &mut std::fs::File::create(out_path)?)
+}
@@ -0,0 +1,243 @@
+//! Compact binary encoding of log records, for consoles too slow for text.
+//!
+//! Every frame starts with a tag byte, followed by fields encoded as unsigned
+//! LEB128 varints:
+//!
+//! - [`TAG_DEFINE`]: `id`, `len`, then `len` bytes of UTF-8 text. Defines the
+//!   string referenced by `id` in the following records.
+//! - [`TAG_RECORD`]: `level` (1 for `error` to 5 for `trace`), `time` in
+//!   microseconds, `cpu_id + 1`, `tid + 1`, `line + 1` (0 if unknown), then
+//!   the target and the message as string references.
+//!
+//! A string reference is either `id << 1` for a string defined earlier, or
+//! `len << 1 | 1` followed by `len` bytes of UTF-8 text.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kspin::SpinNoIrq;
+use log::Record;
+
+use crate::RecordInfo;
+
+/// Tag of a frame defining an interned string.
+pub const TAG_DEFINE: u8 = 0xA0;
+
+/// Tag of a frame holding a log record.
+pub const TAG_RECORD: u8 = 0xA1;
+
+/// Maximum length of an encoded record, longer messages are truncated.
+pub const MAX_BINARY_RECORD_LEN: usize = 256;
+
+/// Maximum number of interned strings, the others are sent inline.
+const MAX_INTERNED: usize = 256;
+
+/// A destination of binary log records, such as a raw UART.
+///
+/// See [`set_binary_sink`].
+pub trait BinarySink: Send + Sync {
+    /// Writes one or more encoded frames.
+    ///
+    /// This is called with the logger locked, so it must not log.
+    fn write_bytes(&self, bytes: &[u8]);
+}
+
+struct Interner {
+    sink: Option<&'static dyn BinarySink>,
+    hashes: [u64; MAX_INTERNED],
+    len: usize,
+}
+
+static INTERNER: SpinNoIrq<Interner> = SpinNoIrq::new(Interner {
+    sink: None,
+    hashes: [0; MAX_INTERNED],
+    len: 0,
+});
+
+/// Whether a binary sink is set, to skip locking the interner otherwise.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Sets the sink of binary log records, or goes back to text with [`None`].
+///
+/// While a binary sink is set, records printed to the console are encoded in
+/// a compact binary format and written to the sink instead. Targets and
+/// constant messages are sent once, then referenced by ID; other messages are
+/// sent as their formatted text, without the record header. The `std` feature
+/// provides a [`BinaryDecoder`](crate::BinaryDecoder) to turn them back into
+/// text on the host.
+///
+/// Registered [`LogSink`](crate::LogSink)s and the ring buffer still receive
+/// text records.
+///
+/// Setting a sink starts a new stream: strings are defined again before being
+/// referenced, so that a new decoder can follow it.
+pub fn set_binary_sink(sink: Option<&'static dyn BinarySink>) {
+    let mut interner = INTERNER.lock();
+    interner.sink = sink;
+    interner.len = 0;
+    ENABLED.store(sink.is_some(), Ordering::Relaxed);
+}
+
+/// A fixed buffer of encoded frames.
+struct FrameBuf {
+    buf: [u8; MAX_BINARY_RECORD_LEN],
+    len: usize,
+}
+
+impl FrameBuf {
+    const fn new() -> Self {
+        Self {
+            buf: [0; MAX_BINARY_RECORD_LEN],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < MAX_BINARY_RECORD_LEN {
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn push_varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.push(byte);
+                return;
+            }
+            self.push(byte | 0x80);
+        }
+    }
+
+    /// Pushes `s` inline as a string reference, truncated to the space left.
+    fn push_inline(&mut self, s: &str) {
+        // Keep room for the longest varint length.
+        let mut len = s
+            .len()
+            .min(MAX_BINARY_RECORD_LEN.saturating_sub(self.len + 10));
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.push_varint((len as u64) << 1 | 1);
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+    }
+}
+
+/// Formats a message inline, after its length is known.
+struct InlineWriter<'a> {
+    text: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for InlineWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut n = s.len().min(self.text.len() - self.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.text[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// FNV-1a hash of an interned string.
+fn str_hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+impl Interner {
+    /// Returns the ID of `s`, defining it in `defs` if it is new, or [`None`]
+    /// if the table is full.
+    fn intern(&mut self, s: &str, defs: &mut FrameBuf) -> Option<usize> {
+        let hash = str_hash(s);
+        if let Some(id) = self.hashes[..self.len].iter().position(|&h| h == hash) {
+            return Some(id);
+        }
+        // The definition must fit entirely, or the record would refer to it
+        // in vain.
+        if self.len == MAX_INTERNED || defs.len + s.len() + 12 > MAX_BINARY_RECORD_LEN {
+            return None;
+        }
+        let id = self.len;
+        self.hashes[id] = hash;
+        self.len += 1;
+        defs.push(TAG_DEFINE);
+        defs.push_varint(id as u64);
+        defs.push_varint(s.len() as u64);
+        defs.buf[defs.len..defs.len + s.len()].copy_from_slice(s.as_bytes());
+        defs.len += s.len();
+        Some(id)
+    }
+}
+
+/// Encodes a record to the binary sink.
+///
+/// Returns `false` if no binary sink is set, then the record is left to the
+/// text console.
+pub(crate) fn write_record(info: &RecordInfo) -> bool {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return false;
+    }
+    let record: &Record = info.record;
+    let mut defs = FrameBuf::new();
+    let mut frame = FrameBuf::new();
+
+    let mut interner = INTERNER.lock();
+    let Some(sink) = interner.sink else {
+        return false;
+    };
+    let target = interner.intern(record.target(), &mut defs);
+    let message = record
+        .args()
+        .as_str()
+        .and_then(|s| interner.intern(s, &mut defs));
+
+    frame.push(TAG_RECORD);
+    frame.push(record.level() as u8);
+    frame.push_varint(info.time.as_micros() as u64);
+    frame.push_varint(info.cpu_id.map_or(0, |id| id as u64 + 1));
+    frame.push_varint(info.tid.map_or(0, |id| id + 1));
+    frame.push_varint(record.line().map_or(0, |line| line as u64 + 1));
+    match target {
+        Some(id) => frame.push_varint((id as u64) << 1),
+        None => frame.push_inline(record.target()),
+    }
+    match (message, record.args().as_str()) {
+        (Some(id), _) => frame.push_varint((id as u64) << 1),
+        (None, Some(s)) => frame.push_inline(s),
+        (None, None) => {
+            // The length goes before the text, so format it into the room
+            // left for the text, then move it right after the length.
+            let room = MAX_BINARY_RECORD_LEN - frame.len;
+            let mut text = [0; MAX_BINARY_RECORD_LEN];
+            let mut w = InlineWriter {
+                text: &mut text[..room.saturating_sub(10)],
+                len: 0,
+            };
+            // `InlineWriter` never fails, it truncates instead.
+            let _ = w.write_fmt(*record.args());
+            let len = w.len;
+            frame.push_varint((len as u64) << 1 | 1);
+            frame.buf[frame.len..frame.len + len].copy_from_slice(&text[..len]);
+            frame.len += len;
+        }
+    }
+
+    // Frames are written with the interner locked, so that definitions reach
+    // the sink before the records referring to them.
+    if defs.len > 0 {
+        sink.write_bytes(defs.as_bytes());
+    }
+    sink.write_bytes(frame.as_bytes());
+    true
+}
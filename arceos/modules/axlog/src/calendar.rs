@@ -0,0 +1,55 @@
+//! Calendar formatting of wall-clock time, without depending on `chrono`.
+
+use core::fmt::{self, Display};
+use core::time::Duration;
+
+/// Displays a time since the UNIX epoch as a UTC date and time, in the
+/// format `2025-06-01 12:00:00.123456`.
+///
+/// # Examples
+///
+/// ```
+/// use core::time::Duration;
+/// use axlog::CalendarTime;
+///
+/// let t = CalendarTime(Duration::new(1_748_779_200, 123_456_000));
+/// assert_eq!(t.to_string(), "2025-06-01 12:00:00.123456");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CalendarTime(pub Duration);
+
+/// Converts a number of days since 1970-01-01 to a (year, month, day) date in
+/// the proleptic Gregorian calendar.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: u64) -> (u64, u32, u32) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097; // [0, 146096]
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365; // [0, 399]
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // [0, 365]
+    let mp = (5 * doy + 2) / 153; // [0, 11], starting from March
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+impl Display for CalendarTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        let (year, month, day) = civil_from_days(secs / 86_400);
+        let secs_of_day = secs % 86_400;
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60,
+            self.0.subsec_micros(),
+        )
+    }
+}
@@ -0,0 +1,163 @@
+//! Configuration of logging from the kernel command line.
+
+use core::fmt;
+use core::str::FromStr;
+
+use log::LevelFilter;
+
+use crate::{OutputFormat, Theme};
+
+/// The error returned by [`configure_from_cmdline`], with the first invalid
+/// logging option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CmdlineError<'a> {
+    /// The invalid option, as `key=value`.
+    pub option: &'a str,
+}
+
+impl fmt::Display for CmdlineError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid logging option `{}`", self.option)
+    }
+}
+
+/// Configures logging from a kernel command line.
+///
+/// The following whitespace-separated options are understood. Other options
+/// are ignored, unless they start with `log.`:
+///
+/// - `loglevel=<level>` or `log.level=<level>`: the maximum log level, see
+///   [`set_max_level`](crate::set_max_level).
+/// - `log.filter=<pattern>=<level>[,<pattern>=<level>...]`: per-target
+///   levels, see [`set_target_level`](crate::set_target_level).
+/// - `log.color=on|off|rainbow`: the color [`Theme`].
+/// - `log.format=human|json`: the [`OutputFormat`].
+/// - `log.ring=<level>`: the level of the ring buffer, see
+///   [`set_ring_buffer_level`](crate::set_ring_buffer_level).
+/// - `log.deferred=on|off`: deferred console output, see
+///   [`set_deferred`](crate::set_deferred).
+/// - `log.max_len=<bytes>`: the maximum length of a message, see
+///   [`set_max_record_len`](crate::set_max_record_len).
+///
+/// Invalid options do not stop the following ones from being applied, the
+/// first of them is returned as an error.
+///
+/// # Examples
+///
+/// ```
+/// axlog::configure_from_cmdline(
+///     "console=ttyS0 loglevel=debug log.color=off log.filter=axtask=trace,axnet=warn",
+/// )
+/// .unwrap();
+/// assert_eq!(axlog::max_level(), axlog::LevelFilter::Debug);
+/// ```
+pub fn configure_from_cmdline(cmdline: &str) -> Result<(), CmdlineError<'_>> {
+    let mut res = Ok(());
+    for option in cmdline.split_whitespace() {
+        let Some((key, value)) = option.split_once('=') else {
+            continue;
+        };
+        let applied = match key {
+            "loglevel" | "log.level" => crate::set_max_level(value).is_ok(),
+            "log.filter" => value.split(',').fold(true, |ok, filter| {
+                let applied = filter.split_once('=').is_some_and(|(pattern, level)| {
+                    LevelFilter::from_str(level)
+                        .is_ok_and(|level| crate::set_target_level(pattern, level).is_ok())
+                });
+                ok && applied
+            }),
+            "log.color" => match value {
+                "on" => Some(Theme::LevelColored),
+                "off" => Some(Theme::Plain),
+                "rainbow" => Some(Theme::Rainbow),
+                _ => None,
+            }
+            .map(crate::set_theme)
+            .is_some(),
+            "log.format" => match value {
+                "human" => Some(OutputFormat::Human),
+                "json" => Some(OutputFormat::Json),
+                _ => None,
+            }
+            .map(crate::set_output_format)
+            .is_some(),
+            "log.ring" => LevelFilter::from_str(value)
+                .map(crate::set_ring_buffer_level)
+                .is_ok(),
+            "log.deferred" => match value {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            }
+            .map(crate::set_deferred)
+            .is_some(),
+            "log.max_len" => value.parse().map(crate::set_max_record_len).is_ok(),
+            _ => !key.starts_with("log."),
+        };
+        if !applied && res.is_ok() {
+            res = Err(CmdlineError { option });
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::target_level;
+
+    #[test]
+    fn options() {
+        let _config = crate::lock_config();
+        configure_from_cmdline(
+            "console=ttyS0 quiet loglevel=debug log.filter=axtask=trace,axnet*=warn \
+             log.ring=info log.max_len=100",
+        )
+        .unwrap();
+        assert_eq!(crate::max_level(), LevelFilter::Debug);
+        assert_eq!(target_level("axtask::run_queue"), Some(LevelFilter::Trace));
+        assert_eq!(target_level("axnet_smoltcp"), Some(LevelFilter::Warn));
+        assert_eq!(crate::ring::ring_buffer_level(), LevelFilter::Info);
+        assert_eq!(crate::max_record_len(), 100);
+
+        let cmdline = format!("log.level=error log.ring=off log.max_len={}", usize::MAX);
+        configure_from_cmdline(&cmdline).unwrap();
+        assert_eq!(crate::max_level(), LevelFilter::Error);
+        assert_eq!(crate::ring::ring_buffer_level(), LevelFilter::Off);
+        assert_eq!(crate::max_record_len(), usize::MAX);
+        crate::clear_target_levels();
+    }
+
+    #[test]
+    fn invalid_options() {
+        let _config = crate::lock_config();
+        crate::set_max_level_filter(LevelFilter::Warn);
+
+        // The first invalid option is returned, the valid ones are applied.
+        assert_eq!(
+            configure_from_cmdline("log.color=blue loglevel=info log.level=loud"),
+            Err(CmdlineError {
+                option: "log.color=blue"
+            })
+        );
+        assert_eq!(crate::max_level(), LevelFilter::Info);
+
+        let err = configure_from_cmdline("log.filter=axfs=trace,axnet,axmm=loud").unwrap_err();
+        assert_eq!(err.option, "log.filter=axfs=trace,axnet,axmm=loud");
+        assert_eq!(target_level("axfs"), Some(LevelFilter::Trace));
+        assert_eq!(target_level("axmm"), None);
+
+        for option in [
+            "log.unknown=1",
+            "log.format=xml",
+            "log.deferred=yes",
+            "log.max_len=-1",
+        ] {
+            assert_eq!(configure_from_cmdline(option), Err(CmdlineError { option }));
+        }
+        // Options without `=` are ignored.
+        configure_from_cmdline("log.level").unwrap();
+        crate::clear_target_levels();
+        crate::set_max_level_filter(LevelFilter::Warn);
+    }
+}
@@ -0,0 +1,514 @@
+//! Lock-free console output.
+//!
+//! Writers (producers) append their output to a bounded multi-producer
+//! single-consumer queue of fixed-size chunks, without waiting for each other.
+//! Whoever finds the console idle after writing becomes the consumer and
+//! drains the queue to the console, until it is empty.
+//!
+//! When the queue is full and the console is busy, output is dropped and
+//! counted, see [`console_dropped_bytes`]. This is only done for log records:
+//! other output waits for the console to be idle and writes to it directly,
+//! see [`write_fmt_blocking`].
+//!
+//! Output reaches [`LogIf::console_write_str`](crate::LogIf::console_write_str)
+//! through a line buffer, one complete line per call as long as it fits.
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::theme::RainbowWriter;
+
+/// Size in bytes of a queue chunk. Writes longer than this may be interleaved
+/// with other writes at chunk boundaries.
+const CHUNK_SIZE: usize = 128;
+
+/// Number of chunks in the queue.
+const NUM_CHUNKS: usize = 64;
+
+/// Size in bytes of the line buffer. Longer lines are written to the console
+/// in several calls.
+const LINE_BUFFER_SIZE: usize = 1024;
+
+struct Chunk {
+    /// Equals the queue position the chunk is free for, plus 1 once it is
+    /// filled. See <https://www.1024cores.net/home/lock-free-algorithms/queues/bounded-mpmc-queue>.
+    seq: AtomicUsize,
+    len: UnsafeCell<usize>,
+    data: UnsafeCell<[u8; CHUNK_SIZE]>,
+}
+
+struct ChunkQueue {
+    chunks: [Chunk; NUM_CHUNKS],
+    enqueue_pos: AtomicUsize,
+    /// Only updated by the consumer.
+    dequeue_pos: AtomicUsize,
+}
+
+// SAFETY: the contents of a chunk are only accessed by the producer that
+// reserved it until it is published through `seq`, then by the consumer.
+unsafe impl Sync for ChunkQueue {}
+
+impl ChunkQueue {
+    const fn new() -> Self {
+        let mut chunks = [const {
+            Chunk {
+                seq: AtomicUsize::new(0),
+                len: UnsafeCell::new(0),
+                data: UnsafeCell::new([0; CHUNK_SIZE]),
+            }
+        }; NUM_CHUNKS];
+        let mut i = 0;
+        while i < NUM_CHUNKS {
+            chunks[i].seq = AtomicUsize::new(i);
+            i += 1;
+        }
+        Self {
+            chunks,
+            enqueue_pos: AtomicUsize::new(0),
+            dequeue_pos: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends a chunk to the queue, returns `false` if the queue is full.
+    fn push(&self, data: &[u8]) -> bool {
+        let mut pos = self.enqueue_pos.load(Ordering::Relaxed);
+        loop {
+            let chunk = &self.chunks[pos % NUM_CHUNKS];
+            let seq = chunk.seq.load(Ordering::Acquire);
+            match (seq as isize).wrapping_sub(pos as isize) {
+                0 => match self.enqueue_pos.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: the chunk at `pos` is reserved by us.
+                        unsafe {
+                            (&mut *chunk.data.get())[..data.len()].copy_from_slice(data);
+                            *chunk.len.get() = data.len();
+                        }
+                        chunk.seq.store(pos + 1, Ordering::Release);
+                        return true;
+                    }
+                    Err(cur) => pos = cur,
+                },
+                diff if diff < 0 => return false,
+                _ => pos = self.enqueue_pos.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Removes the oldest chunk and passes it to `f`.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by the consumer.
+    unsafe fn pop(&self, f: impl FnOnce(&[u8])) -> bool {
+        let pos = self.dequeue_pos.load(Ordering::Relaxed);
+        let chunk = &self.chunks[pos % NUM_CHUNKS];
+        if chunk.seq.load(Ordering::Acquire) != pos + 1 {
+            return false;
+        }
+        f(&(&*chunk.data.get())[..*chunk.len.get()]);
+        chunk.seq.store(pos + NUM_CHUNKS, Ordering::Release);
+        self.dequeue_pos.store(pos + 1, Ordering::Relaxed);
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        let pos = self.dequeue_pos.load(Ordering::Relaxed);
+        self.chunks[pos % NUM_CHUNKS].seq.load(Ordering::Acquire) != pos + 1
+    }
+}
+
+/// Value of [`Console::drainer`] while no one drains the queue.
+const IDLE: usize = usize::MAX;
+
+/// Value of [`Console::drainer`] while the queue is drained on a CPU whose ID
+/// is not known.
+const UNKNOWN_CPU: usize = usize::MAX - 1;
+
+/// Identifies the current CPU, or thread in the `std` environment, as the
+/// one draining the queue.
+fn current_drainer() -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "std")] {
+            std::thread_local! {
+                static ID: u8 = const { 0 };
+            }
+            ID.with(|id| id as *const u8 as usize)
+        } else {
+            crate::cpu_and_task_id().0.unwrap_or(UNKNOWN_CPU)
+        }
+    }
+}
+
+struct Console {
+    queue: ChunkQueue,
+    /// The CPU draining the queue, see [`current_drainer`], or [`IDLE`].
+    drainer: AtomicUsize,
+    dropped_bytes: AtomicUsize,
+}
+
+static CONSOLE: Console = Console::new();
+
+/// Returns the number of bytes of console output dropped because the console
+/// could not keep up.
+pub fn console_dropped_bytes() -> usize {
+    CONSOLE.dropped_bytes.load(Ordering::Relaxed)
+}
+
+/// Assembles console output into complete lines, to write each of them to
+/// the console in a single call instead of one call per piece.
+struct LineBuffer {
+    buf: [u8; LINE_BUFFER_SIZE],
+    len: usize,
+}
+
+impl LineBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: [0; LINE_BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Writes out the buffered output, even if the line is not complete.
+    fn flush(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        // Only whole chars are buffered, see `write_str`.
+        let s = core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default();
+        let _ = crate::Logger.write_str(s);
+        crate::stats::count_bytes_written(s.len());
+        self.len = 0;
+    }
+}
+
+impl Write for LineBuffer {
+    fn write_str(&mut self, mut s: &str) -> fmt::Result {
+        while !s.is_empty() {
+            let line_end = s.find('\n').map_or(s.len(), |i| i + 1);
+            let mut n = line_end.min(LINE_BUFFER_SIZE - self.len);
+            while !s.is_char_boundary(n) {
+                n -= 1;
+            }
+            self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+            self.len += n;
+            s = &s[n..];
+            if n < line_end || self.buf[self.len - 1] == b'\n' {
+                // The line is complete, or does not fit.
+                self.flush();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Console {
+    const fn new() -> Self {
+        Self {
+            queue: ChunkQueue::new(),
+            drainer: AtomicUsize::new(IDLE),
+            dropped_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    fn is_draining(&self) -> bool {
+        self.drainer.load(Ordering::Relaxed) != IDLE
+    }
+
+    /// Becomes the consumer of the queue, unless someone else already is.
+    fn start_draining(&self, drainer: usize) -> bool {
+        self.drainer
+            .compare_exchange(IDLE, drainer, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Writes the queued output to `line`.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by the consumer.
+    unsafe fn drain_queue(&self, line: &mut LineBuffer) {
+        while self.queue.pop(|data| {
+            // Chunks are split at char boundaries, see `ChunkWriter`.
+            let _ = line.write_str(core::str::from_utf8(data).unwrap_or_default());
+        }) {}
+    }
+
+    /// Drains the queue to the console, unless someone else is already doing
+    /// it.
+    fn drain(&self) {
+        let drainer = current_drainer();
+        let mut line = LineBuffer::new();
+        while self.start_draining(drainer) {
+            // SAFETY: we are the only consumer until we stop draining.
+            unsafe { self.drain_queue(&mut line) };
+            // Incomplete lines are not held back, the rest may never come.
+            line.flush();
+            self.drainer.store(IDLE, Ordering::Release);
+            // Chunks pushed after our last `pop` but before we stopped
+            // draining would otherwise be left behind.
+            if self.queue.is_empty() {
+                break;
+            }
+        }
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        let mut w = ChunkWriter {
+            console: self,
+            buf: [0; CHUNK_SIZE],
+            len: 0,
+            dropped: false,
+        };
+        write_themed(&mut w, args)?;
+        w.push();
+        if w.dropped {
+            crate::stats::count_dropped();
+        }
+        self.drain();
+        Ok(())
+    }
+
+    fn write_fmt_blocking(&self, args: fmt::Arguments) -> fmt::Result {
+        let drainer = current_drainer();
+        while !self.start_draining(drainer) {
+            // Waiting for ourselves, e.g., in an interrupt handler, would
+            // never end.
+            if drainer == UNKNOWN_CPU || self.drainer.load(Ordering::Relaxed) == drainer {
+                return self.write_fmt(args);
+            }
+            core::hint::spin_loop();
+        }
+        let mut line = LineBuffer::new();
+        // SAFETY: we are the only consumer until we stop draining.
+        unsafe { self.drain_queue(&mut line) };
+        let res = write_themed(&mut line, args);
+        line.flush();
+        self.drainer.store(IDLE, Ordering::Release);
+        // Output queued while we were writing.
+        self.drain();
+        res
+    }
+}
+
+/// Drains the queue to the console, unless someone else is already doing it.
+pub(crate) fn drain() {
+    CONSOLE.drain();
+}
+
+/// Splits the output into chunks and pushes them to the queue.
+struct ChunkWriter<'a> {
+    console: &'a Console,
+    buf: [u8; CHUNK_SIZE],
+    len: usize,
+    /// Whether some output has been dropped.
+    dropped: bool,
+}
+
+impl ChunkWriter<'_> {
+    fn push(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        while !self.console.queue.push(&self.buf[..self.len]) {
+            // The queue is full: help draining it, or give up if someone
+            // else already is.
+            if self.console.is_draining() {
+                self.console
+                    .dropped_bytes
+                    .fetch_add(self.len, Ordering::Relaxed);
+                self.dropped = true;
+                break;
+            }
+            self.console.drain();
+        }
+        self.len = 0;
+    }
+}
+
+impl Write for ChunkWriter<'_> {
+    fn write_str(&mut self, mut s: &str) -> fmt::Result {
+        while !s.is_empty() {
+            let mut n = s.len().min(CHUNK_SIZE - self.len);
+            while !s.is_char_boundary(n) {
+                n -= 1;
+            }
+            self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+            self.len += n;
+            s = &s[n..];
+            if !s.is_empty() {
+                self.push();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the output in the colors of the theme.
+fn write_themed(w: &mut impl Write, args: fmt::Arguments) -> fmt::Result {
+    if crate::theme::effective_theme() == crate::Theme::Rainbow {
+        let mut rainbow = RainbowWriter::new(w);
+        rainbow.write_fmt(args)?;
+        rainbow.finish()?;
+        Ok(())
+    } else {
+        w.write_fmt(args)
+    }
+}
+
+/// Writes directly to the console, bypassing the queue.
+///
+/// This is only meant for when the queue may never be drained, e.g., on panic.
+pub(crate) fn write_fmt_direct(args: fmt::Arguments) {
+    let mut line = LineBuffer::new();
+    let _ = line.write_fmt(args);
+    line.flush();
+}
+
+/// Writes log records to the console through the queue: they are dropped if
+/// the console can't keep up, and may be interleaved with other output at
+/// chunk boundaries if longer than a chunk.
+pub(crate) fn write_fmt(args: fmt::Arguments) -> fmt::Result {
+    CONSOLE.write_fmt(args)
+}
+
+/// Writes to the console without dropping output or interleaving it with
+/// other output, waiting for the console to be idle.
+///
+/// If the console is busy on the current CPU, e.g., when called from an
+/// interrupt handler, or on an unknown CPU, it can't wait and writes through
+/// the queue like [`write_fmt`].
+pub(crate) fn write_fmt_blocking(args: fmt::Arguments) -> fmt::Result {
+    CONSOLE.write_fmt_blocking(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::String;
+    use std::time::{Duration, Instant};
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Returns a console drained by someone else, whose output stays queued.
+    fn busy_console() -> Console {
+        let console = Console::new();
+        console.drainer.store(UNKNOWN_CPU, Ordering::Relaxed);
+        console
+    }
+
+    fn pop_all(console: &Console) -> Vec<String> {
+        let mut chunks = Vec::new();
+        // SAFETY: the tests are the only consumers of their console.
+        while unsafe {
+            console
+                .queue
+                .pop(|data| chunks.push(String::from_utf8(data.to_vec()).unwrap()))
+        } {}
+        chunks
+    }
+
+    #[test]
+    fn record_in_several_chunks() {
+        let _config = crate::lock_config();
+        let console = busy_console();
+        let record = format!("{}é{}\n", "a".repeat(CHUNK_SIZE - 1), "b".repeat(200));
+        console.write_fmt(format_args!("{}", record)).unwrap();
+
+        // Chunks are split at char boundaries.
+        let chunks = pop_all(&console);
+        let lens: Vec<_> = chunks.iter().map(String::len).collect();
+        assert_eq!(
+            lens,
+            [
+                CHUNK_SIZE - 1,
+                CHUNK_SIZE,
+                record.len() - 2 * CHUNK_SIZE + 1
+            ]
+        );
+        assert_eq!(chunks.concat(), record);
+    }
+
+    #[test]
+    fn full_queue_drops() {
+        let _config = crate::lock_config();
+        let console = busy_console();
+        for i in 0..NUM_CHUNKS {
+            console.write_fmt(format_args!("{}\n", i)).unwrap();
+        }
+        assert_eq!(console.dropped_bytes.load(Ordering::Relaxed), 0);
+
+        console.write_fmt(format_args!("dropped\n")).unwrap();
+        let long = "c".repeat(2 * CHUNK_SIZE + 10);
+        console.write_fmt(format_args!("{}", long)).unwrap();
+        assert_eq!(
+            console.dropped_bytes.load(Ordering::Relaxed),
+            "dropped\n".len() + long.len()
+        );
+
+        let chunks = pop_all(&console);
+        assert_eq!(chunks.len(), NUM_CHUNKS);
+        assert!(chunks
+            .iter()
+            .enumerate()
+            .all(|(i, c)| *c == format!("{}\n", i)));
+
+        // There is room again.
+        console.write_fmt(format_args!("kept\n")).unwrap();
+        assert_eq!(pop_all(&console), ["kept\n"]);
+    }
+
+    #[test]
+    fn no_colors_in_json() {
+        let _config = crate::lock_config();
+        crate::set_theme(crate::Theme::Rainbow);
+        crate::set_output_format(crate::OutputFormat::Json);
+        let console = busy_console();
+        console
+            .write_fmt(format_args!("{{\"message\":\"hi\"}}\n"))
+            .unwrap();
+        assert_eq!(pop_all(&console), ["{\"message\":\"hi\"}\n"]);
+
+        crate::set_output_format(crate::OutputFormat::Human);
+        console.write_fmt(format_args!("hi\n")).unwrap();
+        assert!(pop_all(&console).concat().contains("\u{1B}[38;2;"));
+        crate::set_theme(crate::Theme::default());
+    }
+
+    #[test]
+    fn blocking_write_waits() {
+        let _config = crate::lock_config();
+        static CONSOLE: Console = Console::new();
+        // Drained by another CPU for a while.
+        CONSOLE.drainer.store(0, Ordering::Relaxed);
+        let start = Instant::now();
+        let other = std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(50));
+            CONSOLE.drainer.store(IDLE, Ordering::Release);
+        });
+        for i in 0..NUM_CHUNKS + 1 {
+            CONSOLE.write_fmt_blocking(format_args!("{}\n", i)).unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        other.join().unwrap();
+        assert!(CONSOLE.queue.is_empty());
+        assert_eq!(CONSOLE.dropped_bytes.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn blocking_write_on_the_draining_cpu() {
+        let _config = crate::lock_config();
+        let console = Console::new();
+        console.drainer.store(current_drainer(), Ordering::Relaxed);
+        console
+            .write_fmt_blocking(format_args!("queued\n"))
+            .unwrap();
+        assert_eq!(pop_all(&console), ["queued\n"]);
+    }
+}
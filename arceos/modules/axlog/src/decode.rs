@@ -0,0 +1,233 @@
+//! Host-side decoding of binary log records.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use log::{Level, Record};
+
+use crate::binary::{TAG_DEFINE, TAG_RECORD};
+use crate::{HumanFormatter, LogFormatter, RecordInfo};
+
+/// A log record decoded by [`BinaryDecoder`].
+///
+/// It is displayed like the console shows records, with [`HumanFormatter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedRecord {
+    /// The level of the record.
+    pub level: Level,
+    /// The time of the record, as returned by
+    /// [`current_time`](crate::current_time).
+    pub time: Duration,
+    /// ID of the CPU that emitted the record, if known.
+    pub cpu_id: Option<usize>,
+    /// ID of the task that emitted the record, if known.
+    pub tid: Option<u64>,
+    /// The target of the record.
+    pub target: String,
+    /// The line of the record, if known.
+    pub line: Option<u32>,
+    /// The message of the record.
+    pub message: String,
+}
+
+impl Display for DecodedRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // `format_args!` only lives until the end of the statement.
+        HumanFormatter.format(
+            f,
+            &RecordInfo {
+                record: &Record::builder()
+                    .level(self.level)
+                    .target(&self.target)
+                    .line(self.line)
+                    .args(format_args!("{}", self.message))
+                    .build(),
+                time: self.time,
+                wall_time: None,
+                cpu_id: self.cpu_id,
+                tid: self.tid,
+            },
+        )
+    }
+}
+
+/// Reconstructs log records from the output of a
+/// [`BinarySink`](crate::BinarySink).
+///
+/// Bytes can be fed in pieces of any size, e.g., as they are read from a
+/// serial port. Unknown bytes are skipped, so the decoder resynchronizes on
+/// the next frame if it starts in the middle of the stream, but records that
+/// refer to strings defined before that point are lost.
+///
+/// # Examples
+///
+/// ```
+/// use axlog::{BinaryDecoder, BinarySink};
+/// use std::sync::Mutex;
+///
+/// struct Capture(Mutex<Vec<u8>>);
+///
+/// impl BinarySink for Capture {
+///     fn write_bytes(&self, bytes: &[u8]) {
+///         self.0.lock().unwrap().extend_from_slice(bytes);
+///     }
+/// }
+///
+/// static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+///
+/// axlog::init();
+/// axlog::set_binary_sink(Some(&CAPTURE));
+/// axlog::warn!("disk {} is full", 2);
+/// axlog::set_binary_sink(None);
+///
+/// let mut decoder = BinaryDecoder::new();
+/// let mut messages = Vec::new();
+/// decoder.decode(&CAPTURE.0.lock().unwrap(), |record| {
+///     messages.push(record.message.clone());
+/// });
+/// assert_eq!(messages, ["disk 2 is full"]);
+/// ```
+#[derive(Debug, Default)]
+pub struct BinaryDecoder {
+    strings: HashMap<u64, String>,
+    pending: Vec<u8>,
+    unknown_refs: usize,
+}
+
+/// A byte reader over a frame, which fails at the end of the input.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+/// The input ended in the middle of a frame.
+struct Incomplete;
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, Incomplete> {
+        let byte = *self.bytes.get(self.pos).ok_or(Incomplete)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, Incomplete> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Ok(value)
+    }
+
+    fn text(&mut self, len: u64) -> Result<String, Incomplete> {
+        let end = self.pos.checked_add(len as usize).ok_or(Incomplete)?;
+        let text = self.bytes.get(self.pos..end).ok_or(Incomplete)?;
+        self.pos = end;
+        Ok(String::from_utf8_lossy(text).into_owned())
+    }
+}
+
+impl BinaryDecoder {
+    /// Creates a decoder that knows no strings yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of references to strings never defined,
+    /// usually because the decoder started in the middle of the stream.
+    pub fn unknown_refs(&self) -> usize {
+        self.unknown_refs
+    }
+
+    /// Decodes `bytes`, calling `f` on each complete record.
+    ///
+    /// An incomplete frame at the end is kept until the next call.
+    pub fn decode(&mut self, bytes: &[u8], mut f: impl FnMut(&DecodedRecord)) {
+        let mut pending = core::mem::take(&mut self.pending);
+        pending.extend_from_slice(bytes);
+        let mut pos = 0;
+        while pos < pending.len() {
+            let mut r = Reader {
+                bytes: &pending,
+                pos: pos + 1,
+            };
+            let res = match pending[pos] {
+                TAG_DEFINE => self.define(&mut r).map(|_| None),
+                TAG_RECORD => self.record(&mut r).map(Some),
+                _ => {
+                    pos += 1;
+                    continue;
+                }
+            };
+            match res {
+                Ok(record) => {
+                    pos = r.pos;
+                    if let Some(record) = record {
+                        f(&record);
+                    }
+                }
+                Err(Incomplete) => break,
+            }
+        }
+        pending.drain(..pos);
+        self.pending = pending;
+    }
+
+    fn define(&mut self, r: &mut Reader) -> Result<(), Incomplete> {
+        let id = r.varint()?;
+        let len = r.varint()?;
+        let text = r.text(len)?;
+        self.strings.insert(id, text);
+        Ok(())
+    }
+
+    /// Reads a string reference, as the inline text or the ID to look up
+    /// once the frame is complete.
+    fn string_ref(r: &mut Reader) -> Result<Result<String, u64>, Incomplete> {
+        let value = r.varint()?;
+        if value & 1 == 1 {
+            r.text(value >> 1).map(Ok)
+        } else {
+            Ok(Err(value >> 1))
+        }
+    }
+
+    fn resolve(&mut self, string_ref: Result<String, u64>) -> String {
+        string_ref.unwrap_or_else(|id| match self.strings.get(&id) {
+            Some(s) => s.clone(),
+            None => {
+                self.unknown_refs += 1;
+                String::from("<unknown>")
+            }
+        })
+    }
+
+    fn record(&mut self, r: &mut Reader) -> Result<DecodedRecord, Incomplete> {
+        let level = match r.byte()? {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            _ => Level::Trace,
+        };
+        let time = Duration::from_micros(r.varint()?);
+        let cpu_id = r.varint()?.checked_sub(1).map(|id| id as usize);
+        let tid = r.varint()?.checked_sub(1);
+        let line = r.varint()?.checked_sub(1).map(|line| line as u32);
+        let target = Self::string_ref(r)?;
+        let message = Self::string_ref(r)?;
+        Ok(DecodedRecord {
+            level,
+            time,
+            cpu_id,
+            tid,
+            target: self.resolve(target),
+            line,
+            message: self.resolve(message),
+        })
+    }
+}
@@ -0,0 +1,98 @@
+//! Deferred console output through per-CPU staging buffers.
+//!
+//! In deferred mode, records are formatted into a buffer of the current CPU
+//! instead of being written to the console right away, so that CPUs do not
+//! contend for the console lock and interrupts are only disabled for the
+//! time of formatting. The buffers are written to the console by
+//! [`flush`](crate::flush), CPU by CPU, so records of different CPUs may be
+//! printed out of order.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kspin::SpinNoIrq;
+
+/// Number of per-CPU staging buffers. CPUs with higher IDs share them.
+pub const MAX_CPUS: usize = 16;
+
+/// Size in bytes of each per-CPU staging buffer.
+pub const DEFERRED_BUFFER_SIZE: usize = 4096;
+
+struct Staging {
+    buf: [u8; DEFERRED_BUFFER_SIZE],
+    len: usize,
+}
+
+impl Staging {
+    /// Writes the staged records to the console.
+    fn drain(&mut self) {
+        // Only whole `str`s are staged, see `write_str`.
+        let s = core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default();
+        let _ = crate::console::write_fmt(format_args!("{}", s));
+        self.len = 0;
+    }
+}
+
+impl Write for Staging {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > DEFERRED_BUFFER_SIZE {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+static STAGING: [SpinNoIrq<Staging>; MAX_CPUS] = [const {
+    SpinNoIrq::new(Staging {
+        buf: [0; DEFERRED_BUFFER_SIZE],
+        len: 0,
+    })
+}; MAX_CPUS];
+
+static DEFERRED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables deferred console output of log records.
+///
+/// When enabled, records are staged in per-CPU buffers and only written to
+/// the console by [`flush`](crate::flush), or when the buffer of a CPU is full. Output of
+/// [`ax_print!`](crate::ax_print) is never deferred, so it may appear before
+/// records logged earlier. Disabling it flushes the staged records.
+pub fn set_deferred(deferred: bool) {
+    DEFERRED.store(deferred, Ordering::Relaxed);
+    if !deferred {
+        flush_staged();
+    }
+}
+
+pub(crate) fn is_deferred() -> bool {
+    DEFERRED.load(Ordering::Relaxed)
+}
+
+/// Stages a formatted record in the buffer of the current CPU.
+pub(crate) fn stage(cpu_id: Option<usize>, record: fmt::Arguments) {
+    let mut staging = STAGING[cpu_id.unwrap_or(0) % MAX_CPUS].lock();
+    let start = staging.len;
+    if staging.write_fmt(record).is_ok() {
+        return;
+    }
+    // Make room for the record, or bypass the buffer if it is too large.
+    staging.len = start;
+    staging.drain();
+    if staging.write_fmt(record).is_err() {
+        staging.len = 0;
+        let _ = crate::console::write_fmt(record);
+    }
+}
+
+/// Writes the records staged on all CPUs to the console.
+pub(crate) fn flush_staged() {
+    for staging in &STAGING {
+        let mut staging = staging.lock();
+        if staging.len > 0 {
+            staging.drain();
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Buffering of the records logged before [`init`](crate::init).
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+use log::{Level, Record};
+
+/// Size in bytes of the buffer of records logged before
+/// [`init`](crate::init).
+pub const EARLY_BUFFER_SIZE: usize = 4096;
+
+/// Maximum length of the message of a buffered record, longer ones are
+/// truncated.
+const MAX_EARLY_MESSAGE_LEN: usize = 256;
+
+/// Maximum length of the target of a buffered record, longer ones are
+/// truncated.
+const MAX_EARLY_TARGET_LEN: usize = u8::MAX as usize;
+
+/// Record header: level (1 byte), time in microseconds (8 bytes), target
+/// length (1 byte) and message length (2 bytes).
+const HEADER_LEN: usize = 12;
+
+struct EarlyBuffer {
+    buf: [u8; EARLY_BUFFER_SIZE],
+    len: usize,
+    /// Number of records that did not fit.
+    dropped: usize,
+}
+
+static EARLY: SpinNoIrq<EarlyBuffer> = SpinNoIrq::new(EarlyBuffer {
+    buf: [0; EARLY_BUFFER_SIZE],
+    len: 0,
+    dropped: 0,
+});
+
+/// Set by [`init`](crate::init), with [`EARLY`] locked.
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+#[doc(hidden)]
+#[inline]
+pub fn __is_initialized() -> bool {
+    INITIALIZED.load(Ordering::Acquire)
+}
+
+/// Formats a message into a fixed buffer, truncating it.
+struct MessageWriter {
+    buf: [u8; MAX_EARLY_MESSAGE_LEN],
+    len: usize,
+}
+
+impl Write for MessageWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut n = s.len().min(MAX_EARLY_MESSAGE_LEN - self.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+fn truncate(s: &str, max_len: usize) -> &str {
+    let mut n = s.len().min(max_len);
+    while !s.is_char_boundary(n) {
+        n -= 1;
+    }
+    &s[..n]
+}
+
+/// Keeps a record logged before [`init`](crate::init), to be replayed by it.
+///
+/// Used by the log macros. Records that do not fit in the buffer are dropped
+/// and counted, the earliest ones are kept.
+#[doc(hidden)]
+pub fn __log_early(level: Level, target: &str, args: fmt::Arguments) {
+    let time = crate::current_time();
+    let mut early = EARLY.lock();
+    if __is_initialized() {
+        // `init` was called in the meantime.
+        drop(early);
+        log::logger().log(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(args)
+                .build(),
+        );
+        return;
+    }
+
+    let mut w = MessageWriter {
+        buf: [0; MAX_EARLY_MESSAGE_LEN],
+        len: 0,
+    };
+    // `MessageWriter` never fails, it truncates instead.
+    let _ = w.write_fmt(args);
+    let target = truncate(target, MAX_EARLY_TARGET_LEN);
+    let record_len = HEADER_LEN + target.len() + w.len;
+
+    let start = early.len;
+    if start + record_len > EARLY_BUFFER_SIZE {
+        early.dropped += 1;
+        return;
+    }
+    let buf = &mut early.buf[start..start + record_len];
+    buf[0] = level as u8;
+    buf[1..9].copy_from_slice(&(time.as_micros() as u64).to_le_bytes());
+    buf[9] = target.len() as u8;
+    buf[10..12].copy_from_slice(&(w.len as u16).to_le_bytes());
+    buf[HEADER_LEN..HEADER_LEN + target.len()].copy_from_slice(target.as_bytes());
+    buf[HEADER_LEN + target.len()..].copy_from_slice(&w.buf[..w.len]);
+    early.len += record_len;
+}
+
+/// Marks the logger as initialized, then calls `f` with the level, time,
+/// target and message of each buffered record.
+///
+/// Returns the number of records that did not fit in the buffer.
+pub(crate) fn replay(mut f: impl FnMut(Level, Duration, &str, &str)) -> usize {
+    let (buf, len, dropped) = {
+        let mut early = EARLY.lock();
+        INITIALIZED.store(true, Ordering::Release);
+        let len = core::mem::take(&mut early.len);
+        (early.buf, len, core::mem::take(&mut early.dropped))
+    };
+
+    let mut pos = 0;
+    while pos < len {
+        let level = match buf[pos] {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            _ => Level::Trace,
+        };
+        let micros = u64::from_le_bytes(buf[pos + 1..pos + 9].try_into().unwrap());
+        let target_len = buf[pos + 9] as usize;
+        let message_len = u16::from_le_bytes([buf[pos + 10], buf[pos + 11]]) as usize;
+        let target_start = pos + HEADER_LEN;
+        let message_start = target_start + target_len;
+        // Both were copied from `&str`s and truncated at char boundaries.
+        let target = core::str::from_utf8(&buf[target_start..message_start]).unwrap_or_default();
+        let message = core::str::from_utf8(&buf[message_start..message_start + message_len])
+            .unwrap_or_default();
+        f(level, Duration::from_micros(micros), target, message);
+        pos = message_start + message_len;
+    }
+    dropped
+}
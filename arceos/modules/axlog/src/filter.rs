@@ -0,0 +1,230 @@
+//! Per-target maximum log levels.
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+use log::LevelFilter;
+
+/// Maximum number of per-target levels set with [`set_target_level`].
+pub const MAX_TARGET_FILTERS: usize = 16;
+
+/// Maximum length of a target pattern.
+const MAX_PATTERN_LEN: usize = 32;
+
+/// The error returned by [`set_target_level`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetFilterError {
+    /// All [`MAX_TARGET_FILTERS`] slots are in use.
+    TooManyFilters,
+    /// The target pattern is empty or too long.
+    InvalidPattern,
+}
+
+impl fmt::Display for TargetFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooManyFilters => write!(
+                f,
+                "too many target filters (at most {})",
+                MAX_TARGET_FILTERS
+            ),
+            Self::InvalidPattern => write!(
+                f,
+                "invalid target pattern (1 to {} bytes expected)",
+                MAX_PATTERN_LEN
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TargetFilter {
+    pattern: [u8; MAX_PATTERN_LEN],
+    len: usize,
+    level: LevelFilter,
+}
+
+impl TargetFilter {
+    fn pattern(&self) -> &[u8] {
+        &self.pattern[..self.len]
+    }
+
+    fn match_len(&self, target: &str) -> Option<usize> {
+        // Patterns are only set from `&str`s.
+        match_len(core::str::from_utf8(self.pattern()).ok()?, target)
+    }
+}
+
+/// Returns the length of the match of `pattern` with `target`, if any.
+///
+/// `foo` matches the target `foo` and its submodules `foo::*`, while `foo*`
+/// matches every target starting with `foo`.
+pub(crate) fn match_len(pattern: &str, target: &str) -> Option<usize> {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => target.starts_with(prefix).then_some(prefix.len()),
+        None => {
+            let matched = target.starts_with(pattern)
+                && (target.len() == pattern.len() || target[pattern.len()..].starts_with("::"));
+            matched.then_some(pattern.len())
+        }
+    }
+}
+
+struct FilterTable {
+    filters: [TargetFilter; MAX_TARGET_FILTERS],
+    len: usize,
+}
+
+static FILTERS: SpinNoIrq<FilterTable> = SpinNoIrq::new(FilterTable {
+    filters: [TargetFilter {
+        pattern: [0; MAX_PATTERN_LEN],
+        len: 0,
+        level: LevelFilter::Off,
+    }; MAX_TARGET_FILTERS],
+    len: 0,
+});
+
+/// Number of filters, to skip locking the table when there are none.
+static NUM_FILTERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the maximum console log level of the targets matching `pattern`,
+/// overriding [`set_max_level`](crate::set_max_level) for them.
+///
+/// `pattern` matches a target and all its submodules, e.g., `axfs` matches
+/// `axfs` and `axfs::fops`. With a trailing `*` it matches every target with
+/// that prefix, e.g., `axfs*` also matches `axfs_ramfs`. When several
+/// patterns match, the longest one wins.
+///
+/// Setting the level of an existing pattern replaces it.
+pub fn set_target_level(pattern: &str, level: LevelFilter) -> Result<(), TargetFilterError> {
+    if pattern.is_empty() || pattern.len() > MAX_PATTERN_LEN {
+        return Err(TargetFilterError::InvalidPattern);
+    }
+    {
+        let mut table = FILTERS.lock();
+        let len = table.len;
+        let pos = match table.filters[..len]
+            .iter()
+            .position(|f| f.pattern() == pattern.as_bytes())
+        {
+            Some(pos) => pos,
+            None if len < MAX_TARGET_FILTERS => {
+                table.len += 1;
+                len
+            }
+            None => return Err(TargetFilterError::TooManyFilters),
+        };
+        let filter = &mut table.filters[pos];
+        filter.pattern[..pattern.len()].copy_from_slice(pattern.as_bytes());
+        filter.len = pattern.len();
+        filter.level = level;
+        NUM_FILTERS.store(table.len, Ordering::Relaxed);
+    }
+    crate::update_max_level();
+    Ok(())
+}
+
+/// Removes all per-target levels set with [`set_target_level`].
+pub fn clear_target_levels() {
+    {
+        let mut table = FILTERS.lock();
+        table.len = 0;
+        NUM_FILTERS.store(0, Ordering::Relaxed);
+    }
+    crate::update_max_level();
+}
+
+/// Returns the level of the longest pattern matching `target`, if any.
+pub(crate) fn target_level(target: &str) -> Option<LevelFilter> {
+    if NUM_FILTERS.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let table = FILTERS.lock();
+    table.filters[..table.len]
+        .iter()
+        .filter_map(|f| f.match_len(target).map(|len| (len, f.level)))
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, level)| level)
+}
+
+/// Returns the highest level of all per-target levels.
+pub(crate) fn max_target_level() -> LevelFilter {
+    let table = FILTERS.lock();
+    table.filters[..table.len]
+        .iter()
+        .map(|f| f.level)
+        .max()
+        .unwrap_or(LevelFilter::Off)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert_eq!(match_len("axfs", "axfs"), Some(4));
+        assert_eq!(match_len("axfs", "axfs::fops"), Some(4));
+        assert_eq!(match_len("axfs", "axfs_ramfs"), None);
+        assert_eq!(match_len("axfs", "ax"), None);
+        assert_eq!(match_len("axfs*", "axfs_ramfs::dir"), Some(4));
+        assert_eq!(match_len("axfs*", "axfs"), Some(4));
+        assert_eq!(match_len("*", "axnet"), Some(0));
+        assert_eq!(match_len("axfs::fops", "axfs"), None);
+    }
+
+    #[test]
+    fn target_levels() {
+        let _config = crate::lock_config();
+        clear_target_levels();
+        assert_eq!(target_level("axfs"), None);
+
+        set_target_level("axfs*", LevelFilter::Debug).unwrap();
+        set_target_level("axfs", LevelFilter::Warn).unwrap();
+        set_target_level("axfs::fops", LevelFilter::Trace).unwrap();
+        // The longest matching pattern wins.
+        assert_eq!(target_level("axfs::root"), Some(LevelFilter::Warn));
+        assert_eq!(target_level("axfs::fops::file"), Some(LevelFilter::Trace));
+        assert_eq!(target_level("axfs_ramfs"), Some(LevelFilter::Debug));
+        assert_eq!(target_level("axnet"), None);
+        assert_eq!(max_target_level(), LevelFilter::Trace);
+
+        // Setting a pattern again replaces its level.
+        set_target_level("axfs::fops", LevelFilter::Error).unwrap();
+        assert_eq!(target_level("axfs::fops"), Some(LevelFilter::Error));
+        assert_eq!(FILTERS.lock().len, 3);
+        assert_eq!(max_target_level(), LevelFilter::Debug);
+
+        clear_target_levels();
+        assert_eq!(target_level("axfs"), None);
+        assert_eq!(max_target_level(), LevelFilter::Off);
+    }
+
+    #[test]
+    fn errors() {
+        let _config = crate::lock_config();
+        clear_target_levels();
+        let long = "a".repeat(MAX_PATTERN_LEN + 1);
+        assert_eq!(
+            set_target_level("", LevelFilter::Info),
+            Err(TargetFilterError::InvalidPattern)
+        );
+        assert_eq!(
+            set_target_level(&long, LevelFilter::Info),
+            Err(TargetFilterError::InvalidPattern)
+        );
+        set_target_level(&long[1..], LevelFilter::Info).unwrap();
+
+        for i in 1..MAX_TARGET_FILTERS {
+            set_target_level(&long[i + 1..], LevelFilter::Info).unwrap();
+        }
+        assert_eq!(
+            set_target_level("axnet", LevelFilter::Info),
+            Err(TargetFilterError::TooManyFilters)
+        );
+        // Existing patterns can still be changed.
+        set_target_level(&long[1..], LevelFilter::Trace).unwrap();
+        clear_target_levels();
+    }
+}
@@ -0,0 +1,495 @@
+//! Formatting of log records.
+
+use core::fmt::{self, Display, Write};
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+
+use crate::style::{Color, Style};
+#[cfg(not(feature = "std"))]
+use crate::CalendarTime;
+use log::{Level, Record};
+
+/// A log record with the context in which it was emitted.
+pub struct RecordInfo<'a> {
+    /// The record itself.
+    pub record: &'a Record<'a>,
+    /// Time when the record was emitted, as returned by
+    /// [`current_time`](crate::current_time).
+    pub time: Duration,
+    /// Wall-clock time since the UNIX epoch when the record was emitted, if
+    /// known, as returned by [`current_wall_time`](crate::current_wall_time).
+    pub wall_time: Option<Duration>,
+    /// ID of the CPU that emitted the record, if known.
+    pub cpu_id: Option<usize>,
+    /// ID of the task that emitted the record, if known.
+    pub tid: Option<u64>,
+}
+
+/// Formats log records into text.
+///
+/// Set the formatter in use with [`set_formatter`].
+pub trait LogFormatter: Send + Sync {
+    /// Writes a record to `f`, without the trailing newline.
+    fn format(&self, f: &mut dyn Write, info: &RecordInfo) -> fmt::Result;
+}
+
+/// The default formatter: `[time cpu:tid path:line] message`. With the
+/// [`LevelColored`](crate::Theme::LevelColored) theme, the header is white and
+/// the message is colored by level.
+///
+/// The time is the wall-clock date and time if known (local in the `std`
+/// environment, UTC otherwise), or the clock time since boot. The CPU and
+/// task IDs are shown if [`LogIf`](crate::LogIf) provides them.
+pub struct HumanFormatter;
+
+impl LogFormatter for HumanFormatter {
+    fn format(&self, f: &mut dyn Write, info: &RecordInfo) -> fmt::Result {
+        let args_color = match info.record.level() {
+            Level::Error => Color::Red,
+            Level::Warn => Color::Yellow,
+            Level::Info => Color::Green,
+            Level::Debug => Color::Cyan,
+            Level::Trace => Color::BrightBlack,
+        };
+
+        let colored = crate::theme::effective_theme() == crate::Theme::LevelColored;
+        if colored {
+            Style::new().fg(Color::White).write_start(f, false)?;
+        }
+        f.write_char('[')?;
+        match info.wall_time {
+            Some(time) => write_wall_time(f, time)?,
+            None => write!(
+                f,
+                "{:>3}.{:06}",
+                info.time.as_secs(),
+                info.time.subsec_micros()
+            )?,
+        }
+        if let Some(cpu_id) = info.cpu_id {
+            write!(f, " {}", cpu_id)?;
+            if let Some(tid) = info.tid {
+                write!(f, ":{}", tid)?;
+            }
+        }
+        write!(
+            f,
+            " {}:{}] ",
+            info.record.target(),
+            info.record.line().unwrap_or(0)
+        )?;
+        if colored {
+            // The message color replaces the header color, one reset ends both.
+            Style::new().fg(args_color).write_start(f, false)?;
+            write!(f, "{}\u{1B}[m", info.record.args())
+        } else {
+            write!(f, "{}", info.record.args())
+        }
+    }
+}
+
+/// Writes a wall-clock time since the UNIX epoch as a date and time, local in
+/// the `std` environment, UTC otherwise.
+fn write_wall_time(f: &mut dyn Write, time: Duration) -> fmt::Result {
+    #[cfg(feature = "std")]
+    {
+        use chrono::TimeZone;
+        let time = chrono::Local
+            .timestamp_opt(time.as_secs() as i64, time.subsec_nanos())
+            .single()
+            .unwrap_or_default();
+        write!(f, "{}", time.format("%Y-%m-%d %H:%M:%S%.6f"))
+    }
+    #[cfg(not(feature = "std"))]
+    write!(f, "{}", CalendarTime(time))
+}
+
+/// Formats each record as a single-line JSON object, e.g.:
+///
+/// ```text
+/// {"level":"INFO","timestamp":1.000200,"cpu":0,"tid":2,"target":"axruntime","line":42,"message":"hello"}
+/// ```
+///
+/// `cpu` and `tid` are `null` when unknown. No color escapes are emitted.
+pub struct JsonFormatter;
+
+impl LogFormatter for JsonFormatter {
+    fn format(&self, f: &mut dyn Write, info: &RecordInfo) -> fmt::Result {
+        write!(
+            f,
+            "{{\"level\":\"{}\",\"timestamp\":{}.{:06},\"cpu\":",
+            info.record.level(),
+            info.time.as_secs(),
+            info.time.subsec_micros(),
+        )?;
+        match info.cpu_id {
+            Some(cpu_id) => write!(f, "{}", cpu_id)?,
+            None => f.write_str("null")?,
+        }
+        f.write_str(",\"tid\":")?;
+        match info.tid {
+            Some(tid) => write!(f, "{}", tid)?,
+            None => f.write_str("null")?,
+        }
+        write!(
+            f,
+            ",\"target\":\"{}\",\"line\":{},\"message\":\"{}\"}}",
+            JsonEscaped(info.record.target()),
+            info.record.line().unwrap_or(0),
+            JsonEscaped(info.record.args()),
+        )
+    }
+}
+
+/// A formatter driven by a template string, in which the placeholders
+/// `{time}`, `{cpu}`, `{tid}`, `{level}`, `{target}`, `{line}` and
+/// `{message}` are replaced by the fields of the record.
+///
+/// `{time}` is the wall-clock date and time if known, as in
+/// [`HumanFormatter`], or the clock time since boot. Unknown CPU or task IDs
+/// are shown as `-`, other text is copied as is.
+///
+/// # Examples
+///
+/// ```
+/// use axlog::TemplateFormatter;
+///
+/// static FORMATTER: TemplateFormatter = TemplateFormatter::new("{level} {target}: {message}");
+/// axlog::set_formatter(&FORMATTER);
+/// ```
+pub struct TemplateFormatter {
+    template: &'static str,
+}
+
+impl TemplateFormatter {
+    /// Creates a formatter from a template string.
+    pub const fn new(template: &'static str) -> Self {
+        Self { template }
+    }
+}
+
+impl LogFormatter for TemplateFormatter {
+    fn format(&self, f: &mut dyn Write, info: &RecordInfo) -> fmt::Result {
+        let mut rest = self.template;
+        while let Some(start) = rest.find('{') {
+            f.write_str(&rest[..start])?;
+            rest = &rest[start..];
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            match &rest[1..end] {
+                "time" => match info.wall_time {
+                    Some(time) => write_wall_time(f, time)?,
+                    None => write!(
+                        f,
+                        "{}.{:06}",
+                        info.time.as_secs(),
+                        info.time.subsec_micros()
+                    )?,
+                },
+                "cpu" => match info.cpu_id {
+                    Some(cpu_id) => write!(f, "{}", cpu_id)?,
+                    None => f.write_str("-")?,
+                },
+                "tid" => match info.tid {
+                    Some(tid) => write!(f, "{}", tid)?,
+                    None => f.write_str("-")?,
+                },
+                "level" => write!(f, "{}", info.record.level())?,
+                "target" => f.write_str(info.record.target())?,
+                "line" => write!(f, "{}", info.record.line().unwrap_or(0))?,
+                "message" => write!(f, "{}", info.record.args())?,
+                _ => f.write_str(&rest[..=end])?,
+            }
+            rest = &rest[end + 1..];
+        }
+        f.write_str(rest)
+    }
+}
+
+/// Built-in formats of log records, see [`set_output_format`].
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// The colored format of [`HumanFormatter`], for humans.
+    #[default]
+    Human = 0,
+    /// The format of [`JsonFormatter`], for machines. No colors are written
+    /// to the console in this format, whatever the [`Theme`](crate::Theme).
+    Json = 1,
+}
+
+static FORMATTER: SpinNoIrq<&'static dyn LogFormatter> = SpinNoIrq::new(&HumanFormatter);
+
+static OUTPUT_FORMAT: AtomicU8 = AtomicU8::new(OutputFormat::Human as u8);
+
+/// Sets the formatter of log records.
+pub fn set_formatter(formatter: &'static dyn LogFormatter) {
+    *FORMATTER.lock() = formatter;
+    OUTPUT_FORMAT.store(OutputFormat::Human as u8, Ordering::Relaxed);
+}
+
+/// Sets one of the built-in formatters of log records.
+pub fn set_output_format(format: OutputFormat) {
+    set_formatter(match format {
+        OutputFormat::Human => &HumanFormatter,
+        OutputFormat::Json => &JsonFormatter,
+    });
+    OUTPUT_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// Returns the built-in format of log records in use, [`OutputFormat::Human`]
+/// for the formatters set with [`set_formatter`].
+pub fn output_format() -> OutputFormat {
+    match OUTPUT_FORMAT.load(Ordering::Relaxed) {
+        1 => OutputFormat::Json,
+        _ => OutputFormat::Human,
+    }
+}
+
+/// A record displayed with the current formatter.
+pub(crate) struct Formatted<'a> {
+    formatter: &'static dyn LogFormatter,
+    info: &'a RecordInfo<'a>,
+}
+
+impl<'a> Formatted<'a> {
+    pub fn new(info: &'a RecordInfo<'a>) -> Self {
+        Self {
+            formatter: *FORMATTER.lock(),
+            info,
+        }
+    }
+}
+
+impl Display for Formatted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.formatter.format(f, self.info)
+    }
+}
+
+/// Maximum length in bytes of the message of a record, see
+/// [`set_max_record_len`].
+static MAX_RECORD_LEN: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Sets the maximum length in bytes of the message of a log record.
+///
+/// Longer messages are cut at this length, and followed by `...(+N bytes)`
+/// with the number of bytes left out. They are still formatted in full to
+/// count them, but the rest is not written anywhere. There is no limit by
+/// default.
+pub fn set_max_record_len(len: usize) {
+    MAX_RECORD_LEN.store(len, Ordering::Relaxed);
+}
+
+/// Returns the maximum length in bytes of the message of a log record, see
+/// [`set_max_record_len`].
+pub fn max_record_len() -> usize {
+    MAX_RECORD_LEN.load(Ordering::Relaxed)
+}
+
+/// A message displayed up to a maximum length, followed by a marker with the
+/// number of bytes left out.
+pub(crate) struct Truncated<'a> {
+    args: &'a fmt::Arguments<'a>,
+    max_len: usize,
+}
+
+impl<'a> Truncated<'a> {
+    pub fn new(args: &'a fmt::Arguments<'a>, max_len: usize) -> Self {
+        Self { args, max_len }
+    }
+}
+
+impl Display for Truncated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        struct Limiter<'a, 'b> {
+            f: &'a mut fmt::Formatter<'b>,
+            remaining: usize,
+            skipped: usize,
+        }
+
+        impl Write for Limiter<'_, '_> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                let mut n = s.len().min(self.remaining);
+                while !s.is_char_boundary(n) {
+                    n -= 1;
+                }
+                self.f.write_str(&s[..n])?;
+                // Once a char does not fit, nothing more is written.
+                self.remaining = if n < s.len() { 0 } else { self.remaining - n };
+                self.skipped += s.len() - n;
+                Ok(())
+            }
+        }
+
+        let mut limiter = Limiter {
+            f,
+            remaining: self.max_len,
+            skipped: 0,
+        };
+        limiter.write_fmt(*self.args)?;
+        if limiter.skipped > 0 {
+            write!(limiter.f, "...(+{} bytes)", limiter.skipped)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays a value as the contents of a JSON string.
+struct JsonEscaped<T>(T);
+
+impl<T: Display> Display for JsonEscaped<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        struct Escaper<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+        impl Write for Escaper<'_, '_> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                for c in s.chars() {
+                    match c {
+                        '"' => self.0.write_str("\\\"")?,
+                        '\\' => self.0.write_str("\\\\")?,
+                        '\n' => self.0.write_str("\\n")?,
+                        '\r' => self.0.write_str("\\r")?,
+                        '\t' => self.0.write_str("\\t")?,
+                        c if (c as u32) < 0x20 => write!(self.0, "\\u{:04x}", c as u32)?,
+                        c => self.0.write_char(c)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        write!(Escaper(f), "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::String;
+
+    use super::*;
+
+    fn truncated(max_len: usize, args: fmt::Arguments) -> String {
+        format!("{}", Truncated::new(&args, max_len))
+    }
+
+    fn format_record(formatter: &dyn LogFormatter, record: &Record) -> String {
+        let info = RecordInfo {
+            record,
+            time: Duration::new(1, 200_000),
+            wall_time: None,
+            cpu_id: Some(0),
+            tid: None,
+        };
+        let mut s = String::new();
+        formatter.format(&mut s, &info).unwrap();
+        s
+    }
+
+    #[test]
+    fn truncation() {
+        assert_eq!(truncated(5, format_args!("hello")), "hello");
+        assert_eq!(
+            truncated(5, format_args!("hello world")),
+            "hello...(+6 bytes)"
+        );
+        assert_eq!(truncated(0, format_args!("hi")), "...(+2 bytes)");
+        // Cut at a char boundary.
+        assert_eq!(truncated(2, format_args!("héllo")), "h...(+5 bytes)");
+        // Nothing is written after the cut, even if it would fit.
+        assert_eq!(
+            truncated(3, format_args!("ab{}cd", "XYZ")),
+            "abX...(+4 bytes)"
+        );
+    }
+
+    #[test]
+    fn json_escaping() {
+        assert_eq!(
+            format!("{}", JsonEscaped("a\"b\\c\nd\re\tf\u{1}g\u{7f}é")),
+            "a\\\"b\\\\c\\nd\\re\\tf\\u0001g\u{7f}é"
+        );
+        let json = format_record(
+            &JsonFormatter,
+            &Record::builder()
+                .level(Level::Warn)
+                .target("axfs::\"fops\"")
+                .line(Some(7))
+                .args(format_args!("say \"{}\"\n", "hi"))
+                .build(),
+        );
+        assert_eq!(
+            json,
+            "{\"level\":\"WARN\",\"timestamp\":1.000200,\"cpu\":0,\"tid\":null,\
+             \"target\":\"axfs::\\\"fops\\\"\",\"line\":7,\"message\":\"say \\\"hi\\\"\\n\"}"
+        );
+    }
+
+    #[test]
+    fn human() {
+        let _config = crate::lock_config();
+        let record = Record::builder()
+            .level(Level::Error)
+            .target("axfs")
+            .line(Some(7))
+            .args(format_args!("boom"))
+            .build();
+        assert_eq!(
+            format_record(&HumanFormatter, &record),
+            "\u{1B}[37m[  1.000200 0 axfs:7] \u{1B}[31mboom\u{1B}[m"
+        );
+        crate::set_theme(crate::Theme::Plain);
+        assert_eq!(
+            format_record(&HumanFormatter, &record),
+            "[  1.000200 0 axfs:7] boom"
+        );
+        crate::set_theme(crate::Theme::default());
+
+        // No colors on a console that does not support them.
+        crate::set_console_caps(crate::ConsoleCaps {
+            supports_ansi_color: false,
+            ..Default::default()
+        });
+        assert_eq!(
+            format_record(&HumanFormatter, &record),
+            "[  1.000200 0 axfs:7] boom"
+        );
+        crate::set_console_caps(crate::ConsoleCaps::default());
+    }
+
+    #[test]
+    fn template() {
+        let formatter = TemplateFormatter::new(
+            "{time} {cpu}:{tid} {level} {unknown} {target}:{line} {message} {",
+        );
+        let text = format_record(
+            &formatter,
+            &Record::builder()
+                .level(Level::Info)
+                .target("axfs")
+                .line(Some(3))
+                .args(format_args!("hello"))
+                .build(),
+        );
+        assert_eq!(text, "1.000200 0:- INFO {unknown} axfs:3 hello {");
+
+        // The wall-clock time is preferred, a year after the epoch is
+        // 1971-01-01 in any time zone.
+        let record = Record::builder().args(format_args!("")).build();
+        let info = RecordInfo {
+            record: &record,
+            time: Duration::new(1, 200_000),
+            wall_time: Some(Duration::from_secs(365 * 86400 + 43200)),
+            cpu_id: None,
+            tid: None,
+        };
+        let mut text = String::new();
+        TemplateFormatter::new("{time}")
+            .format(&mut text, &info)
+            .unwrap();
+        assert!(text.starts_with("1971-01-01 "), "{}", text);
+    }
+}
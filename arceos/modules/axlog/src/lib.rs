@@ -25,6 +25,60 @@
 //!   optimized out to a no-op.
 //! - `log-level-warn`, `log-level-info`, `log-level-debug`, `log-level-trace`:
 //!   Similar to `log-level-error`.
+//! - `allocator`: Provide [`TracedAllocator`], which traces the operations of
+//!   an allocator of the [`allocator`] crate. This is disabled by default.
+//!
+//! The log macros of this crate can also be compiled out per module, by
+//! setting the `AX_LOG_STATIC` environment variable when building, e.g.,
+//! `AX_LOG_STATIC=axfs*=trace,warn` to keep all levels in the `axfs*` crates
+//! but only `warn` and `error` elsewhere. Its entries are `<pattern>=<level>`,
+//! with the patterns of [`set_target_level`], plus an optional `<level>` for
+//! the other modules. Records logged with the `log` crate directly are not
+//! affected.
+//!
+//! # Sinks
+//!
+//! Besides the console, log records can be sent to any number of extra
+//! destinations, by implementing [`LogSink`] and registering it with
+//! [`register_sink`], or with [`register_sink_with_filter`] to select the
+//! records it receives by level and target.
+//!
+//! Records are printed in a colored human-readable format by default, or as
+//! one JSON object per line after [`set_output_format`]`(`[`OutputFormat::Json`]`)`.
+//! Colors follow the [`Theme`] set with [`set_theme`].
+//! Targets can be given their own maximum level with [`set_target_level`].
+//! All of these can be configured at once from the kernel command line with
+//! [`configure_from_cmdline`].
+//! Custom formats can be defined by implementing [`LogFormatter`] or with a
+//! [`TemplateFormatter`], and set with [`set_formatter`].
+//!
+//! Counters of logged records and console output are available from
+//! [`stats`] and [`target_stats`].
+//!
+//! For consoles too slow for text, records can instead be sent in a compact
+//! binary format to a [`BinarySink`] set with [`set_binary_sink`], and decoded
+//! on the host by `BinaryDecoder` (with the `std` feature).
+//!
+//! Other console output can be colored with the [`style`] module, e.g.,
+//! `"hello".fg(Color::Red).bold()`, which leaves out the escape sequences
+//! that the console does not support.
+//!
+//! Messages longer than [`set_max_record_len`] are truncated, so that a
+//! single record cannot keep the console busy for long.
+//!
+//! Console output of log records can be deferred with [`set_deferred`], to be
+//! written out later by [`flush`].
+//!
+//! Nested stages of work, such as boot-time initialization, can be traced
+//! with [`span!`], which also indents the records logged inside.
+//!
+//! Floods of log records can be contained with [`log_ratelimited!`] at a call
+//! site, or globally with [`set_duplicate_suppression`]. Messages that only
+//! need to be seen once can be logged with [`warn_once!`] and its siblings.
+//!
+//! The latest records are also kept in an in-memory ring buffer, which can be
+//! read back with [`dump_ring_buffer`] or [`read_since`]. It can capture more
+//! verbose records than the console shows, see [`set_ring_buffer_level`].
 //!
 //! # Examples
 //!
@@ -34,7 +88,7 @@
 //! // Initialize the logger.
 //! axlog::init();
 //! // Set the maximum log level to `info`.
-//! axlog::set_max_level("info");
+//! axlog::set_max_level("info").unwrap();
 //!
 //! // The following logs will be printed.
 //! error!("error");
@@ -50,15 +104,145 @@
 
 extern crate log;
 
+mod binary;
+mod calendar;
+mod cmdline;
+mod console;
+#[cfg(feature = "std")]
+mod decode;
+mod deferred;
+mod early;
+mod filter;
+mod format;
+mod panic;
+mod ratelimit;
+mod ring;
+mod sink;
+mod span;
+mod static_level;
+mod stats;
+pub mod style;
+mod theme;
+#[cfg(feature = "allocator")]
+mod traced_alloc;
+
 use core::fmt::{self, Write};
 use core::str::FromStr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicUsize, Ordering};
 
-use log::{Level, LevelFilter, Log, Metadata, Record};
+use log::{Log, Metadata, Record};
 
 #[cfg(not(feature = "std"))]
 use crate_interface::call_interface;
 
-pub use log::{debug, error, info, trace, warn};
+pub use binary::{set_binary_sink, BinarySink, MAX_BINARY_RECORD_LEN};
+pub use calendar::CalendarTime;
+pub use cmdline::{configure_from_cmdline, CmdlineError};
+pub use console::console_dropped_bytes;
+#[cfg(feature = "std")]
+pub use decode::{BinaryDecoder, DecodedRecord};
+pub use deferred::{set_deferred, DEFERRED_BUFFER_SIZE, MAX_CPUS};
+pub use early::EARLY_BUFFER_SIZE;
+pub use filter::{clear_target_levels, set_target_level, TargetFilterError, MAX_TARGET_FILTERS};
+pub use format::{
+    max_record_len, output_format, set_formatter, set_max_record_len, set_output_format,
+    HumanFormatter, JsonFormatter, LogFormatter, OutputFormat, RecordInfo, TemplateFormatter,
+};
+pub use log::{Level, LevelFilter};
+pub use panic::panic_report;
+pub use ratelimit::set_duplicate_suppression;
+pub use ring::{
+    dump_ring_buffer, read_since, set_ring_buffer_level, RingRecord, MAX_RING_RECORD_LEN,
+    RING_BUFFER_SIZE,
+};
+pub use sink::{
+    register_sink, register_sink_with_filter, unregister_sink, LogSink, SinkFilter, TooManySinks,
+    MAX_SINKS,
+};
+pub use span::Span;
+pub use stats::{stats, target_stats, LogStats, MAX_TARGETS};
+pub use theme::{set_theme, theme, Theme};
+#[cfg(feature = "allocator")]
+pub use traced_alloc::TracedAllocator;
+
+#[doc(hidden)]
+pub use early::{__is_initialized, __log_early};
+#[doc(hidden)]
+pub use log::{log as __log, log_enabled as __log_enabled};
+#[doc(hidden)]
+pub use ratelimit::__RateLimit;
+#[doc(hidden)]
+pub use static_level::__static_max_level;
+
+/// Returns whether records at `lvl` from the calling module are kept by the
+/// static per-module levels and the `log-level-*` features.
+///
+/// This is a constant for constant levels, so that the disabled call sites
+/// are compiled out.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __static_enabled {
+    ($lvl:expr) => {
+        ($lvl as usize) <= const { $crate::__static_max_level(module_path!()) as usize }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_static {
+    ($lvl:expr, target: $target:expr, $($arg:tt)+) => {
+        if $crate::__static_enabled!($lvl) {
+            if $crate::__is_initialized() {
+                $crate::__log!(target: $target, $lvl, $($arg)+);
+            } else {
+                $crate::__log_early($lvl, $target, format_args!($($arg)+));
+            }
+        }
+    };
+    ($lvl:expr, $($arg:tt)+) => {
+        $crate::__log_static!($lvl, target: module_path!(), $($arg)+)
+    };
+}
+
+/// Logs a message at the error level.
+///
+/// The arguments are the same as those of [`log::error!`].
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::__log_static!($crate::Level::Error, $($arg)+) };
+}
+
+/// Logs a message at the warn level.
+///
+/// The arguments are the same as those of [`log::warn!`].
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::__log_static!($crate::Level::Warn, $($arg)+) };
+}
+
+/// Logs a message at the info level.
+///
+/// The arguments are the same as those of [`log::info!`].
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::__log_static!($crate::Level::Info, $($arg)+) };
+}
+
+/// Logs a message at the debug level.
+///
+/// The arguments are the same as those of [`log::debug!`].
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::__log_static!($crate::Level::Debug, $($arg)+) };
+}
+
+/// Logs a message at the trace level.
+///
+/// The arguments are the same as those of [`log::trace!`].
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::__log_static!($crate::Level::Trace, $($arg)+) };
+}
 
 /// Prints to the console.
 ///
@@ -80,31 +264,70 @@ macro_rules! ax_println {
     }
 }
 
-macro_rules! with_color {
-    ($color_code:expr, $($arg:tt)*) => {{
-        format_args!("\u{1B}[{}m{}\u{1B}[m", $color_code as u8, format_args!($($arg)*))
+/// Runs an operation in a `trace` level [`span!`], and logs its arguments,
+/// result and duration when it exits.
+///
+/// Records logged by the operation, e.g., by the operations it calls, are
+/// indented inside the span. The operation is given as a closure, so `?` and
+/// `return` inside it only leave the operation itself. The result must
+/// implement [`Debug`].
+///
+/// [`Debug`]: core::fmt::Debug
+///
+/// # Examples
+///
+/// ```
+/// let sum = axlog::trace_op!("add", || 1 + 2, "a={}, b={}", 1, 2);
+/// assert_eq!(sum, 3);
+/// ```
+#[macro_export]
+macro_rules! trace_op {
+    ($op:expr, $body:expr) => {
+        $crate::trace_op!($op, $body, "")
+    };
+    ($op:expr, $body:expr, $($arg:tt)+) => {{
+        let __span = $crate::span!($crate::Level::Trace, $op);
+        let __ret = ($body)();
+        __span.exit_with(format_args!("({}) -> {:?}", format_args!($($arg)+), __ret));
+        __ret
     }};
 }
 
-#[repr(u8)]
-#[allow(dead_code)]
-enum ColorCode {
-    Black = 30,
-    Red = 31,
-    Green = 32,
-    Yellow = 33,
-    Blue = 34,
-    Magenta = 35,
-    Cyan = 36,
-    White = 37,
-    BrightBlack = 90,
-    BrightRed = 91,
-    BrightGreen = 92,
-    BrightYellow = 93,
-    BrightBlue = 94,
-    BrightMagenta = 95,
-    BrightCyan = 96,
-    BrightWhite = 97,
+/// Enters a span, returning a [`Span`] guard that logs when the span is
+/// entered and exited, with the time spent in it.
+///
+/// Records logged by the same task while the guard is alive are indented
+/// one level further. The span is logged at the `debug` level unless
+/// another level is given first.
+///
+/// # Examples
+///
+/// ```
+/// use axlog::{info, span, Level};
+///
+/// fn init_drivers() {
+///     let _span = span!("init_drivers");
+///     info!("found 2 block devices"); // indented
+/// }
+///
+/// let _span = span!(Level::Info, "boot");
+/// init_drivers();
+/// ```
+#[macro_export]
+macro_rules! span {
+    ($name:expr) => {
+        $crate::span!($crate::Level::Debug, $name)
+    };
+    ($lvl:expr, $name:expr) => {
+        $crate::Span::enter(
+            $name,
+            $lvl,
+            $crate::__static_enabled!($lvl),
+            module_path!(),
+            file!(),
+            line!(),
+        )
+    };
 }
 
 /// Extern interfaces that must be implemented in other crates.
@@ -127,6 +350,66 @@ pub trait LogIf {
     fn current_task_id() -> Option<u64>;
 }
 
+/// Capabilities of the console, set with [`set_console_caps`].
+///
+/// The logger does not emit color escape sequences that the console does
+/// not support, whatever the [`Theme`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsoleCaps {
+    /// Whether the console understands ANSI color escape sequences.
+    pub supports_ansi_color: bool,
+    /// Whether the console understands 24-bit color escape sequences, as
+    /// used by [`Theme::Rainbow`].
+    pub supports_truecolor: bool,
+    /// Width of the console in characters, if known.
+    pub line_width: Option<usize>,
+}
+
+impl Default for ConsoleCaps {
+    fn default() -> Self {
+        Self {
+            supports_ansi_color: true,
+            supports_truecolor: true,
+            line_width: None,
+        }
+    }
+}
+
+const CAPS_ANSI_COLOR: u8 = 1 << 0;
+const CAPS_TRUECOLOR: u8 = 1 << 1;
+
+/// Flags of the [`ConsoleCaps`] set with [`set_console_caps`].
+static CAPS_FLAGS: AtomicU8 = AtomicU8::new(CAPS_ANSI_COLOR | CAPS_TRUECOLOR);
+/// Line width of the console, or `usize::MAX` if unknown.
+static CAPS_LINE_WIDTH: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Sets the capabilities of the console.
+///
+/// Until they are set, they are the [default](ConsoleCaps::default), which
+/// assumes a terminal that supports all colors.
+pub fn set_console_caps(caps: ConsoleCaps) {
+    let mut flags = 0;
+    if caps.supports_ansi_color {
+        flags |= CAPS_ANSI_COLOR;
+    }
+    if caps.supports_truecolor {
+        flags |= CAPS_TRUECOLOR;
+    }
+    CAPS_FLAGS.store(flags, Ordering::Relaxed);
+    CAPS_LINE_WIDTH.store(caps.line_width.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// Returns the capabilities of the console, set with [`set_console_caps`].
+pub fn console_caps() -> ConsoleCaps {
+    let flags = CAPS_FLAGS.load(Ordering::Relaxed);
+    let line_width = CAPS_LINE_WIDTH.load(Ordering::Relaxed);
+    ConsoleCaps {
+        supports_ansi_color: flags & CAPS_ANSI_COLOR != 0,
+        supports_truecolor: flags & CAPS_TRUECOLOR != 0,
+        line_width: (line_width != usize::MAX).then_some(line_width),
+    }
+}
+
 struct Logger;
 
 impl Write for Logger {
@@ -154,83 +437,208 @@ impl Log for Logger {
         }
 
         let level = record.level();
-        let line = record.line().unwrap_or(0);
-        let path = record.target();
-        let args_color = match level {
-            Level::Error => ColorCode::Red,
-            Level::Warn => ColorCode::Yellow,
-            Level::Info => ColorCode::Green,
-            Level::Debug => ColorCode::Cyan,
-            Level::Trace => ColorCode::BrightBlack,
-        };
+        if !is_wanted(level, record.target()) {
+            return;
+        }
 
-        cfg_if::cfg_if! {
-            if #[cfg(feature = "std")] {
-                __print_impl(with_color!(
-                    ColorCode::White,
-                    "[{time} {path}:{line}] {args}\n",
-                    time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.6f"),
-                    path = path,
-                    line = line,
-                    args = with_color!(args_color, "{}", record.args()),
-                ));
-            } else {
-                let cpu_id = call_interface!(LogIf::current_cpu_id);
-                let tid = call_interface!(LogIf::current_task_id);
-                let now = call_interface!(LogIf::current_time);
-                if let Some(cpu_id) = cpu_id {
-                    if let Some(tid) = tid {
-                        // show CPU ID and task ID
-                        __print_impl(with_color!(
-                            ColorCode::White,
-                            "[{:>3}.{:06} {cpu_id}:{tid} {path}:{line}] {args}\n",
-                            now.as_secs(),
-                            now.subsec_micros(),
-                            cpu_id = cpu_id,
-                            tid = tid,
-                            path = path,
-                            line = line,
-                            args = with_color!(args_color, "{}", record.args()),
-                        ));
-                    } else {
-                        // show CPU ID only
-                        __print_impl(with_color!(
-                            ColorCode::White,
-                            "[{:>3}.{:06} {cpu_id} {path}:{line}] {args}\n",
-                            now.as_secs(),
-                            now.subsec_micros(),
-                            cpu_id = cpu_id,
-                            path = path,
-                            line = line,
-                            args = with_color!(args_color, "{}", record.args()),
-                        ));
-                    }
-                } else {
-                    // neither CPU ID nor task ID is shown
-                    __print_impl(with_color!(
-                        ColorCode::White,
-                        "[{:>3}.{:06} {path}:{line}] {args}\n",
-                        now.as_secs(),
-                        now.subsec_micros(),
-                        path = path,
-                        line = line,
-                        args = with_color!(args_color, "{}", record.args()),
-                    ));
-                }
-            }
+        stats::count_record(level, record.target());
+        let now = current_time();
+        let dedup = ratelimit::dedup(record, now);
+        if let Some((level, repeated)) = dedup.repeated {
+            write_record(
+                &Record::builder()
+                    .level(level)
+                    .target(module_path!())
+                    .args(format_args!("last message repeated {} times", repeated))
+                    .build(),
+                now,
+            );
+        }
+        if dedup.emit {
+            write_record(record, now);
+        } else {
+            stats::count_suppressed();
+        }
+    }
+
+    fn flush(&self) {
+        flush();
+    }
+}
+
+/// Returns whether a record is needed by either the console, the ring buffer
+/// or a sink.
+fn is_wanted(level: Level, target: &str) -> bool {
+    level <= console_level(target)
+        || level <= ring::ring_buffer_level()
+        || level <= sink::max_sink_level()
+}
+
+/// Writes a record logged before [`init`] to its destinations, with the time
+/// when it was logged.
+fn replay_record(record: &Record, time: core::time::Duration) {
+    if is_wanted(record.level(), record.target()) {
+        stats::count_record(record.level(), record.target());
+        write_record(record, time);
+    }
+}
+
+/// Writes a record to its destinations, indented by the spans it is in and
+/// truncated to the [`max_record_len`].
+fn write_record(record: &Record, time: core::time::Duration) {
+    let depth = span::depth();
+    let max_len = format::max_record_len();
+    if depth == 0 && max_len == usize::MAX {
+        return write_formatted(record, time);
+    }
+    write_formatted(
+        &Record::builder()
+            .metadata(record.metadata().clone())
+            .module_path(record.module_path())
+            .file(record.file())
+            .line(record.line())
+            .args(format_args!(
+                "{:width$}{}",
+                "",
+                format::Truncated::new(record.args(), max_len),
+                width = depth * span::INDENT_WIDTH
+            ))
+            .build(),
+        time,
+    )
+}
+
+/// Formats a record and writes it to its destinations.
+fn write_formatted(record: &Record, time: core::time::Duration) {
+    let (cpu_id, tid) = cpu_and_task_id();
+    let info = RecordInfo {
+        record,
+        time,
+        wall_time: current_wall_time(),
+        cpu_id,
+        tid,
+    };
+    let level = record.level();
+    let printed = level <= console_level(record.target());
+    emit(
+        level,
+        record.target(),
+        cpu_id,
+        // In binary mode, the console gets the binary record instead.
+        printed && !binary::write_record(&info),
+        printed,
+        format_args!("{}\n", format::Formatted::new(&info)),
+    );
+}
+
+/// Writes a formatted record to its destinations: if `to_console` is set, to
+/// the console (or its staging buffer in deferred mode), to the sinks that
+/// select it, and to the ring buffer if it is `printed` to the console or
+/// within the ring buffer level.
+fn emit(
+    level: Level,
+    target: &str,
+    cpu_id: Option<usize>,
+    to_console: bool,
+    printed: bool,
+    record: fmt::Arguments,
+) {
+    if to_console {
+        if deferred::is_deferred() {
+            deferred::stage(cpu_id, record);
+        } else {
+            let _ = console::write_fmt(record);
         }
     }
+    sink::dispatch(level, target, printed, record);
+    if printed || level <= ring::ring_buffer_level() {
+        ring::capture(level, record);
+    }
+}
 
-    fn flush(&self) {}
+/// Writes out all buffered log records.
+///
+/// This prints the records staged in deferred mode (see [`set_deferred`]) and
+/// flushes all registered sinks. It is called from the idle loop.
+pub fn flush() {
+    deferred::flush_staged();
+    sink::flush();
+}
+
+/// Returns the IDs of the current CPU and task, if known.
+fn cpu_and_task_id() -> (Option<usize>, Option<u64>) {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "std")] {
+            (None, None)
+        } else {
+            (
+                call_interface!(LogIf::current_cpu_id),
+                call_interface!(LogIf::current_task_id),
+            )
+        }
+    }
+}
+
+/// Returns the current time, as used in log headers.
+///
+/// In the `std` environment it is the time since the UNIX epoch, otherwise it
+/// comes from [`LogIf::current_time`].
+pub fn current_time() -> core::time::Duration {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "std")] {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+        } else {
+            call_interface!(LogIf::current_time)
+        }
+    }
+}
+
+/// The wall clock set with [`set_wall_clock`], a `fn() -> Option<(u64, u32)>`
+/// or null.
+static WALL_CLOCK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets the function that gets the current wall-clock time since the UNIX
+/// epoch, as seconds and nanoseconds.
+///
+/// The function returns [`None`] if the time is unknown (e.g., there is no
+/// RTC). Until a wall clock is set, the log shows the clock time from
+/// [`LogIf::current_time`] instead. It has no effect in the `std`
+/// environment, which uses the system time.
+pub fn set_wall_clock(clock: fn() -> Option<(u64, u32)>) {
+    WALL_CLOCK.store(clock as *mut (), Ordering::Release);
+}
+
+/// Returns the current wall-clock time since the UNIX epoch, if known.
+///
+/// In the `std` environment it is the system time, otherwise it comes from
+/// the clock set with [`set_wall_clock`].
+pub fn current_wall_time() -> Option<core::time::Duration> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "std")] {
+            Some(current_time())
+        } else {
+            let clock = WALL_CLOCK.load(Ordering::Acquire);
+            if clock.is_null() {
+                return None;
+            }
+            // SAFETY: only `set_wall_clock` stores non-null pointers, which are
+            // functions of this type.
+            let clock: fn() -> Option<(u64, u32)> = unsafe { core::mem::transmute(clock) };
+            clock().map(|(secs, nanos)| core::time::Duration::new(secs, nanos))
+        }
+    }
 }
 
 /// Prints the formatted string to the console.
+///
+/// Unlike log records, which are dropped when the console can't keep up (see
+/// [`console_dropped_bytes`]), the output waits for the console to be idle and
+/// is never interleaved with other output. That is, unless the console is
+/// busy on the current CPU, e.g., when printing from an interrupt handler.
 pub fn print_fmt(args: fmt::Arguments) -> fmt::Result {
-    use kspin::SpinNoIrq; // TODO: more efficient
-    static LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
-
-    let _guard = LOCK.lock();
-    Logger.write_fmt(args)
+    console::write_fmt_blocking(args)
 }
 
 #[doc(hidden)]
@@ -240,11 +648,70 @@ pub fn __print_impl(args: fmt::Arguments) {
 
 /// Initializes the logger.
 ///
-/// This function should be called before any log macros are used, otherwise
-/// nothing will be printed.
+/// The maximum log level is set to `warn`, unless it has already been set
+/// with [`set_max_level`].
+///
+/// The records logged with the macros of this crate before this function is
+/// called are kept in a buffer of [`EARLY_BUFFER_SIZE`] bytes, and written
+/// out now, followed by a warning if some did not fit. Records logged with
+/// the `log` crate directly before are lost.
 pub fn init() {
     log::set_logger(&Logger).unwrap();
-    log::set_max_level(LevelFilter::Warn);
+    if !LEVEL_SET.load(Ordering::Relaxed) {
+        set_max_level_filter(LevelFilter::Warn);
+    }
+    let dropped = early::replay(|level, time, target, message| {
+        replay_record(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{}", message))
+                .build(),
+            time,
+        )
+    });
+    if dropped > 0 {
+        stats::count_early_dropped(dropped);
+        warn!(
+            "{} records logged before the logger was initialized were dropped",
+            dropped
+        );
+    }
+}
+
+static CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+
+/// Whether the maximum level has been set, so that [`init`] keeps it.
+static LEVEL_SET: AtomicBool = AtomicBool::new(false);
+
+fn level_filter_from_usize(level: usize) -> LevelFilter {
+    LevelFilter::iter().nth(level).unwrap_or(LevelFilter::Trace)
+}
+
+/// Returns the maximum console log level of `target`.
+fn console_level(target: &str) -> LevelFilter {
+    filter::target_level(target).unwrap_or_else(max_level)
+}
+
+/// Lets through the `log` crate every record needed by either the console,
+/// the ring buffer or a sink.
+fn update_max_level() {
+    log::set_max_level(
+        max_level()
+            .max(filter::max_target_level())
+            .max(ring::ring_buffer_level())
+            .max(sink::max_sink_level()),
+    );
+}
+
+/// The error returned by [`set_max_level`] for an unknown level name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidLevel;
+
+impl fmt::Display for InvalidLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid log level, expected one of off, error, warn, info, debug, trace")
+    }
 }
 
 /// Set the maximum log level.
@@ -253,10 +720,48 @@ pub fn init() {
 /// this way incurs runtime overhead. In addition, this function is no effect
 /// when those features are enabled.
 ///
-/// `level` should be one of `off`, `error`, `warn`, `info`, `debug`, `trace`.
-pub fn set_max_level(level: &str) {
-    let lf = LevelFilter::from_str(level)
-        .ok()
-        .unwrap_or(LevelFilter::Off);
-    log::set_max_level(lf);
+/// `level` should be one of `off`, `error`, `warn`, `info`, `debug`, `trace`
+/// (case-insensitive), otherwise the level is left unchanged and
+/// [`InvalidLevel`] is returned.
+pub fn set_max_level(level: &str) -> Result<(), InvalidLevel> {
+    let lf = LevelFilter::from_str(level).map_err(|_| InvalidLevel)?;
+    set_max_level_filter(lf);
+    Ok(())
+}
+
+/// Set the maximum log level, see [`set_max_level`].
+pub fn set_max_level_filter(level: LevelFilter) {
+    CONSOLE_LEVEL.store(level as usize, Ordering::Relaxed);
+    LEVEL_SET.store(true, Ordering::Relaxed);
+    update_max_level();
+}
+
+/// Returns the maximum log level of the console, as set by
+/// [`set_max_level`]. Targets may override it, see [`set_target_level`].
+pub fn max_level() -> LevelFilter {
+    level_filter_from_usize(CONSOLE_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Returns whether records at `level` are logged anywhere (to the console, the
+/// ring buffer or a sink).
+///
+/// This can guard the construction of expensive log arguments:
+///
+/// ```
+/// use axlog::{debug, Level};
+///
+/// if axlog::level_enabled(Level::Debug) {
+///     let sum: u64 = (0..1000).sum();
+///     debug!("sum = {}", sum);
+/// }
+/// ```
+pub fn level_enabled(level: Level) -> bool {
+    level <= log::STATIC_MAX_LEVEL && level <= log::max_level()
+}
+
+/// Serializes the tests that change the global logging configuration.
+#[cfg(test)]
+fn lock_config() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
 }
@@ -0,0 +1,56 @@
+//! Reporting of panics.
+
+use core::panic::PanicInfo;
+
+use log::Level;
+
+use crate::{console, deferred, ring, Theme};
+
+const BANNER_WIDTH: usize = 64;
+
+/// Prints a report of a panic to the console, regardless of the log level.
+///
+/// Buffered records are written out first, then the report is written
+/// synchronously, in a red banner with the IDs of the current CPU and task.
+/// It is also captured in the ring buffer. This is meant to be called from
+/// panic handlers:
+///
+/// ```ignore
+/// #[panic_handler]
+/// fn panic(info: &PanicInfo) -> ! {
+///     axlog::panic_report(info);
+///     axhal::misc::terminate()
+/// }
+/// ```
+pub fn panic_report(info: &PanicInfo) {
+    deferred::flush_staged();
+    console::drain();
+
+    let (cpu_id, tid) = crate::cpu_and_task_id();
+    let (red, reset) = if crate::theme::effective_theme() == Theme::Plain {
+        ("", "")
+    } else {
+        ("\u{1B}[1;31m", "\u{1B}[m")
+    };
+    console::write_fmt_direct(format_args!(
+        "{red}{:=^width$}\n",
+        " PANIC ",
+        width = BANNER_WIDTH
+    ));
+    match (cpu_id, tid) {
+        (Some(cpu_id), Some(tid)) => {
+            console::write_fmt_direct(format_args!("CPU {}, task {}\n", cpu_id, tid))
+        }
+        (Some(cpu_id), None) => console::write_fmt_direct(format_args!("CPU {}\n", cpu_id)),
+        _ => {}
+    }
+    console::write_fmt_direct(format_args!(
+        "{}\n{:=<width$}{reset}\n",
+        info,
+        "",
+        width = BANNER_WIDTH
+    ));
+
+    ring::capture(Level::Error, format_args!("{}\n", info));
+    crate::flush();
+}
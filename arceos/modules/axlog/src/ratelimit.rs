@@ -0,0 +1,299 @@
+//! Rate limiting of log records and suppression of repeated ones.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+use log::{Level, Record};
+
+/// Logs a message at most once per `interval` at this call site.
+///
+/// The arguments after the interval are the same as those of [`log::log!`].
+/// Messages dropped in between are counted and reported with the next one
+/// that gets through.
+///
+/// # Examples
+///
+/// ```
+/// use core::time::Duration;
+/// use axlog::{log_ratelimited, Level};
+///
+/// for i in 0..1000 {
+///     log_ratelimited!(Duration::from_secs(1), Level::Error, "device timeout #{}", i);
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_ratelimited {
+    ($interval:expr, $lvl:expr, $($arg:tt)+) => {{
+        static __LIMIT: $crate::__RateLimit = $crate::__RateLimit::new();
+        let lvl = $lvl;
+        if $crate::__static_enabled!(lvl) && $crate::__log_enabled!(lvl) {
+            match __LIMIT.check($interval) {
+                Some(0) => $crate::__log!(lvl, $($arg)+),
+                Some(suppressed) => $crate::__log!(
+                    lvl,
+                    "{} ({} similar messages suppressed)",
+                    format_args!($($arg)+),
+                    suppressed,
+                ),
+                None => {}
+            }
+        }
+    }};
+}
+
+/// Logs a message only the first time this call site is reached.
+///
+/// The arguments are the same as those of [`log::log!`]. A call site reached
+/// while the level is disabled does not count as reached.
+///
+/// # Examples
+///
+/// ```
+/// use axlog::{log_once, Level};
+///
+/// for _ in 0..3 {
+///     log_once!(Level::Warn, "printed only once");
+/// }
+/// ```
+#[macro_export]
+macro_rules! log_once {
+    ($lvl:expr, $($arg:tt)+) => {{
+        static __LOGGED: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+        let lvl = $lvl;
+        if $crate::__static_enabled!(lvl)
+            && $crate::__log_enabled!(lvl)
+            && !__LOGGED.swap(true, core::sync::atomic::Ordering::Relaxed)
+        {
+            $crate::__log!(lvl, $($arg)+);
+        }
+    }};
+}
+
+/// Logs a message at the error level, only the first time this call site is
+/// reached. See [`log_once!`].
+#[macro_export]
+macro_rules! error_once {
+    ($($arg:tt)+) => { $crate::log_once!($crate::Level::Error, $($arg)+) };
+}
+
+/// Logs a message at the warn level, only the first time this call site is
+/// reached. See [`log_once!`].
+#[macro_export]
+macro_rules! warn_once {
+    ($($arg:tt)+) => { $crate::log_once!($crate::Level::Warn, $($arg)+) };
+}
+
+/// Logs a message at the info level, only the first time this call site is
+/// reached. See [`log_once!`].
+#[macro_export]
+macro_rules! info_once {
+    ($($arg:tt)+) => { $crate::log_once!($crate::Level::Info, $($arg)+) };
+}
+
+/// Logs a message at the debug level, only the first time this call site is
+/// reached. See [`log_once!`].
+#[macro_export]
+macro_rules! debug_once {
+    ($($arg:tt)+) => { $crate::log_once!($crate::Level::Debug, $($arg)+) };
+}
+
+/// Logs a message at the trace level, only the first time this call site is
+/// reached. See [`log_once!`].
+#[macro_export]
+macro_rules! trace_once {
+    ($($arg:tt)+) => { $crate::log_once!($crate::Level::Trace, $($arg)+) };
+}
+
+#[doc(hidden)]
+pub struct __RateLimit {
+    /// Time of the last logged message in microseconds, [`u64::MAX`] if none.
+    last: AtomicU64,
+    suppressed: AtomicUsize,
+}
+
+impl __RateLimit {
+    pub const fn new() -> Self {
+        Self {
+            last: AtomicU64::new(u64::MAX),
+            suppressed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of messages suppressed since the last one if a
+    /// message can be logged now, otherwise counts it as suppressed.
+    pub fn check(&self, interval: Duration) -> Option<usize> {
+        let now = crate::current_time().as_micros() as u64;
+        let last = self.last.load(Ordering::Relaxed);
+        let allowed = (last == u64::MAX || now.saturating_sub(last) >= interval.as_micros() as u64)
+            && self
+                .last
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok();
+        if allowed {
+            Some(self.suppressed.swap(0, Ordering::Relaxed))
+        } else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+impl Default for __RateLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Report interval of repeated records in microseconds, 0 if suppression of
+/// repeated records is disabled.
+static DEDUP_INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+struct DedupState {
+    /// Hash of the last record.
+    hash: u64,
+    level: Level,
+    /// Times the last record has been repeated since it was last reported.
+    repeated: usize,
+    /// Time when the last record was printed or its repetitions reported.
+    last_report: Duration,
+}
+
+static DEDUP: SpinNoIrq<DedupState> = SpinNoIrq::new(DedupState {
+    hash: 0,
+    level: Level::Trace,
+    repeated: 0,
+    last_report: Duration::ZERO,
+});
+
+/// Enables or disables suppression of repeated log records.
+///
+/// When enabled, a record identical to the previous one (same level, location
+/// and message) is not printed. Instead, a `last message repeated N times`
+/// line is printed every `interval` while it keeps repeating, and once a
+/// different record arrives. Disabled (`None`) by default.
+pub fn set_duplicate_suppression(interval: Option<Duration>) {
+    let micros = interval.map_or(0, |i| (i.as_micros() as u64).max(1));
+    DEDUP_INTERVAL.store(micros, Ordering::Relaxed);
+}
+
+/// What to do with a record after duplicate suppression.
+pub(crate) struct Dedup {
+    /// Level and count of repetitions of the previous record to report first.
+    pub repeated: Option<(Level, usize)>,
+    /// Whether the record itself should be printed.
+    pub emit: bool,
+}
+
+pub(crate) fn dedup(record: &Record, now: Duration) -> Dedup {
+    let interval = DEDUP_INTERVAL.load(Ordering::Relaxed);
+    if interval == 0 {
+        return Dedup {
+            repeated: None,
+            emit: true,
+        };
+    }
+
+    let hash = record_hash(record);
+    let mut state = DEDUP.lock();
+    if hash == state.hash && state.level == record.level() {
+        state.repeated += 1;
+        let repeated = if now.saturating_sub(state.last_report).as_micros() as u64 >= interval {
+            state.last_report = now;
+            Some((state.level, core::mem::take(&mut state.repeated)))
+        } else {
+            None
+        };
+        Dedup {
+            repeated,
+            emit: false,
+        }
+    } else {
+        let repeated = (state.repeated > 0).then_some((state.level, state.repeated));
+        *state = DedupState {
+            hash,
+            level: record.level(),
+            repeated: 0,
+            last_report: now,
+        };
+        Dedup {
+            repeated,
+            emit: true,
+        }
+    }
+}
+
+/// FNV-1a hash of the location and message of a record.
+fn record_hash(record: &Record) -> u64 {
+    struct Fnv(u64);
+
+    impl Write for Fnv {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for b in s.bytes() {
+                self.0 = (self.0 ^ b as u64).wrapping_mul(0x100_0000_01b3);
+            }
+            Ok(())
+        }
+    }
+
+    let mut h = Fnv(0xcbf2_9ce4_8422_2325);
+    let _ = write!(
+        h,
+        "{}:{}:{}",
+        record.target(),
+        record.line().unwrap_or(0),
+        record.args()
+    );
+    h.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit() {
+        let limit = __RateLimit::new();
+        let hour = Duration::from_secs(3600);
+        assert_eq!(limit.check(hour), Some(0));
+        assert_eq!(limit.check(hour), None);
+        assert_eq!(limit.check(hour), None);
+        // The suppressed messages are reported with the next one.
+        assert_eq!(limit.check(Duration::ZERO), Some(2));
+        assert_eq!(limit.check(Duration::ZERO), Some(0));
+    }
+
+    #[test]
+    fn duplicates() {
+        let _config = crate::lock_config();
+        let check = |line, level, ms| {
+            let d = dedup(
+                &Record::builder()
+                    .level(level)
+                    .target("axfs")
+                    .line(Some(line))
+                    .args(format_args!("message"))
+                    .build(),
+                Duration::from_millis(ms),
+            );
+            (d.repeated, d.emit)
+        };
+
+        // Disabled.
+        assert_eq!(check(1, Level::Info, 0), (None, true));
+        assert_eq!(check(1, Level::Info, 0), (None, true));
+
+        set_duplicate_suppression(Some(Duration::from_secs(1)));
+        assert_eq!(check(2, Level::Info, 0), (None, true));
+        assert_eq!(check(2, Level::Info, 10), (None, false));
+        assert_eq!(check(2, Level::Info, 20), (None, false));
+        // Reported every interval while repeating.
+        assert_eq!(check(2, Level::Info, 1000), (Some((Level::Info, 3)), false));
+        assert_eq!(check(2, Level::Info, 1500), (None, false));
+        // Then when another record comes, even if only its level differs.
+        assert_eq!(check(2, Level::Warn, 1600), (Some((Level::Info, 1)), true));
+        assert_eq!(check(3, Level::Warn, 1700), (None, true));
+        set_duplicate_suppression(None);
+    }
+}
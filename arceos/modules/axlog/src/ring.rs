@@ -0,0 +1,329 @@
+//! In-memory ring buffer of recent log records, similar to the kernel log
+//! buffer read by `dmesg`.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+use log::{Level, LevelFilter};
+
+/// Size in bytes of the log ring buffer.
+pub const RING_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Maximum length in bytes of the text of a record in the ring buffer. Longer
+/// records are truncated.
+pub const MAX_RING_RECORD_LEN: usize = 512;
+
+/// Length of the per-record header: text length (`u16`), level (`u8`) and
+/// timestamp in microseconds (`u64`).
+const HEADER_LEN: usize = 11;
+
+/// A log record read from the ring buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct RingRecord<'a> {
+    /// Sequence number of the record, starting from 0 at boot.
+    pub seq: u64,
+    /// Level of the record.
+    pub level: Level,
+    /// Time when the record was captured, as returned by
+    /// [`current_time`](crate::current_time).
+    pub time: Duration,
+    /// Formatted record, without color escape sequences.
+    pub text: &'a str,
+}
+
+/// Records are stored back to back as a header followed by the text. Byte
+/// positions grow monotonically and are reduced modulo the buffer size only
+/// when indexing, so a position stays valid until its record is evicted.
+struct RingBuffer {
+    buf: [u8; RING_BUFFER_SIZE],
+    /// Position of the oldest record.
+    head: u64,
+    /// Position where the next record will be written.
+    tail: u64,
+    /// Sequence number of the oldest record.
+    first_seq: u64,
+    /// Sequence number of the next record.
+    next_seq: u64,
+}
+
+impl RingBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: [0; RING_BUFFER_SIZE],
+            head: 0,
+            tail: 0,
+            first_seq: 0,
+            next_seq: 0,
+        }
+    }
+
+    fn copy_in(&mut self, pos: u64, data: &[u8]) {
+        let start = (pos % RING_BUFFER_SIZE as u64) as usize;
+        let first = data.len().min(RING_BUFFER_SIZE - start);
+        self.buf[start..start + first].copy_from_slice(&data[..first]);
+        self.buf[..data.len() - first].copy_from_slice(&data[first..]);
+    }
+
+    fn copy_out(&self, pos: u64, data: &mut [u8]) {
+        let start = (pos % RING_BUFFER_SIZE as u64) as usize;
+        let first = data.len().min(RING_BUFFER_SIZE - start);
+        data[..first].copy_from_slice(&self.buf[start..start + first]);
+        let rest = data.len() - first;
+        data[first..].copy_from_slice(&self.buf[..rest]);
+    }
+
+    fn header(&self, pos: u64) -> (usize, Level, Duration) {
+        let mut header = [0; HEADER_LEN];
+        self.copy_out(pos, &mut header);
+        let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+        let level = level_from_u8(header[2]);
+        let mut micros = [0; 8];
+        micros.copy_from_slice(&header[3..]);
+        (
+            len,
+            level,
+            Duration::from_micros(u64::from_le_bytes(micros)),
+        )
+    }
+
+    fn push(&mut self, level: Level, time: Duration, text: &[u8]) {
+        let total = (HEADER_LEN + text.len()) as u64;
+        while self.tail - self.head + total > RING_BUFFER_SIZE as u64 {
+            let (len, ..) = self.header(self.head);
+            self.head += (HEADER_LEN + len) as u64;
+            self.first_seq += 1;
+        }
+
+        let mut header = [0; HEADER_LEN];
+        header[..2].copy_from_slice(&(text.len() as u16).to_le_bytes());
+        header[2] = level as u8;
+        header[3..].copy_from_slice(&(time.as_micros() as u64).to_le_bytes());
+        self.copy_in(self.tail, &header);
+        self.copy_in(self.tail + HEADER_LEN as u64, text);
+        self.tail += total;
+        self.next_seq += 1;
+    }
+
+    /// Returns the sequence number and position of the first record whose
+    /// sequence number is at least `seq`.
+    fn locate(&self, seq: u64) -> Option<(u64, u64)> {
+        if seq >= self.next_seq {
+            return None;
+        }
+        let (mut cur, mut pos) = (self.first_seq, self.head);
+        while cur < seq {
+            let (len, ..) = self.header(pos);
+            pos += (HEADER_LEN + len) as u64;
+            cur += 1;
+        }
+        Some((cur, pos))
+    }
+}
+
+fn level_from_u8(level: u8) -> Level {
+    match level {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+static RING: SpinNoIrq<RingBuffer> = SpinNoIrq::new(RingBuffer::new());
+
+static RING_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+
+/// Sets the level up to which records are captured in the ring buffer, even
+/// if they are not printed to the console.
+///
+/// The ring buffer always captures the records printed to the console, so the
+/// default [`LevelFilter::Off`] captures nothing more.
+pub fn set_ring_buffer_level(level: LevelFilter) {
+    RING_LEVEL.store(level as usize, Ordering::Relaxed);
+    crate::update_max_level();
+}
+
+pub(crate) fn ring_buffer_level() -> LevelFilter {
+    crate::level_filter_from_usize(RING_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Formats a record into a fixed buffer, dropping color escape sequences and
+/// truncating it to [`MAX_RING_RECORD_LEN`] bytes.
+struct RecordWriter {
+    buf: [u8; MAX_RING_RECORD_LEN],
+    len: usize,
+    in_escape: bool,
+}
+
+impl Write for RecordWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.in_escape {
+                // CSI sequences end with a byte in `@`..=`~`, except `[` itself.
+                self.in_escape = c == '[' || !('@'..='~').contains(&c);
+            } else if c == '\u{1B}' {
+                self.in_escape = true;
+            } else if self.len + c.len_utf8() <= MAX_RING_RECORD_LEN {
+                c.encode_utf8(&mut self.buf[self.len..]);
+                self.len += c.len_utf8();
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn capture(level: Level, record: fmt::Arguments) {
+    let time = crate::current_time();
+    let mut w = RecordWriter {
+        buf: [0; MAX_RING_RECORD_LEN],
+        len: 0,
+        in_escape: false,
+    };
+    // `RecordWriter` never fails, it truncates instead.
+    let _ = w.write_fmt(record);
+    RING.lock().push(level, time, &w.buf[..w.len]);
+}
+
+/// Calls `f` on each record in the ring buffer whose sequence number is at
+/// least `seq`, from the oldest to the newest.
+///
+/// Records that have already been overwritten are skipped. Returns the
+/// sequence number to pass to the next call to only read new records.
+///
+/// The ring buffer is not locked while `f` runs, so `f` may log.
+pub fn read_since(seq: u64, mut f: impl FnMut(&RingRecord)) -> u64 {
+    let mut seq = seq;
+    let mut text = [0; MAX_RING_RECORD_LEN];
+    loop {
+        let (level, time, len) = {
+            let ring = RING.lock();
+            let Some((cur, pos)) = ring.locate(seq) else {
+                return seq;
+            };
+            seq = cur;
+            let (len, level, time) = ring.header(pos);
+            ring.copy_out(pos + HEADER_LEN as u64, &mut text[..len]);
+            (level, time, len)
+        };
+        // Records are built from `str`s and truncated at char boundaries.
+        let text = core::str::from_utf8(&text[..len]).unwrap_or_default();
+        f(&RingRecord {
+            seq,
+            level,
+            time,
+            text,
+        });
+        seq += 1;
+    }
+}
+
+/// Writes all records in the ring buffer to `w`, from the oldest to the
+/// newest.
+///
+/// # Examples
+///
+/// ```
+/// axlog::init();
+/// axlog::set_max_level("info").unwrap();
+/// axlog::info!("hello");
+///
+/// let mut dmesg = String::new();
+/// axlog::dump_ring_buffer(&mut dmesg).unwrap();
+/// assert!(dmesg.ends_with("hello\n"));
+/// ```
+pub fn dump_ring_buffer(w: &mut dyn Write) -> fmt::Result {
+    let mut res = Ok(());
+    read_since(0, |record| {
+        if res.is_ok() {
+            res = w.write_str(record.text);
+        }
+    });
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+    use std::string::String;
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn records(ring: &RingBuffer) -> Vec<(u64, Level, u64, String)> {
+        let mut records = Vec::new();
+        let mut seq = 0;
+        while let Some((cur, pos)) = ring.locate(seq) {
+            let (len, level, time) = ring.header(pos);
+            let mut text = vec![0; len];
+            ring.copy_out(pos + HEADER_LEN as u64, &mut text);
+            let text = String::from_utf8(text).unwrap();
+            records.push((cur, level, time.as_micros() as u64, text));
+            seq = cur + 1;
+        }
+        records
+    }
+
+    #[test]
+    fn push_and_locate() {
+        let mut ring = Box::new(RingBuffer::new());
+        assert!(ring.locate(0).is_none());
+        ring.push(Level::Warn, Duration::from_micros(5), b"first\n");
+        ring.push(Level::Debug, Duration::from_micros(7), b"second\n");
+        assert_eq!(
+            records(&ring),
+            [
+                (0, Level::Warn, 5, "first\n".into()),
+                (1, Level::Debug, 7, "second\n".into()),
+            ]
+        );
+        assert_eq!(ring.locate(1).map(|(seq, _)| seq), Some(1));
+        assert!(ring.locate(2).is_none());
+    }
+
+    #[test]
+    fn eviction_and_wrap_around() {
+        let mut ring = Box::new(RingBuffer::new());
+        let text = [b'x'; 100];
+        let per_ring = RING_BUFFER_SIZE / (HEADER_LEN + text.len());
+        let count = 3 * per_ring as u64 + 7;
+        for i in 0..count {
+            ring.push(Level::Info, Duration::from_micros(i), &text);
+        }
+        assert!(ring.tail - ring.head <= RING_BUFFER_SIZE as u64);
+
+        // The oldest records are evicted, the others survive the wrap around.
+        let records = records(&ring);
+        assert_eq!(records.len(), per_ring);
+        assert_eq!(records[0].0, count - per_ring as u64);
+        assert_eq!(ring.first_seq, records[0].0);
+        for (seq, level, time, text) in &records {
+            assert_eq!((*level, *time), (Level::Info, *seq));
+            assert_eq!(text.as_bytes(), &[b'x'; 100]);
+        }
+        // Evicted records are skipped.
+        assert_eq!(ring.locate(0).map(|(seq, _)| seq), Some(records[0].0));
+    }
+
+    #[test]
+    fn record_writer() {
+        let mut w = RecordWriter {
+            buf: [0; MAX_RING_RECORD_LEN],
+            len: 0,
+            in_escape: false,
+        };
+        write!(
+            w,
+            "\u{1B}[31merror\u{1B}[m: {}",
+            "é".repeat(MAX_RING_RECORD_LEN)
+        )
+        .unwrap();
+        let text = core::str::from_utf8(&w.buf[..w.len]).unwrap();
+
+        // Colors are dropped, and the text truncated at a char boundary.
+        assert!(text.starts_with("error: é"));
+        assert_eq!(text.len(), MAX_RING_RECORD_LEN - 1);
+    }
+}
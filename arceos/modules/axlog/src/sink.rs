@@ -0,0 +1,278 @@
+//! Additional destinations of log records besides the console.
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+use log::{Level, LevelFilter};
+
+/// Maximum number of sinks that can be registered at the same time.
+pub const MAX_SINKS: usize = 8;
+
+/// A destination of formatted log records, such as a memory buffer or a
+/// secondary console.
+///
+/// Sinks are registered with [`register_sink`] and receive every record that
+/// is printed to the console, in addition to the console itself, or with
+/// [`register_sink_with_filter`] to receive their own selection of records.
+pub trait LogSink: Send + Sync {
+    /// Writes one formatted log record, which ends with a newline.
+    fn write_record(&self, level: Level, record: fmt::Arguments);
+
+    /// Flushes any buffered records.
+    fn flush(&self) {}
+}
+
+/// The error returned by [`register_sink`] when all [`MAX_SINKS`] slots are
+/// in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooManySinks;
+
+impl fmt::Display for TooManySinks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "too many log sinks (at most {})", MAX_SINKS)
+    }
+}
+
+/// The records sent to a sink, see [`register_sink_with_filter`].
+///
+/// The default filter selects the records printed to the console.
+///
+/// # Examples
+///
+/// Errors and warnings of all targets, whatever the console shows:
+///
+/// ```
+/// use axlog::{LevelFilter, SinkFilter};
+///
+/// let filter = SinkFilter::default().with_level(LevelFilter::Warn);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SinkFilter {
+    level: Option<LevelFilter>,
+    target: Option<&'static str>,
+}
+
+impl SinkFilter {
+    /// Selects the records up to `level`, instead of those printed to the
+    /// console.
+    pub const fn with_level(mut self, level: LevelFilter) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Only selects the records whose target matches `pattern`, with the
+    /// syntax of [`set_target_level`](crate::set_target_level).
+    pub const fn with_target(mut self, pattern: &'static str) -> Self {
+        self.target = Some(pattern);
+        self
+    }
+
+    fn matches(&self, level: Level, target: &str, printed: bool) -> bool {
+        let level_ok = match self.level {
+            Some(max) => level <= max,
+            None => printed,
+        };
+        let target_ok = match self.target {
+            Some(pattern) => crate::filter::match_len(pattern, target).is_some(),
+            None => true,
+        };
+        level_ok && target_ok
+    }
+}
+
+type SinkSlots = [Option<(&'static dyn LogSink, SinkFilter)>; MAX_SINKS];
+
+static SINKS: SpinNoIrq<SinkSlots> = SpinNoIrq::new([None; MAX_SINKS]);
+
+/// Highest level of the sinks with their own level.
+static MAX_SINK_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+
+/// Registers a sink that receives every log record printed to the console
+/// from now on.
+pub fn register_sink(sink: &'static dyn LogSink) -> Result<(), TooManySinks> {
+    register_sink_with_filter(sink, SinkFilter::default())
+}
+
+/// Registers a sink that receives the log records selected by `filter` from
+/// now on, independently of the console.
+///
+/// For example, a sink on a second serial port can receive all errors and
+/// warnings, even while the console is flooded by other records or set to a
+/// lower level.
+pub fn register_sink_with_filter(
+    sink: &'static dyn LogSink,
+    filter: SinkFilter,
+) -> Result<(), TooManySinks> {
+    {
+        let mut sinks = SINKS.lock();
+        let slot = sinks.iter_mut().find(|s| s.is_none()).ok_or(TooManySinks)?;
+        *slot = Some((sink, filter));
+        update_max_sink_level(&sinks);
+    }
+    crate::update_max_level();
+    Ok(())
+}
+
+/// Unregisters a sink previously registered by [`register_sink`] or
+/// [`register_sink_with_filter`].
+///
+/// Returns `false` if the sink was not registered.
+pub fn unregister_sink(sink: &'static dyn LogSink) -> bool {
+    {
+        let mut sinks = SINKS.lock();
+        let Some(slot) = sinks
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|(s, _)| core::ptr::addr_eq(s, sink)))
+        else {
+            return false;
+        };
+        *slot = None;
+        update_max_sink_level(&sinks);
+    }
+    crate::update_max_level();
+    true
+}
+
+fn update_max_sink_level(sinks: &SinkSlots) {
+    let max = sinks
+        .iter()
+        .flatten()
+        .filter_map(|(_, filter)| filter.level)
+        .max()
+        .unwrap_or(LevelFilter::Off);
+    MAX_SINK_LEVEL.store(max as usize, Ordering::Relaxed);
+}
+
+/// Returns the highest level of the sinks with their own level.
+pub(crate) fn max_sink_level() -> LevelFilter {
+    crate::level_filter_from_usize(MAX_SINK_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Returns a snapshot of the registered sinks, so that they are not called
+/// with the registry locked.
+fn sinks() -> SinkSlots {
+    *SINKS.lock()
+}
+
+/// Sends a record to the sinks whose filter selects it. `printed` tells
+/// whether the record is printed to the console.
+pub(crate) fn dispatch(level: Level, target: &str, printed: bool, record: fmt::Arguments) {
+    for (sink, filter) in sinks().into_iter().flatten() {
+        if filter.matches(level, target, printed) {
+            sink.write_record(level, record);
+        }
+    }
+}
+
+pub(crate) fn flush() {
+    for (sink, _) in sinks().into_iter().flatten() {
+        sink.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+    use std::string::{String, ToString};
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct TestSink(Mutex<Vec<(Level, String)>>);
+
+    impl LogSink for TestSink {
+        fn write_record(&self, level: Level, record: fmt::Arguments) {
+            self.0.lock().unwrap().push((level, record.to_string()));
+        }
+    }
+
+    fn new_sink() -> &'static TestSink {
+        Box::leak(Box::default())
+    }
+
+    #[test]
+    fn register() {
+        let _config = crate::lock_config();
+        let sink = new_sink();
+        register_sink(sink).unwrap();
+        dispatch(Level::Info, "axfs", true, format_args!("printed\n"));
+        dispatch(Level::Debug, "axfs", false, format_args!("not printed\n"));
+        assert!(unregister_sink(sink));
+        dispatch(Level::Info, "axfs", true, format_args!("unregistered\n"));
+        assert!(!unregister_sink(sink));
+
+        let records = sink.0.lock().unwrap();
+        assert_eq!(*records, [(Level::Info, "printed\n".to_string())]);
+    }
+
+    #[test]
+    fn too_many_sinks() {
+        let _config = crate::lock_config();
+        let sinks: Vec<_> = (0..MAX_SINKS).map(|_| new_sink()).collect();
+        for &sink in &sinks {
+            register_sink(sink).unwrap();
+        }
+        assert_eq!(register_sink(new_sink()), Err(TooManySinks));
+
+        // A freed slot can be reused.
+        assert!(unregister_sink(sinks[3]));
+        let sink = new_sink();
+        register_sink(sink).unwrap();
+        assert!(unregister_sink(sink));
+        for (i, &sink) in sinks.iter().enumerate() {
+            assert_eq!(unregister_sink(sink), i != 3);
+        }
+    }
+
+    #[test]
+    fn filters() {
+        let default = SinkFilter::default();
+        assert!(default.matches(Level::Trace, "axfs", true));
+        assert!(!default.matches(Level::Error, "axfs", false));
+
+        let warn = SinkFilter::default().with_level(LevelFilter::Warn);
+        assert!(warn.matches(Level::Error, "axfs", false));
+        assert!(warn.matches(Level::Warn, "axfs", false));
+        assert!(!warn.matches(Level::Info, "axfs", true));
+
+        let axfs = SinkFilter::default().with_target("axfs");
+        assert!(axfs.matches(Level::Info, "axfs::fops", true));
+        assert!(!axfs.matches(Level::Info, "axfs_ramfs", true));
+        assert!(!axfs.matches(Level::Info, "axfs", false));
+
+        let axfs_all = axfs.with_target("axfs*").with_level(LevelFilter::Debug);
+        assert!(axfs_all.matches(Level::Debug, "axfs_ramfs", false));
+        assert!(!axfs_all.matches(Level::Trace, "axfs_ramfs", true));
+        assert!(!axfs_all.matches(Level::Error, "axnet", true));
+    }
+
+    #[test]
+    fn max_level() {
+        let _config = crate::lock_config();
+        assert_eq!(max_sink_level(), LevelFilter::Off);
+        let debug = new_sink();
+        let info = new_sink();
+        register_sink_with_filter(debug, SinkFilter::default().with_level(LevelFilter::Debug))
+            .unwrap();
+        register_sink_with_filter(info, SinkFilter::default().with_level(LevelFilter::Info))
+            .unwrap();
+        let console = new_sink();
+        register_sink(console).unwrap();
+        assert_eq!(max_sink_level(), LevelFilter::Debug);
+        assert!(log::max_level() >= LevelFilter::Debug);
+
+        // Records below the console level reach the sinks selecting them.
+        dispatch(Level::Debug, "axfs", false, format_args!("debug\n"));
+        assert_eq!(debug.0.lock().unwrap().len(), 1);
+        assert!(info.0.lock().unwrap().is_empty());
+
+        assert!(unregister_sink(debug));
+        assert_eq!(max_sink_level(), LevelFilter::Info);
+        assert!(unregister_sink(info));
+        assert_eq!(max_sink_level(), LevelFilter::Off);
+        assert!(unregister_sink(console));
+    }
+}
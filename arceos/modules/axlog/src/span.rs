@@ -0,0 +1,158 @@
+//! Scoped spans, which indent the records logged inside them.
+
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use log::{Level, Record};
+
+/// Number of spaces of indentation per level of nesting.
+pub(crate) const INDENT_WIDTH: usize = 2;
+
+/// Number of spans entered and not exited yet, in all tasks, to skip looking
+/// up the depth when there are none.
+static OPEN_SPANS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the span nesting depth of the current task.
+pub(crate) fn depth() -> usize {
+    if OPEN_SPANS.load(Ordering::Relaxed) == 0 {
+        return 0;
+    }
+    task_depth()
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::cell::Cell;
+
+        std::thread_local! {
+            static DEPTH: Cell<usize> = const { Cell::new(0) };
+        }
+
+        fn task_depth() -> usize {
+            DEPTH.with(|d| d.get())
+        }
+
+        fn set_depth(f: impl FnOnce(usize) -> usize) {
+            DEPTH.with(|d| d.set(f(d.get())));
+        }
+    } else {
+        use kspin::SpinNoIrq;
+
+        /// Maximum number of tasks whose span nesting is tracked at the same
+        /// time. Spans of other tasks are logged without indentation.
+        const MAX_SPAN_TASKS: usize = 64;
+
+        /// Span nesting depth of each task in a span, by task ID. Tasks
+        /// without an ID share the same entry.
+        static DEPTHS: SpinNoIrq<[(Option<u64>, usize); MAX_SPAN_TASKS]> =
+            SpinNoIrq::new([(None, 0); MAX_SPAN_TASKS]);
+
+        fn current_task_id() -> Option<u64> {
+            crate::cpu_and_task_id().1
+        }
+
+        fn task_depth() -> usize {
+            let tid = current_task_id();
+            DEPTHS
+                .lock()
+                .iter()
+                .find(|(id, depth)| *depth > 0 && *id == tid)
+                .map_or(0, |(_, depth)| *depth)
+        }
+
+        fn set_depth(f: impl FnOnce(usize) -> usize) {
+            let tid = current_task_id();
+            let mut depths = DEPTHS.lock();
+            let entry = match depths.iter().position(|(id, depth)| *depth > 0 && *id == tid) {
+                Some(pos) => &mut depths[pos],
+                None => match depths.iter_mut().find(|(_, depth)| *depth == 0) {
+                    Some(entry) => entry,
+                    None => return,
+                },
+            };
+            *entry = (tid, f(entry.1));
+        }
+    }
+}
+
+/// A guard returned by [`span!`](crate::span!), which logs when the span is
+/// entered and exited.
+///
+/// While the guard is alive, the records logged by the same task are indented
+/// one level further.
+#[must_use = "the span is exited as soon as the guard is dropped"]
+pub struct Span {
+    name: &'static str,
+    level: Level,
+    target: &'static str,
+    file: &'static str,
+    line: u32,
+    start: Duration,
+    /// Whether the span is logged at all, otherwise it does not indent.
+    active: bool,
+}
+
+impl Span {
+    #[doc(hidden)]
+    pub fn enter(
+        name: &'static str,
+        level: Level,
+        static_enabled: bool,
+        target: &'static str,
+        file: &'static str,
+        line: u32,
+    ) -> Self {
+        let span = Self {
+            name,
+            level,
+            target,
+            file,
+            line,
+            start: crate::current_time(),
+            active: static_enabled && crate::level_enabled(level),
+        };
+        if span.active {
+            span.log(format_args!("{} {{", name));
+            OPEN_SPANS.fetch_add(1, Ordering::Relaxed);
+            set_depth(|d| d + 1);
+        }
+        span
+    }
+
+    /// Exits the span, logging `args` after its name, e.g., the arguments
+    /// and result of the operation it covers.
+    pub fn exit_with(self, args: fmt::Arguments) {
+        ManuallyDrop::new(self).exit(args);
+    }
+
+    fn exit(&mut self, args: fmt::Arguments) {
+        if self.active {
+            set_depth(|d| d.saturating_sub(1));
+            OPEN_SPANS.fetch_sub(1, Ordering::Relaxed);
+            let elapsed = crate::current_time().saturating_sub(self.start);
+            self.log(format_args!("}} {}{} [{:?}]", self.name, args, elapsed));
+        }
+    }
+
+    /// Logs a record at the location of the span.
+    fn log(&self, args: fmt::Arguments) {
+        log::logger().log(
+            &Record::builder()
+                .level(self.level)
+                .target(self.target)
+                .module_path_static(Some(self.target))
+                .file_static(Some(self.file))
+                .line(Some(self.line))
+                .args(args)
+                .build(),
+        );
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        self.exit(format_args!(""));
+    }
+}
@@ -0,0 +1,70 @@
+//! Per-module maximum log levels, fixed at compile time.
+//!
+//! They are set with the `AX_LOG_STATIC` environment variable when building,
+//! a comma-separated list of `<pattern>=<level>` entries with the syntax of
+//! [`set_target_level`](crate::set_target_level), plus an optional `<level>`
+//! for all other modules. For example, `AX_LOG_STATIC=axfs*=trace,warn` keeps
+//! every level in the `axfs*` crates, but compiles out the `info`, `debug` and
+//! `trace` records of all other modules.
+
+use log::LevelFilter;
+
+include!(concat!(env!("OUT_DIR"), "/static_levels.rs"));
+
+/// Const version of [`filter::match_len`](crate::filter::match_len).
+const fn match_len(pattern: &[u8], path: &[u8]) -> Option<usize> {
+    let (prefix_len, is_prefix) = match pattern {
+        [.., b'*'] => (pattern.len() - 1, true),
+        _ => (pattern.len(), false),
+    };
+    if path.len() < prefix_len {
+        return None;
+    }
+    let mut i = 0;
+    while i < prefix_len {
+        if pattern[i] != path[i] {
+            return None;
+        }
+        i += 1;
+    }
+    if is_prefix
+        || path.len() == prefix_len
+        || (path.len() >= prefix_len + 2
+            && path[prefix_len] == b':'
+            && path[prefix_len + 1] == b':')
+    {
+        Some(prefix_len)
+    } else {
+        None
+    }
+}
+
+/// Returns the static maximum level of the module at `module_path`, from the
+/// longest matching pattern, capped by the `log-level-*` features.
+///
+/// Used by the log macros in const context.
+#[doc(hidden)]
+pub const fn __static_max_level(module_path: &str) -> LevelFilter {
+    let mut level = STATIC_DEFAULT_LEVEL;
+    let mut best = None;
+    let mut i = 0;
+    while i < STATIC_LEVELS.len() {
+        let (pattern, pattern_level) = STATIC_LEVELS[i];
+        if let Some(len) = match_len(pattern.as_bytes(), module_path.as_bytes()) {
+            let longer = match best {
+                Some(best_len) => len > best_len,
+                None => true,
+            };
+            if longer {
+                best = Some(len);
+                level = pattern_level;
+            }
+        }
+        i += 1;
+    }
+    if level as usize > log::STATIC_MAX_LEVEL as usize {
+        log::STATIC_MAX_LEVEL
+    } else {
+        level
+    }
+}
@@ -0,0 +1,169 @@
+//! Logging statistics.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+use log::Level;
+
+/// Maximum number of targets counted separately by [`target_stats`].
+pub const MAX_TARGETS: usize = 32;
+
+/// Maximum length of a target name in [`target_stats`], longer names are
+/// truncated.
+const MAX_TARGET_LEN: usize = 24;
+
+/// Counters of the logging system, returned by [`stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LogStats {
+    /// Number of records logged at each level, indexed by
+    /// [`LogStats::index`]. This includes suppressed records.
+    pub records: [usize; 5],
+    /// Number of records not printed by duplicate suppression (see
+    /// [`set_duplicate_suppression`](crate::set_duplicate_suppression)).
+    pub suppressed_records: usize,
+    /// Number of console writes, records or plain prints, that lost output
+    /// because the console could not keep up.
+    pub dropped_records: usize,
+    /// Number of bytes of console output dropped because the console could
+    /// not keep up.
+    pub dropped_bytes: usize,
+    /// Number of bytes written to the console.
+    pub bytes_written: usize,
+    /// Number of records logged before [`init`](crate::init) that were
+    /// dropped because they did not fit in the early buffer.
+    pub early_dropped_records: usize,
+}
+
+impl LogStats {
+    /// Returns the index of a level in [`LogStats::records`].
+    pub const fn index(level: Level) -> usize {
+        level as usize - 1
+    }
+
+    /// Returns the number of records logged at `level`.
+    pub const fn count(&self, level: Level) -> usize {
+        self.records[Self::index(level)]
+    }
+
+    /// Returns the number of records logged at all levels.
+    pub fn total(&self) -> usize {
+        self.records.iter().sum()
+    }
+}
+
+static RECORDS: [AtomicUsize; 5] = [const { AtomicUsize::new(0) }; 5];
+static SUPPRESSED_RECORDS: AtomicUsize = AtomicUsize::new(0);
+static DROPPED_RECORDS: AtomicUsize = AtomicUsize::new(0);
+static BYTES_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+static EARLY_DROPPED_RECORDS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Copy)]
+struct TargetEntry {
+    name: [u8; MAX_TARGET_LEN],
+    len: usize,
+    records: [usize; 5],
+}
+
+struct TargetTable {
+    entries: [TargetEntry; MAX_TARGETS],
+    len: usize,
+}
+
+static TARGETS: SpinNoIrq<TargetTable> = SpinNoIrq::new(TargetTable {
+    entries: [TargetEntry {
+        name: [0; MAX_TARGET_LEN],
+        len: 0,
+        records: [0; 5],
+    }; MAX_TARGETS],
+    len: 0,
+});
+
+/// Returns a snapshot of the logging counters.
+///
+/// # Examples
+///
+/// ```
+/// use axlog::{stats, Level};
+///
+/// axlog::init();
+/// axlog::error!("oops");
+/// assert_eq!(stats().count(Level::Error), 1);
+/// ```
+pub fn stats() -> LogStats {
+    LogStats {
+        records: core::array::from_fn(|i| RECORDS[i].load(Ordering::Relaxed)),
+        suppressed_records: SUPPRESSED_RECORDS.load(Ordering::Relaxed),
+        dropped_records: DROPPED_RECORDS.load(Ordering::Relaxed),
+        dropped_bytes: crate::console_dropped_bytes(),
+        bytes_written: BYTES_WRITTEN.load(Ordering::Relaxed),
+        early_dropped_records: EARLY_DROPPED_RECORDS.load(Ordering::Relaxed),
+    }
+}
+
+/// Calls `f` with the name and the per-level record counts (indexed by
+/// [`LogStats::index`]) of each target.
+///
+/// Targets are counted by crate, i.e., by their part before the first `::`.
+/// Once [`MAX_TARGETS`] crates have been seen, the others are counted
+/// together under `*`.
+pub fn target_stats(mut f: impl FnMut(&str, &[usize; 5])) {
+    let (entries, len) = {
+        let table = TARGETS.lock();
+        (table.entries, table.len)
+    };
+    for entry in &entries[..len] {
+        f(
+            core::str::from_utf8(&entry.name[..entry.len]).unwrap_or_default(),
+            &entry.records,
+        );
+    }
+}
+
+pub(crate) fn count_record(level: Level, target: &str) {
+    let idx = LogStats::index(level);
+    RECORDS[idx].fetch_add(1, Ordering::Relaxed);
+
+    let mut name = target.split("::").next().unwrap_or_default();
+    if name.len() > MAX_TARGET_LEN {
+        let mut end = MAX_TARGET_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name = &name[..end];
+    }
+
+    let mut table = TARGETS.lock();
+    let len = table.len;
+    let pos = table.entries[..len]
+        .iter()
+        .position(|e| &e.name[..e.len] == name.as_bytes());
+    let pos = match pos {
+        Some(pos) => pos,
+        None if len < MAX_TARGETS => {
+            let name = if len == MAX_TARGETS - 1 { "*" } else { name };
+            let entry = &mut table.entries[len];
+            entry.name[..name.len()].copy_from_slice(name.as_bytes());
+            entry.len = name.len();
+            table.len += 1;
+            len
+        }
+        None => MAX_TARGETS - 1,
+    };
+    table.entries[pos].records[idx] += 1;
+}
+
+pub(crate) fn count_suppressed() {
+    SUPPRESSED_RECORDS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn count_dropped() {
+    DROPPED_RECORDS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn count_bytes_written(bytes: usize) {
+    BYTES_WRITTEN.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub(crate) fn count_early_dropped(records: usize) {
+    EARLY_DROPPED_RECORDS.fetch_add(records, Ordering::Relaxed);
+}
@@ -0,0 +1,277 @@
+//! Styling of console text, without writing escape sequences by hand.
+//!
+//! # Examples
+//!
+//! ```
+//! use axlog::style::{Color, Stylize};
+//!
+//! let hello = "hello".fg(Color::Rgb(150, 50, 255)).bold();
+//! assert_eq!(format!("{}", hello), "\u{1B}[1;38;2;150;50;255mhello\u{1B}[0m");
+//! ```
+
+use core::fmt::{self, Display, Write};
+
+/// A text color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+    /// A 24-bit color, shown as the closest of the other colors on consoles
+    /// without [`supports_truecolor`](crate::ConsoleCaps::supports_truecolor).
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Writes the SGR parameters of the color as foreground, or background if
+    /// `background` is set.
+    fn write_params(
+        self,
+        f: &mut (impl Write + ?Sized),
+        background: bool,
+        truecolor: bool,
+    ) -> fmt::Result {
+        let offset = if background { 10 } else { 0 };
+        let code = match self {
+            Self::Black => 30,
+            Self::Red => 31,
+            Self::Green => 32,
+            Self::Yellow => 33,
+            Self::Blue => 34,
+            Self::Magenta => 35,
+            Self::Cyan => 36,
+            Self::White => 37,
+            Self::BrightBlack => 90,
+            Self::BrightRed => 91,
+            Self::BrightGreen => 92,
+            Self::BrightYellow => 93,
+            Self::BrightBlue => 94,
+            Self::BrightMagenta => 95,
+            Self::BrightCyan => 96,
+            Self::BrightWhite => 97,
+            Self::Rgb(r, g, b) if truecolor => {
+                return write!(f, "{};2;{};{};{}", 38 + offset, r, g, b);
+            }
+            Self::Rgb(r, g, b) => {
+                // The channels above half are on, and the color is bright if
+                // one of them is close to full.
+                let bits = (r > 127) as u8 | ((g > 127) as u8) << 1 | ((b > 127) as u8) << 2;
+                let base = if r.max(g).max(b) > 191 { 90 } else { 30 };
+                base + bits
+            }
+        };
+        write!(f, "{}", code + offset)
+    }
+}
+
+/// A set of text attributes and colors, applied with [`Styled`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl Style {
+    /// Creates a style without any attribute.
+    pub const fn new() -> Self {
+        Self {
+            fg: None,
+            bg: None,
+            bold: false,
+            dim: false,
+            italic: false,
+            underline: false,
+        }
+    }
+
+    /// Sets the foreground color.
+    pub const fn fg(mut self, color: Color) -> Self {
+        self.fg = Some(color);
+        self
+    }
+
+    /// Sets the background color.
+    pub const fn bg(mut self, color: Color) -> Self {
+        self.bg = Some(color);
+        self
+    }
+
+    /// Makes the text bold.
+    pub const fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    /// Makes the text dim.
+    pub const fn dim(mut self) -> Self {
+        self.dim = true;
+        self
+    }
+
+    /// Makes the text italic.
+    pub const fn italic(mut self) -> Self {
+        self.italic = true;
+        self
+    }
+
+    /// Underlines the text.
+    pub const fn underline(mut self) -> Self {
+        self.underline = true;
+        self
+    }
+
+    /// Writes the escape sequence that starts the style, for a console with
+    /// the given capabilities.
+    pub(crate) fn write_start(
+        &self,
+        f: &mut (impl Write + ?Sized),
+        truecolor: bool,
+    ) -> fmt::Result {
+        let mut sep = "";
+        f.write_str("\u{1B}[")?;
+        for (set, code) in [
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+        ] {
+            if set {
+                write!(f, "{}{}", sep, code)?;
+                sep = ";";
+            }
+        }
+        if let Some(fg) = self.fg {
+            f.write_str(sep)?;
+            fg.write_params(f, false, truecolor)?;
+            sep = ";";
+        }
+        if let Some(bg) = self.bg {
+            f.write_str(sep)?;
+            bg.write_params(f, true, truecolor)?;
+        }
+        f.write_char('m')
+    }
+}
+
+/// A value displayed with a [`Style`].
+///
+/// The escape sequences are only written if the console supports them, see
+/// [`console_caps`](crate::console_caps). The formatting options, such as the
+/// width, apply to the value itself.
+#[derive(Debug, Clone, Copy)]
+pub struct Styled<T> {
+    value: T,
+    style: Style,
+}
+
+impl<T> Styled<T> {
+    /// Creates a styled value.
+    pub const fn new(value: T, style: Style) -> Self {
+        Self { value, style }
+    }
+
+    /// Sets the foreground color.
+    pub fn fg(mut self, color: Color) -> Self {
+        self.style = self.style.fg(color);
+        self
+    }
+
+    /// Sets the background color.
+    pub fn bg(mut self, color: Color) -> Self {
+        self.style = self.style.bg(color);
+        self
+    }
+
+    /// Makes the text bold.
+    pub fn bold(mut self) -> Self {
+        self.style = self.style.bold();
+        self
+    }
+
+    /// Makes the text dim.
+    pub fn dim(mut self) -> Self {
+        self.style = self.style.dim();
+        self
+    }
+
+    /// Makes the text italic.
+    pub fn italic(mut self) -> Self {
+        self.style = self.style.italic();
+        self
+    }
+
+    /// Underlines the text.
+    pub fn underline(mut self) -> Self {
+        self.style = self.style.underline();
+        self
+    }
+}
+
+impl<T: Display> Display for Styled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let caps = crate::console_caps();
+        if self.style == Style::new() || !caps.supports_ansi_color {
+            return self.value.fmt(f);
+        }
+        self.style.write_start(f, caps.supports_truecolor)?;
+        self.value.fmt(f)?;
+        f.write_str("\u{1B}[0m")
+    }
+}
+
+/// Styling methods for all displayable values, starting a [`Styled`] value.
+pub trait Stylize: Sized {
+    /// Displays the value with the given style.
+    fn style(self, style: Style) -> Styled<Self> {
+        Styled::new(self, style)
+    }
+
+    /// Sets the foreground color.
+    fn fg(self, color: Color) -> Styled<Self> {
+        self.style(Style::new().fg(color))
+    }
+
+    /// Sets the background color.
+    fn bg(self, color: Color) -> Styled<Self> {
+        self.style(Style::new().bg(color))
+    }
+
+    /// Makes the text bold.
+    fn bold(self) -> Styled<Self> {
+        self.style(Style::new().bold())
+    }
+
+    /// Makes the text dim.
+    fn dim(self) -> Styled<Self> {
+        self.style(Style::new().dim())
+    }
+
+    /// Makes the text italic.
+    fn italic(self) -> Styled<Self> {
+        self.style(Style::new().italic())
+    }
+
+    /// Underlines the text.
+    fn underline(self) -> Styled<Self> {
+        self.style(Style::new().underline())
+    }
+}
+
+impl<T: Display> Stylize for T {}
@@ -0,0 +1,117 @@
+//! Color themes of console output.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU16, AtomicU8, Ordering};
+
+/// The color theme of console output, set with [`set_theme`].
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Theme {
+    /// No colors at all.
+    Plain = 0,
+    /// Log records are colored by level, other output is left as is.
+    #[default]
+    LevelColored = 1,
+    /// Every character of console output is colored along a rainbow, with
+    /// 24-bit color escapes.
+    ///
+    /// This is slow and makes the output hard to search, so it is only meant
+    /// for fun.
+    Rainbow = 2,
+}
+
+static THEME: AtomicU8 = AtomicU8::new(Theme::LevelColored as u8);
+
+/// Sets the color theme of console output.
+///
+/// Colors that the console does not support are left out, see
+/// [`ConsoleCaps`](crate::ConsoleCaps).
+pub fn set_theme(theme: Theme) {
+    THEME.store(theme as u8, Ordering::Relaxed);
+}
+
+/// Returns the color theme of console output.
+pub fn theme() -> Theme {
+    match THEME.load(Ordering::Relaxed) {
+        0 => Theme::Plain,
+        2 => Theme::Rainbow,
+        _ => Theme::LevelColored,
+    }
+}
+
+/// Returns the theme actually used, downgraded to what the console supports
+/// (see [`console_caps`](crate::console_caps)), or to [`Theme::Plain`] in the
+/// JSON [output format](crate::output_format), which colors would corrupt.
+pub(crate) fn effective_theme() -> Theme {
+    let caps = crate::console_caps();
+    match theme() {
+        _ if !caps.supports_ansi_color => Theme::Plain,
+        _ if crate::output_format() == crate::OutputFormat::Json => Theme::Plain,
+        Theme::Rainbow if !caps.supports_truecolor => Theme::LevelColored,
+        theme => theme,
+    }
+}
+
+/// Hue in degrees of the next character colored by [`RainbowWriter`], kept
+/// across writes so that consecutive lines continue the rainbow.
+static HUE: AtomicU16 = AtomicU16::new(0);
+
+/// Hue increment between two consecutive characters.
+const HUE_STEP: u16 = 6;
+
+/// Converts a hue in degrees to RGB, with full saturation and value.
+fn hue_to_rgb(hue: u16) -> (u8, u8, u8) {
+    let f = ((hue % 60) as u32 * 255 / 60) as u8;
+    match hue / 60 % 6 {
+        0 => (255, f, 0),
+        1 => (255 - f, 255, 0),
+        2 => (0, 255, f),
+        3 => (0, 255 - f, 255),
+        4 => (f, 0, 255),
+        _ => (255, 0, 255 - f),
+    }
+}
+
+/// Colors each character written to the inner writer with the next color of
+/// the rainbow. Whitespace and existing escape sequences are passed through.
+pub(crate) struct RainbowWriter<W> {
+    inner: W,
+    in_escape: bool,
+}
+
+impl<W: Write> RainbowWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            in_escape: false,
+        }
+    }
+
+    /// Resets the color and returns the inner writer.
+    pub fn finish(mut self) -> Result<W, fmt::Error> {
+        self.inner.write_str("\u{1B}[m")?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for RainbowWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.in_escape {
+                self.in_escape = c == '[' || !('@'..='~').contains(&c);
+            } else if c == '\u{1B}' {
+                self.in_escape = true;
+            } else if !c.is_whitespace() {
+                let hue = HUE
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |h| {
+                        Some((h + HUE_STEP) % 360)
+                    })
+                    .unwrap_or(0);
+                let (r, g, b) = hue_to_rgb(hue);
+                write!(self.inner, "\u{1B}[38;2;{};{};{}m", r, g, b)?;
+            }
+            self.inner.write_char(c)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+//! Call tracing of allocator operations, enabled by the `allocator` feature.
+
+use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use crate::trace_op;
+
+/// An allocator wrapper that runs every operation of the inner allocator in
+/// a `trace` level span, see [`trace_op!`].
+pub struct TracedAllocator<A>(A);
+
+impl<A> TracedAllocator<A> {
+    /// Wraps the given allocator.
+    pub const fn new(inner: A) -> Self {
+        Self(inner)
+    }
+}
+
+impl<A: BaseAllocator> BaseAllocator for TracedAllocator<A> {
+    fn init(&mut self, start: usize, size: usize) {
+        trace_op!(
+            "init",
+            || self.0.init(start, size),
+            "{:#x}, {:#x}",
+            start,
+            size
+        )
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        trace_op!(
+            "add_memory",
+            || self.0.add_memory(start, size),
+            "{:#x}, {:#x}",
+            start,
+            size
+        )
+    }
+}
+
+impl<A: ByteAllocator> ByteAllocator for TracedAllocator<A> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        trace_op!("alloc", || self.0.alloc(layout), "{:?}", layout)
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        trace_op!(
+            "dealloc",
+            || self.0.dealloc(pos, layout),
+            "{:?}, {:?}",
+            pos,
+            layout
+        )
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.0.total_bytes()
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.0.used_bytes()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.0.available_bytes()
+    }
+}
+
+impl<A: PageAllocator> PageAllocator for TracedAllocator<A> {
+    const PAGE_SIZE: usize = A::PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        trace_op!(
+            "alloc_pages",
+            || self.0.alloc_pages(num_pages, align_pow2),
+            "{}, {:#x}",
+            num_pages,
+            align_pow2
+        )
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        trace_op!(
+            "dealloc_pages",
+            || self.0.dealloc_pages(pos, num_pages),
+            "{:#x}, {}",
+            pos,
+            num_pages
+        )
+    }
+
+    fn total_pages(&self) -> usize {
+        self.0.total_pages()
+    }
+
+    fn used_pages(&self) -> usize {
+        self.0.used_pages()
+    }
+
+    fn available_pages(&self) -> usize {
+        self.0.available_pages()
+    }
+}
@@ -2,6 +2,6 @@ use core::panic::PanicInfo;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    error!("{}", info);
+    axlog::panic_report(info);
     axhal::misc::terminate()
 }
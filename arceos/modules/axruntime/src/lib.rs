@@ -125,8 +125,20 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         chrono::DateTime::from_timestamp_nanos(axhal::time::wall_time_nanos() as _),
     );
 
+    #[cfg(feature = "rtc")]
+    axlog::set_wall_clock(|| {
+        let nanos = axhal::time::wall_time_nanos();
+        Some((nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32))
+    });
+
+    let log_level = option_env!("AX_LOG").unwrap_or("");
+    // no effect if set `log-level-*` features
+    let level_res = axlog::set_max_level(log_level);
+    // set the level first, so that the records logged before are filtered by it
     axlog::init();
-    axlog::set_max_level(option_env!("AX_LOG").unwrap_or("")); // no effect if set `log-level-*` features
+    if let Err(e) = level_res {
+        warn!("AX_LOG={:?}: {}", log_level, e);
+    }
     info!("Logging is enabled.");
     info!("Primary CPU {} started, dtb = {:#x}.", cpu_id, dtb);
 
@@ -197,6 +209,7 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     #[cfg(not(feature = "multitask"))]
     {
         debug!("main task exited: exit_code={}", 0);
+        axlog::flush();
         axhal::misc::terminate();
     }
 }
@@ -60,6 +60,7 @@ pub extern "C" fn rust_main_secondary(cpu_id: usize) -> ! {
     axtask::run_idle();
     #[cfg(not(feature = "multitask"))]
     loop {
+        axlog::flush();
         axhal::arch::wait_for_irqs();
     }
 }
@@ -162,10 +162,12 @@ pub fn exit(exit_code: i32) -> ! {
 
 /// The idle task routine.
 ///
-/// It runs an infinite loop that keeps calling [`yield_now()`].
+/// It runs an infinite loop that keeps calling [`yield_now()`], and writes
+/// out deferred log records whenever it gets the CPU.
 pub fn run_idle() -> ! {
     loop {
         yield_now();
+        axlog::flush();
         debug!("idle task: waiting for IRQs...");
         #[cfg(feature = "irq")]
         axhal::arch::wait_for_irqs();
@@ -88,6 +88,7 @@ impl AxRunQueue {
         assert!(!curr.is_idle());
         if curr.is_init() {
             EXITED_TASKS.lock().clear();
+            axlog::flush();
             axhal::misc::terminate();
         } else {
             curr.set_state(TaskState::Exited);
@@ -0,0 +1,83 @@
+//! Guard bytes and poisoning of byte allocations, enabled by the
+//! `alloc-debug` feature.
+//!
+//! Each byte allocation is surrounded by [`GUARD_SIZE`] bytes of
+//! [`GUARD_BYTE`] (the alignment padding before it too), which are checked
+//! when it is freed. Freed allocations are filled with [`POISON_BYTE`], and
+//! when the bytes area of a region is reset, it must only contain these two
+//! patterns, otherwise some memory was written after it was freed.
+
+use core::alloc::Layout;
+use core::slice;
+
+/// Number of guard bytes before and after each byte allocation.
+pub const GUARD_SIZE: usize = 16;
+
+/// Pattern of the guard bytes.
+pub const GUARD_BYTE: u8 = 0xfd;
+
+/// Pattern of freed memory.
+pub const POISON_BYTE: u8 = 0xdd;
+
+/// # Safety
+///
+/// `[start, end)` must be valid memory owned by the allocator.
+unsafe fn bytes<'a>(start: usize, end: usize) -> &'a mut [u8] {
+    slice::from_raw_parts_mut(start as *mut u8, end - start)
+}
+
+/// Fills the guard bytes of a new allocation at `pos`, from the previous end
+/// of the bytes area `b_pos`.
+pub fn on_alloc(b_pos: usize, pos: usize, size: usize) {
+    unsafe {
+        bytes(b_pos, pos).fill(GUARD_BYTE);
+        bytes(pos + size, pos + size + GUARD_SIZE).fill(GUARD_BYTE);
+    }
+}
+
+/// Checks the guard bytes of an allocation, and logs it if they were
+/// overwritten.
+fn check_guards(pos: usize, layout: Layout) {
+    let (front, back) = unsafe {
+        (
+            bytes(pos - GUARD_SIZE, pos),
+            bytes(pos + layout.size(), pos + layout.size() + GUARD_SIZE),
+        )
+    };
+    if front.iter().any(|&b| b != GUARD_BYTE) {
+        error!("early allocator: underflow before {:#x} ({:?})", pos, layout);
+    }
+    if back.iter().any(|&b| b != GUARD_BYTE) {
+        error!("early allocator: overflow after {:#x} ({:?})", pos, layout);
+    }
+}
+
+/// Checks the guard bytes of an allocation being freed, then poisons it.
+pub fn on_dealloc(pos: usize, layout: Layout) {
+    check_guards(pos, layout);
+    unsafe { bytes(pos - GUARD_SIZE, pos + layout.size() + GUARD_SIZE).fill(POISON_BYTE) };
+}
+
+/// Checks the guard bytes of the allocation at `pos` being grown to
+/// `new_size` bytes, then moves its trailing guard bytes.
+pub fn on_grow(pos: usize, old_layout: Layout, new_size: usize) {
+    check_guards(pos, old_layout);
+    unsafe { bytes(pos + new_size, pos + new_size + GUARD_SIZE).fill(GUARD_BYTE) };
+}
+
+/// Checks that the bytes area `[start, b_pos)` being reset only contains
+/// freed allocations and guard bytes.
+pub fn on_reset(start: usize, b_pos: usize) {
+    let area = unsafe { bytes(start, b_pos) };
+    if let Some(offset) = area
+        .iter()
+        .position(|&b| b != POISON_BYTE && b != GUARD_BYTE)
+    {
+        error!(
+            "early allocator: memory written after free at {:#x} (bytes area [{:#x}, {:#x}))",
+            start + offset,
+            start,
+            b_pos
+        );
+    }
+}
@@ -1,8 +1,242 @@
 #![no_std]
 
+#[cfg(any(feature = "alloc-debug", feature = "alloc-oom-log"))]
+#[macro_use]
+extern crate log;
+
+#[cfg(feature = "alloc-debug")]
+mod debug;
+mod observer;
+mod sync;
+
+#[cfg(test)]
+mod tests;
+
 use core::alloc::Layout;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
 use core::ptr::NonNull;
-use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+
+pub use observer::AllocObserver;
+pub use sync::{SyncEarlyAllocator, MAX_SYNC_REGION_SIZE};
+
+#[cfg(feature = "alloc-debug")]
+use debug::GUARD_SIZE;
+/// Number of guard bytes before and after each byte allocation, only with
+/// the `alloc-debug` feature.
+#[cfg(not(feature = "alloc-debug"))]
+const GUARD_SIZE: usize = 0;
+
+/// Maximum number of disjoint memory regions, see
+/// [`add_memory`](BaseAllocator::add_memory).
+pub const MAX_REGIONS: usize = 8;
+
+/// Maximum number of freed page runs kept for reuse. Pages freed when the
+/// list is full stay allocated.
+pub const MAX_FREE_RUNS: usize = 32;
+
+/// Maximum number of [`Mark`]s that can be rolled back to. Taking another one
+/// makes the oldest one stale.
+pub const MAX_MARKS: usize = 8;
+
+/// One memory region of the early allocator.
+#[derive(Clone, Copy)]
+struct Region {
+    start: usize,
+    end: usize,
+    b_pos: usize,
+    p_pos: usize,
+    count: usize,
+    /// Highest `b_pos` and lowest `p_pos` since the region was added, the
+    /// memory between them was never allocated.
+    b_high: usize,
+    p_low: usize,
+}
+
+impl Region {
+    const EMPTY: Self = Self {
+        start: 0,
+        end: 0,
+        b_pos: 0,
+        p_pos: 0,
+        count: 0,
+        b_high: 0,
+        p_low: 0,
+    };
+
+    const fn new(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            b_pos: start,
+            p_pos: end,
+            count: 0,
+            b_high: start,
+            p_low: end,
+        }
+    }
+
+    fn contains(&self, pos: usize) -> bool {
+        self.start <= pos && pos < self.end
+    }
+
+    /// Returns whether `[start, end)` was never allocated.
+    fn untouched(&self, start: usize, end: usize) -> bool {
+        self.b_high <= start && end <= self.p_low
+    }
+
+    /// Returns the start of `size` bytes aligned to `align` in the available
+    /// area, if they fit with their guard bytes.
+    fn fit_bytes(&self, size: usize, align: usize) -> Option<usize> {
+        let aligned_pos = self.b_pos.checked_add(GUARD_SIZE + align - 1)? & !(align - 1);
+        (aligned_pos.checked_add(size + GUARD_SIZE)? <= self.p_pos).then_some(aligned_pos)
+    }
+
+    /// Returns the start of `size` bytes of pages aligned to `align` at the
+    /// top of the available area, if they fit.
+    fn fit_pages(&self, size: usize, align: usize) -> Option<usize> {
+        let aligned_pos = self.p_pos.checked_sub(size)? & !(align - 1);
+        (aligned_pos >= self.b_pos).then_some(aligned_pos)
+    }
+}
+
+/// Statistics of an [`EarlyAllocator`] since it was initialized, see
+/// [`DynEarlyAllocator::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EarlyStats {
+    /// Highest number of bytes used in the bytes areas at the same time.
+    pub peak_used_bytes: usize,
+    /// Highest number of pages used in the pages areas at the same time.
+    pub peak_used_pages: usize,
+    /// Size in bytes of the largest byte allocation.
+    pub largest_alloc: usize,
+    /// Number of pages of the largest page allocation.
+    pub largest_alloc_pages: usize,
+    /// Number of successful byte allocations.
+    pub allocs: usize,
+    /// Number of byte deallocations.
+    pub deallocs: usize,
+    /// Number of successful page allocations.
+    pub page_allocs: usize,
+    /// Number of page deallocations.
+    pub page_deallocs: usize,
+    /// Number of byte and page allocations that failed.
+    pub failed_allocs: usize,
+}
+
+impl EarlyStats {
+    const ZERO: Self = Self {
+        peak_used_bytes: 0,
+        peak_used_pages: 0,
+        largest_alloc: 0,
+        largest_alloc_pages: 0,
+        allocs: 0,
+        deallocs: 0,
+        page_allocs: 0,
+        page_deallocs: 0,
+        failed_allocs: 0,
+    };
+}
+
+/// The kind of a failed allocation, see [`FailureInfo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocKind {
+    Bytes,
+    Pages,
+}
+
+/// Why the last allocation of an [`EarlyAllocator`] failed, see
+/// [`DynEarlyAllocator::last_failure`].
+///
+/// If `largest_gap` is at least `size` but `largest_satisfiable` is smaller,
+/// the alignment padding was the problem, otherwise the memory was short.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailureInfo {
+    pub kind: AllocKind,
+    /// Requested size in bytes, `num_pages` times the page size for pages.
+    pub size: usize,
+    /// Requested alignment in bytes.
+    pub align: usize,
+    /// Size in bytes of the largest available area `[b_pos, p_pos)` of a
+    /// region, or freed page run for pages.
+    pub largest_gap: usize,
+    /// Largest size in bytes that could be allocated with the same alignment.
+    pub largest_satisfiable: usize,
+}
+
+impl fmt::Display for FailureInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} allocation of {:#x} bytes aligned to {:#x} failed, largest gap {:#x} bytes, \
+             largest satisfiable {:#x} bytes",
+            self.kind, self.size, self.align, self.largest_gap, self.largest_satisfiable
+        )
+    }
+}
+
+/// An iterator over the memory not used by an [`EarlyAllocator`], as
+/// `(start, size)` pairs, see [`DynEarlyAllocator::remaining_regions`].
+pub struct RemainingRegions {
+    regions: [Region; MAX_REGIONS],
+    num_regions: usize,
+    free_runs: [PageRun; MAX_FREE_RUNS],
+    num_free_runs: usize,
+    /// Index of the next region, then of the next free run.
+    next: usize,
+}
+
+impl Iterator for RemainingRegions {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        loop {
+            let (start, end) = if self.next < self.num_regions {
+                let r = &self.regions[self.next];
+                (r.b_pos, r.p_pos)
+            } else if self.next < self.num_regions + self.num_free_runs {
+                let r = &self.free_runs[self.next - self.num_regions];
+                (r.start, r.end)
+            } else {
+                return None;
+            };
+            self.next += 1;
+            if start < end {
+                return Some((start, end - start));
+            }
+        }
+    }
+}
+
+/// A saved state of an [`EarlyAllocator`], see
+/// [`DynEarlyAllocator::checkpoint`].
+pub struct Mark {
+    /// Identifies the mark among those of the allocator.
+    stamp: u64,
+    regions: [Region; MAX_REGIONS],
+    num_regions: usize,
+    free_runs: [PageRun; MAX_FREE_RUNS],
+    num_free_runs: usize,
+}
+
+/// A run of freed pages `[start, end)` in the pages area.
+#[derive(Clone, Copy)]
+struct PageRun {
+    start: usize,
+    end: usize,
+}
+
+impl PageRun {
+    const EMPTY: Self = Self { start: 0, end: 0 };
+
+    /// Returns the start of `size` bytes aligned to `align` in the run, if
+    /// they fit.
+    fn fit(&self, size: usize, align: usize) -> Option<usize> {
+        let aligned_pos = self.start.checked_add(align - 1)? & !(align - 1);
+        (aligned_pos.checked_add(size)? <= self.end).then_some(aligned_pos)
+    }
+}
 
 /// Early memory allocator
 /// Use it before formal bytes-allocator and pages-allocator can work!
@@ -16,121 +250,677 @@ use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
 ///
 /// For bytes area, 'count' records number of allocations.
 /// When it goes down to ZERO, free bytes-used area.
-/// For pages area, it will never be freed!
+/// The allocations of a boot stage can also be freed at once, with
+/// [`checkpoint`](DynEarlyAllocator::checkpoint) and
+/// [`rollback_to`](DynEarlyAllocator::rollback_to).
+/// For pages area, freed pages at `p_pos` move it back up, other freed runs
+/// of pages are kept in a list (up to [`MAX_FREE_RUNS`]) and reused by the
+/// next page allocations that fit.
+///
+/// Up to [`MAX_REGIONS`] disjoint regions can be used, each one laid out as
+/// above. Allocations are served by the first region (in the order they were
+/// added) with enough space left.
+///
+/// This is a thin wrapper around [`DynEarlyAllocator`], which has all the
+/// other methods, with a page size fixed at compile time.
 /// > 字节分配从低到高s→b，页从高到低p←e
 pub struct EarlyAllocator <const PAGE_SIZE: usize> {
-    start: usize,
-    end: usize,
-    b_pos: usize,
-    p_pos: usize,
-    count: usize,
+    inner: DynEarlyAllocator,
 }
 
 impl <const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
     pub const fn new() -> EarlyAllocator<PAGE_SIZE> {
         Self {
-            start: 0,
-            end  : 0,
-            b_pos: 0,
-            p_pos: 0,
-            // 分配了多少指针的计数，归零就重置指针
-            count: 0,
+            inner: DynEarlyAllocator::new_with_page_size(PAGE_SIZE),
         }
     }
-    
-    fn can_alloc_bytes(&self, size: usize, align: usize) -> bool {
-        let aligned_pos = (self.b_pos + align - 1) & !(align - 1);
-        aligned_pos + size <= self.p_pos
+
+    /// Consumes the allocator, and returns the memory it does not use, see
+    /// [`remaining_regions`](DynEarlyAllocator::remaining_regions).
+    pub fn into_regions(self) -> RemainingRegions {
+        self.inner.into_regions()
     }
+}
 
-    fn can_alloc_pages(&self, num_pages: usize, align_pow2: usize) -> bool {
-        let size = num_pages * PAGE_SIZE;
-        let aligned_pos = (self.p_pos - size) & !(align_pow2 - 1);
-        aligned_pos >= self.b_pos
+impl <const PAGE_SIZE: usize> Default for EarlyAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl <const PAGE_SIZE: usize> BaseAllocator for EarlyAllocator<PAGE_SIZE> {
+impl <const PAGE_SIZE: usize> Deref for EarlyAllocator<PAGE_SIZE> {
+    type Target = DynEarlyAllocator;
+
+    fn deref(&self) -> &DynEarlyAllocator {
+        &self.inner
+    }
+}
+
+impl <const PAGE_SIZE: usize> DerefMut for EarlyAllocator<PAGE_SIZE> {
+    fn deref_mut(&mut self) -> &mut DynEarlyAllocator {
+        &mut self.inner
+    }
+}
+
+/// The [`EarlyAllocator`] with a page size chosen at runtime, e.g., 4 KiB or
+/// 16 KiB depending on the platform detected at boot.
+///
+/// As the page size is not a constant, it does not implement
+/// [`PageAllocator`], but has the same page methods.
+pub struct DynEarlyAllocator {
+    page_size: usize,
+    regions: [Region; MAX_REGIONS],
+    num_regions: usize,
+    free_runs: [PageRun; MAX_FREE_RUNS],
+    num_free_runs: usize,
+    stats: EarlyStats,
+    last_failure: Option<FailureInfo>,
+    untouched_zeroed: bool,
+    /// Stamps of the marks that can be rolled back to, the oldest first.
+    marks: [u64; MAX_MARKS],
+    num_marks: usize,
+    next_stamp: u64,
+    #[cfg(feature = "alloc-observer")]
+    observer: Option<&'static dyn AllocObserver>,
+}
+
+impl DynEarlyAllocator {
+    /// Creates an empty allocator with pages of `page_size` bytes, which
+    /// must be a power of two.
+    pub const fn new_with_page_size(page_size: usize) -> Self {
+        assert!(page_size.is_power_of_two());
+        Self {
+            page_size,
+            // 每个区域各自记录分配了多少指针的计数，归零就重置指针
+            regions: [Region::EMPTY; MAX_REGIONS],
+            num_regions: 0,
+            free_runs: [PageRun::EMPTY; MAX_FREE_RUNS],
+            num_free_runs: 0,
+            stats: EarlyStats::ZERO,
+            last_failure: None,
+            untouched_zeroed: false,
+            marks: [0; MAX_MARKS],
+            num_marks: 0,
+            next_stamp: 0,
+            #[cfg(feature = "alloc-observer")]
+            observer: None,
+        }
+    }
+
+    /// Sets the observer invoked on every allocation and deallocation, or
+    /// removes it with `None`.
+    #[cfg(feature = "alloc-observer")]
+    pub fn set_observer(&mut self, observer: Option<&'static dyn AllocObserver>) {
+        self.observer = observer;
+    }
+
+    /// Invokes the observer, if any.
+    #[inline(always)]
+    fn observe(&self, f: impl FnOnce(&dyn AllocObserver)) {
+        #[cfg(feature = "alloc-observer")]
+        if let Some(observer) = self.observer {
+            f(observer);
+        }
+        #[cfg(not(feature = "alloc-observer"))]
+        let _ = f;
+    }
+
+    /// Returns the statistics since the allocator was initialized, e.g., to
+    /// size the early memory region from the peak usage.
+    pub fn stats(&self) -> EarlyStats {
+        self.stats
+    }
+
+    /// Returns why the last failed allocation since the allocator was
+    /// initialized did not fit, if any.
+    pub fn last_failure(&self) -> Option<FailureInfo> {
+        self.last_failure
+    }
+
+    /// Records a failed allocation, and logs it with the state of the
+    /// regions the first time with the `alloc-oom-log` feature.
+    fn on_failure(&mut self, kind: AllocKind, size: usize, align: usize) {
+        self.stats.failed_allocs += 1;
+        let (largest_gap, largest_satisfiable) = match kind {
+            AllocKind::Bytes => self.regions().iter().fold((0, 0), |(gap, fit), r| {
+                let pos = r.b_pos.saturating_add(GUARD_SIZE + align - 1) & !(align - 1);
+                let size = r.p_pos.saturating_sub(pos.saturating_add(GUARD_SIZE));
+                (gap.max(r.p_pos - r.b_pos), fit.max(size))
+            }),
+            AllocKind::Pages => {
+                let (page_size, align) = (self.page_size, align.max(self.page_size));
+                let areas = self.regions().iter().map(|r| (r.b_pos, r.p_pos));
+                let runs = self.free_runs().iter().map(|r| (r.start, r.end));
+                areas.chain(runs).fold((0, 0), |(gap, fit), (start, end)| {
+                    let pos = start.saturating_add(align - 1) & !(align - 1);
+                    let size = end.saturating_sub(pos) & !(page_size - 1);
+                    (gap.max(end - start), fit.max(size))
+                })
+            }
+        };
+        let info = FailureInfo {
+            kind,
+            size,
+            align,
+            largest_gap,
+            largest_satisfiable,
+        };
+        #[cfg(feature = "alloc-oom-log")]
+        if self.last_failure.is_none() {
+            error!("early allocator: {}", info);
+            for r in self.regions() {
+                error!(
+                    "  region [{:#x}, {:#x}): bytes up to {:#x}, pages from {:#x}",
+                    r.start, r.end, r.b_pos, r.p_pos
+                );
+            }
+            for r in self.free_runs() {
+                error!("  freed pages [{:#x}, {:#x})", r.start, r.end);
+            }
+        }
+        self.last_failure = Some(info);
+    }
+
+    /// Returns the size in bytes of a page.
+    pub const fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Tells whether the memory of the regions is zeroed when they are added,
+    /// e.g., cleared by the loader, which is not assumed by default.
+    ///
+    /// If so, [`alloc_zeroed`](Self::alloc_zeroed) and
+    /// [`alloc_pages_zeroed`](Self::alloc_pages_zeroed) skip the zeroing of
+    /// memory never allocated before.
+    pub fn set_untouched_zeroed(&mut self, zeroed: bool) {
+        self.untouched_zeroed = zeroed;
+    }
+
+    /// Allocates zeroed bytes, like [`alloc`](ByteAllocator::alloc).
+    pub fn alloc_zeroed(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let (pos, untouched) = self.alloc_bytes(layout)?;
+        if !(untouched && self.untouched_zeroed) {
+            unsafe { core::ptr::write_bytes(pos as *mut u8, 0, layout.size()) };
+        }
+        unsafe { Ok(NonNull::new_unchecked(pos as *mut u8)) }
+    }
+
+    /// Allocates zeroed pages, like [`alloc_pages`](Self::alloc_pages), e.g.,
+    /// for page tables.
+    pub fn alloc_pages_zeroed(
+        &mut self,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<usize> {
+        let (pos, untouched) = self.alloc_pages_inner(num_pages, align_pow2)?;
+        if !(untouched && self.untouched_zeroed) {
+            unsafe { core::ptr::write_bytes(pos as *mut u8, 0, num_pages * self.page_size) };
+        }
+        Ok(pos)
+    }
+
+    /// Grows the most recent byte allocation of its region in place, to
+    /// `new_size` bytes.
+    ///
+    /// Returns [`AllocError::NoMemory`] if it is not the most recent one
+    /// (`ptr + old_layout.size() != b_pos`) or the available area is too
+    /// small, in which case the caller has to allocate a new block instead,
+    /// or [`AllocError::InvalidParam`] if `new_size` is smaller than the
+    /// old size.
+    pub fn grow_last(
+        &mut self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> AllocResult {
+        let pos = ptr.as_ptr() as usize;
+        if new_size < old_layout.size() {
+            return Err(AllocError::InvalidParam);
+        }
+        let region = self
+            .regions_mut()
+            .iter_mut()
+            .find(|r| r.start <= pos && pos + old_layout.size() + GUARD_SIZE == r.b_pos)
+            .ok_or(AllocError::NoMemory)?;
+        // 只有最后一次分配能原地扩展：直接推进 b_pos
+        let new_end = pos
+            .checked_add(new_size + GUARD_SIZE)
+            .ok_or(AllocError::NoMemory)?;
+        if new_end > region.p_pos {
+            return Err(AllocError::NoMemory);
+        }
+        #[cfg(feature = "alloc-debug")]
+        debug::on_grow(pos, old_layout, new_size);
+        region.b_pos = new_end;
+        region.b_high = region.b_high.max(new_end);
+
+        self.stats.largest_alloc = self.stats.largest_alloc.max(new_size);
+        self.stats.peak_used_bytes = self.stats.peak_used_bytes.max(self.used_bytes());
+        Ok(())
+    }
+
+    /// Allocates `num_pages` contiguous pages aligned to `1 << align_log2`
+    /// bytes, which can be larger than the page size, e.g., a 2 MiB aligned
+    /// block for a framebuffer or a DMA ring.
+    ///
+    /// The pages skipped to align the block are kept for later page
+    /// allocations.
+    pub fn alloc_pages_aligned(&mut self, num_pages: usize, align_log2: u32) -> AllocResult<usize> {
+        let align = 1usize.checked_shl(align_log2).ok_or(AllocError::InvalidParam)?;
+        self.alloc_pages(num_pages, align)
+    }
+
+    /// Saves the current state of the allocator, to free everything
+    /// allocated after it at once with [`rollback_to`](Self::rollback_to).
+    ///
+    /// Up to [`MAX_MARKS`] marks can be kept, the oldest one is dropped to
+    /// take another.
+    pub fn checkpoint(&mut self) -> Mark {
+        if self.num_marks == MAX_MARKS {
+            self.marks.copy_within(1.., 0);
+            self.num_marks -= 1;
+        }
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.marks[self.num_marks] = stamp;
+        self.num_marks += 1;
+        Mark {
+            stamp,
+            regions: self.regions,
+            num_regions: self.num_regions,
+            free_runs: self.free_runs,
+            num_free_runs: self.num_free_runs,
+        }
+    }
+
+    /// Frees all the bytes and pages allocated since `mark` was taken by
+    /// [`checkpoint`](Self::checkpoint) on this allocator, since it was last
+    /// initialized.
+    ///
+    /// Allocations made before the mark survive, even those freed after it,
+    /// which stay allocated. Regions added after the mark are kept, but
+    /// emptied. The marks taken after it can't be rolled back to anymore.
+    ///
+    /// Returns [`AllocError::InvalidParam`] without changing anything if the
+    /// mark is stale: taken before the allocator was initialized, before
+    /// another mark that was rolled back to, or dropped for newer marks.
+    pub fn rollback_to(&mut self, mark: Mark) -> AllocResult {
+        let idx = self.marks[..self.num_marks]
+            .iter()
+            .position(|&stamp| stamp == mark.stamp)
+            .ok_or(AllocError::InvalidParam)?;
+        // 回滚到这个标记后，它和之后的标记都作废
+        self.num_marks = idx;
+        let regions = self.regions;
+        for region in &mut self.regions[mark.num_regions..self.num_regions] {
+            *region = Region::new(region.start, region.end);
+        }
+        self.regions[..mark.num_regions].copy_from_slice(&mark.regions[..mark.num_regions]);
+        // 回滚的内存已经写过，高水位不回退
+        for (region, old) in self.regions_mut().iter_mut().zip(&regions) {
+            region.b_high = old.b_high;
+            region.p_low = old.p_low;
+        }
+        self.free_runs = mark.free_runs;
+        self.num_free_runs = mark.num_free_runs;
+        Ok(())
+    }
+
+    /// Returns the memory that the allocator does not use, as `(start, size)`
+    /// pairs: the available area `[b_pos, p_pos)` of each region and the
+    /// freed page runs.
+    ///
+    /// They can be handed to the formal allocator with its `add_memory`,
+    /// once the early allocator is no longer used. They are not page
+    /// aligned, except the freed page runs.
+    pub fn remaining_regions(&self) -> RemainingRegions {
+        RemainingRegions {
+            regions: self.regions,
+            num_regions: self.num_regions,
+            free_runs: self.free_runs,
+            num_free_runs: self.num_free_runs,
+            next: 0,
+        }
+    }
+
+    /// Consumes the allocator, and returns the memory it does not use, see
+    /// [`remaining_regions`](Self::remaining_regions).
+    pub fn into_regions(self) -> RemainingRegions {
+        self.remaining_regions()
+    }
+
+    fn regions(&self) -> &[Region] {
+        &self.regions[..self.num_regions]
+    }
+
+    fn regions_mut(&mut self) -> &mut [Region] {
+        &mut self.regions[..self.num_regions]
+    }
+
+    fn free_runs(&self) -> &[PageRun] {
+        &self.free_runs[..self.num_free_runs]
+    }
+
+    /// Returns the number of freed pages kept for reuse.
+    fn free_run_pages(&self) -> usize {
+        self.free_runs().iter().map(|r| (r.end - r.start) / self.page_size).sum()
+    }
+
+    fn remove_free_run(&mut self, idx: usize) -> PageRun {
+        let run = self.free_runs[idx];
+        self.num_free_runs -= 1;
+        self.free_runs[idx] = self.free_runs[self.num_free_runs];
+        run
+    }
+
+    /// Returns whether a region starts at `pos`, so that the runs on each
+    /// side of it can't be merged, they may belong to two adjacent regions.
+    fn is_region_start(&self, pos: usize) -> bool {
+        self.regions().iter().any(|r| r.start == pos)
+    }
+
+    /// Adds a run to the free list, merged with its neighbours in the same
+    /// region. Returns `false` if the list is full.
+    fn insert_free_run(&mut self, mut run: PageRun) -> bool {
+        while let Some(idx) = self.free_runs().iter().position(|r| {
+            (r.end == run.start && !self.is_region_start(run.start))
+                || (r.start == run.end && !self.is_region_start(run.end))
+        }) {
+            let neighbour = self.remove_free_run(idx);
+            run.start = run.start.min(neighbour.start);
+            run.end = run.end.max(neighbour.end);
+        }
+        if self.num_free_runs == MAX_FREE_RUNS {
+            return false;
+        }
+        self.free_runs[self.num_free_runs] = run;
+        self.num_free_runs += 1;
+        true
+    }
+
+    /// Allocates `size` bytes of pages from the freed runs.
+    fn alloc_from_free_runs(&mut self, size: usize, align: usize) -> Option<usize> {
+        let (idx, pos) = self
+            .free_runs()
+            .iter()
+            .enumerate()
+            .find_map(|(idx, r)| Some((idx, r.fit(size, align)?)))?;
+        let run = self.remove_free_run(idx);
+        // 切下的两端放回空闲链表，放不下就只好泄漏
+        for rest in [
+            PageRun { start: run.start, end: pos },
+            PageRun { start: pos + size, end: run.end },
+        ] {
+            if rest.start < rest.end {
+                self.insert_free_run(rest);
+            }
+        }
+        Some(pos)
+    }
+}
+
+impl BaseAllocator for DynEarlyAllocator {
     fn init(&mut self, start: usize, size: usize) {
-        self.start = start;
-        self.end = start + size;
-        self.b_pos = start;
-        self.p_pos = self.end;
-        self.count = 0;
+        self.regions[0] = Region::new(start, start + size);
+        self.num_regions = 1;
+        self.num_free_runs = 0;
+        self.num_marks = 0;
+        self.stats = EarlyStats::ZERO;
+        self.last_failure = None;
     }
 
     fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
-        todo!()
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        if size == 0 {
+            return Err(AllocError::InvalidParam);
+        }
+        if self.regions().iter().any(|r| start < r.end && r.start < end) {
+            return Err(AllocError::MemoryOverlap);
+        }
+        if self.num_regions == MAX_REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+        self.regions[self.num_regions] = Region::new(start, end);
+        self.num_regions += 1;
+        Ok(())
     }
 }
 
-impl <const PAGE_SIZE: usize> ByteAllocator for EarlyAllocator<PAGE_SIZE> {
-    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+impl DynEarlyAllocator {
+    /// Allocates bytes, and returns their address and whether they were
+    /// never allocated before.
+    fn alloc_bytes(&mut self, layout: Layout) -> AllocResult<(usize, bool)> {
         let size = layout.size();
         let align = layout.align();
-        
-        if !self.can_alloc_bytes(size, align) {
-            return Err(allocator::AllocError::NoMemory);
-        }
 
-        let aligned_pos = (self.b_pos + align - 1) & !(align - 1);
-        self.b_pos = aligned_pos + size;
-        self.count += 1;
+        let found = self.regions_mut().iter_mut().find_map(|region| {
+            let aligned_pos = region.fit_bytes(size, align)?;
+            let untouched = region.untouched(aligned_pos, aligned_pos + size);
+            #[cfg(feature = "alloc-debug")]
+            debug::on_alloc(region.b_pos, aligned_pos, size);
+            region.b_pos = aligned_pos + size + GUARD_SIZE;
+            region.b_high = region.b_high.max(region.b_pos);
+            region.count += 1;
+            Some((aligned_pos, untouched))
+        });
+        self.observe(|o| o.on_alloc(layout, found.map(|f| f.0), self.available_bytes()));
+        let Some(found) = found else {
+            self.on_failure(AllocKind::Bytes, size, align);
+            return Err(AllocError::NoMemory);
+        };
+
+        self.stats.allocs += 1;
+        self.stats.largest_alloc = self.stats.largest_alloc.max(size);
+        self.stats.peak_used_bytes = self.stats.peak_used_bytes.max(self.used_bytes());
+        Ok(found)
+    }
+}
 
-        unsafe { Ok(NonNull::new_unchecked(aligned_pos as *mut u8)) }
+impl ByteAllocator for DynEarlyAllocator {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let (pos, _) = self.alloc_bytes(layout)?;
+        unsafe { Ok(NonNull::new_unchecked(pos as *mut u8)) }
     }
 
     fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
-        self.count = self.count.saturating_sub(1);
-        if self.count == 0 {
-            self.b_pos = self.start;
+        self.stats.deallocs += 1;
+        let pos = pos.as_ptr() as usize;
+        if let Some(region) = self.regions_mut().iter_mut().find(|r| r.contains(pos)) {
+            #[cfg(feature = "alloc-debug")]
+            debug::on_dealloc(pos, layout);
+            region.count = region.count.saturating_sub(1);
+            if region.count == 0 {
+                #[cfg(feature = "alloc-debug")]
+                debug::on_reset(region.start, region.b_pos);
+                region.b_pos = region.start;
+            }
         }
+        self.observe(|o| o.on_dealloc(pos, layout, self.available_bytes()));
     }
 
     fn total_bytes(&self) -> usize {
-        self.end - self.start
+        self.regions().iter().map(|r| r.end - r.start).sum()
     }
 
     fn used_bytes(&self) -> usize {
-        self.b_pos - self.start
+        self.regions().iter().map(|r| r.b_pos - r.start).sum()
     }
 
     fn available_bytes(&self) -> usize {
-        self.p_pos - self.b_pos
+        self.regions().iter().map(|r| r.p_pos - r.b_pos).sum()
     }
 }
 
-impl <const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
-    const PAGE_SIZE: usize = PAGE_SIZE;
+impl DynEarlyAllocator {
+    /// Allocates `num_pages` contiguous pages aligned to `align_pow2` bytes,
+    /// see [`PageAllocator::alloc_pages`].
+    pub fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        self.alloc_pages_inner(num_pages, align_pow2).map(|(pos, _)| pos)
+    }
 
-    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        let align = align_pow2.max(PAGE_SIZE);
-        let size = num_pages * PAGE_SIZE;
+    /// Allocates pages, and returns their address and whether they were
+    /// never allocated before.
+    fn alloc_pages_inner(
+        &mut self,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> AllocResult<(usize, bool)> {
+        if num_pages == 0 || !align_pow2.is_power_of_two() {
+            return Err(AllocError::InvalidParam);
+        }
+        let align = align_pow2.max(self.page_size);
+        let size = num_pages.checked_mul(self.page_size).ok_or(AllocError::NoMemory)?;
+
+        let page_size = self.page_size;
+        // 空闲链表里的页都用过
+        let mut found = self.alloc_from_free_runs(size, align).map(|pos| (pos, false));
+        if found.is_none() {
+            let fit = self.regions_mut().iter_mut().find_map(|region| {
+                // 计算对齐后的地址
+                let aligned_pos = region.fit_pages(size, align)?;
+                let untouched = region.untouched(aligned_pos, aligned_pos + size);
+                let gap = PageRun {
+                    start: aligned_pos + size,
+                    end: region.p_pos & !(page_size - 1),
+                };
+                region.p_pos = aligned_pos;
+                region.p_low = region.p_low.min(aligned_pos);
+                Some((aligned_pos, untouched, gap))
+            });
+            if let Some((aligned_pos, untouched, gap)) = fit {
+                // 对齐大于一页时，跳过的空隙留给后面的分配
+                if gap.start < gap.end {
+                    self.insert_free_run(gap);
+                }
+                found = Some((aligned_pos, untouched));
+            }
+        }
+        let pos = found.map(|f| f.0);
+        self.observe(|o| o.on_alloc_pages(num_pages, align_pow2, pos, self.available_pages()));
+        let Some(found) = found else {
+            self.on_failure(AllocKind::Pages, size, align_pow2);
+            return Err(AllocError::NoMemory);
+        };
+
+        self.stats.page_allocs += 1;
+        self.stats.largest_alloc_pages = self.stats.largest_alloc_pages.max(num_pages);
+        self.stats.peak_used_pages = self.stats.peak_used_pages.max(self.used_pages());
+        Ok(found)
+    }
+
+    /// Frees `num_pages` pages at `pos`.
+    pub fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        self.stats.page_deallocs += 1;
+        let end = pos + num_pages * self.page_size;
+        // 跨过相邻区域边界的页，按区域分开释放
+        let mut start = pos;
+        while let Some(ridx) = self.regions().iter().position(|r| r.contains(start)) {
+            let run = PageRun {
+                start,
+                end: end.min(self.regions[ridx].end),
+            };
+            self.free_pages_in(ridx, run);
+            if run.end == end {
+                break;
+            }
+            start = run.end;
+        }
+        self.observe(|o| o.on_dealloc_pages(pos, num_pages, self.available_pages()));
+    }
 
-        if !self.can_alloc_pages(num_pages, align) {
-            return Err(allocator::AllocError::NoMemory);
+    /// Frees the pages of `run`, all in the region `ridx`.
+    fn free_pages_in(&mut self, ridx: usize, run: PageRun) {
+        let region = self.regions[ridx];
+        if run.start != region.p_pos {
+            self.insert_free_run(run);
+            return;
+        }
+        // 释放的是最低的页，p_pos 上移，并吞掉紧接着的空闲页，不超过区域末尾
+        let mut p_pos = run.end;
+        while let Some(idx) = self
+            .free_runs()
+            .iter()
+            .position(|r| r.start == p_pos && p_pos < region.end)
+        {
+            p_pos = self.remove_free_run(idx).end;
         }
+        self.regions[ridx].p_pos = p_pos;
+    }
+
+    /// Returns the number of pages in all regions.
+    pub fn total_pages(&self) -> usize {
+        self.regions().iter().map(|r| (r.end - r.start) / self.page_size).sum()
+    }
+
+    /// Returns the number of allocated pages.
+    pub fn used_pages(&self) -> usize {
+        let pages_area: usize = self.regions().iter().map(|r| r.end - r.p_pos).sum();
+        pages_area / self.page_size - self.free_run_pages()
+    }
 
-        // 计算对齐后的地址
-        let aligned_pos = (self.p_pos - size) & !(align - 1);
-        self.p_pos = aligned_pos;
+    /// Returns the number of pages that can still be allocated.
+    pub fn available_pages(&self) -> usize {
+        let avail_pages: usize = self
+            .regions()
+            .iter()
+            .map(|r| (r.p_pos - r.b_pos) / self.page_size)
+            .sum();
+        avail_pages + self.free_run_pages()
+    }
+}
+
+impl <const PAGE_SIZE: usize> BaseAllocator for EarlyAllocator<PAGE_SIZE> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.inner.init(start, size)
+    }
 
-        Ok(aligned_pos)
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.inner.add_memory(start, size)
+    }
+}
+
+impl <const PAGE_SIZE: usize> ByteAllocator for EarlyAllocator<PAGE_SIZE> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        self.inner.alloc(layout)
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        self.inner.dealloc(pos, layout)
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.inner.total_bytes()
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.inner.used_bytes()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.inner.available_bytes()
+    }
+}
+
+impl <const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        self.inner.alloc_pages(num_pages, align_pow2)
     }
 
     fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
-        todo!()
+        self.inner.dealloc_pages(pos, num_pages)
     }
 
     fn total_pages(&self) -> usize {
-        (self.end - self.start) / PAGE_SIZE
+        self.inner.total_pages()
     }
 
     fn used_pages(&self) -> usize {
-        (self.end - self.p_pos) / PAGE_SIZE
+        self.inner.used_pages()
     }
 
     fn available_pages(&self) -> usize {
-        (self.p_pos - self.b_pos) / PAGE_SIZE
+        self.inner.available_pages()
     }
 }
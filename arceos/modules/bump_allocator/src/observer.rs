@@ -0,0 +1,35 @@
+//! Hooks to follow the allocations of an [`EarlyAllocator`](crate::EarlyAllocator).
+
+use core::alloc::Layout;
+
+/// Callbacks invoked on every allocation and deallocation of an
+/// [`EarlyAllocator`](crate::EarlyAllocator), e.g., to log the early-boot
+/// allocations and find out which subsystems consume the early memory.
+///
+/// Set with [`set_observer`](crate::DynEarlyAllocator::set_observer),
+/// only with the `alloc-observer` feature. Without it, the hooks are compiled
+/// out.
+///
+/// All methods do nothing by default.
+pub trait AllocObserver: Sync {
+    /// Called after a byte allocation, with its address or `None` if it
+    /// failed, and the number of bytes still available.
+    fn on_alloc(&self, _layout: Layout, _pos: Option<usize>, _available_bytes: usize) {}
+
+    /// Called after a byte deallocation, with the number of bytes available.
+    fn on_dealloc(&self, _pos: usize, _layout: Layout, _available_bytes: usize) {}
+
+    /// Called after a page allocation, with its address or `None` if it
+    /// failed, and the number of pages still available.
+    fn on_alloc_pages(
+        &self,
+        _num_pages: usize,
+        _align_pow2: usize,
+        _pos: Option<usize>,
+        _available_pages: usize,
+    ) {
+    }
+
+    /// Called after a page deallocation, with the number of pages available.
+    fn on_dealloc_pages(&self, _pos: usize, _num_pages: usize, _available_pages: usize) {}
+}
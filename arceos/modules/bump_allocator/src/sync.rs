@@ -0,0 +1,149 @@
+//! An early allocator that can be shared by several CPUs without a lock.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use allocator::{AllocError, AllocResult};
+
+/// Maximum size of the memory region of a [`SyncEarlyAllocator`], larger
+/// regions are truncated.
+pub const MAX_SYNC_REGION_SIZE: usize = u32::MAX as usize;
+
+/// Early memory allocator for several CPUs at once.
+///
+/// It has the same layout as [`EarlyAllocator`](crate::EarlyAllocator) over
+/// a single region, bytes forward from `start` and pages backward from
+/// `end`, but allocates with a compare-exchange instead of `&mut self`, so
+/// that secondary CPUs can allocate their stacks and idle tasks before the
+/// global allocator and its lock exist.
+///
+/// It only bumps: byte allocations and pages are only given back if they are
+/// the most recent ones at `b_pos` or `p_pos`, other ones are never freed.
+pub struct SyncEarlyAllocator<const PAGE_SIZE: usize> {
+    start: AtomicUsize,
+    size: AtomicUsize,
+    /// Offsets from `start` of `b_pos` (low 32 bits) and `p_pos` (high 32
+    /// bits), updated together so that the two areas never overlap.
+    pos: AtomicU64,
+}
+
+const fn pack(b_off: usize, p_off: usize) -> u64 {
+    b_off as u64 | (p_off as u64) << 32
+}
+
+const fn unpack(pos: u64) -> (usize, usize) {
+    (pos as u32 as usize, (pos >> 32) as usize)
+}
+
+impl<const PAGE_SIZE: usize> SyncEarlyAllocator<PAGE_SIZE> {
+    pub const fn new() -> Self {
+        Self {
+            start: AtomicUsize::new(0),
+            size: AtomicUsize::new(0),
+            pos: AtomicU64::new(0),
+        }
+    }
+
+    /// Initializes the allocator with the region `[start, start + size)`, up
+    /// to [`MAX_SYNC_REGION_SIZE`] bytes.
+    ///
+    /// Must be called before other CPUs use the allocator.
+    pub fn init(&self, start: usize, size: usize) {
+        let size = size.min(MAX_SYNC_REGION_SIZE);
+        self.start.store(start, Ordering::Relaxed);
+        self.size.store(size, Ordering::Relaxed);
+        self.pos.store(pack(0, size), Ordering::Release);
+    }
+
+    /// Updates `b_pos` and `p_pos` with `f`, which returns the new offsets
+    /// and the result, or `None` if they do not fit.
+    fn update<T>(
+        &self,
+        mut f: impl FnMut(usize, usize, usize) -> Option<(usize, usize, T)>,
+    ) -> Option<T> {
+        let start = self.start.load(Ordering::Relaxed);
+        let mut pos = self.pos.load(Ordering::Acquire);
+        loop {
+            let (b_off, p_off) = unpack(pos);
+            let (new_b_off, new_p_off, res) = f(start, b_off, p_off)?;
+            match self.pos.compare_exchange_weak(
+                pos,
+                pack(new_b_off, new_p_off),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(res),
+                Err(cur) => pos = cur,
+            }
+        }
+    }
+
+    /// Allocates bytes forward from `b_pos`.
+    pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let (size, align) = (layout.size(), layout.align());
+        self.update(|start, b_off, p_off| {
+            let aligned_pos = (start + b_off).checked_add(align - 1)? & !(align - 1);
+            let new_b_off = aligned_pos.checked_add(size)? - start;
+            (new_b_off <= p_off).then_some((new_b_off, p_off, aligned_pos))
+        })
+        .map(|pos| unsafe { NonNull::new_unchecked(pos as *mut u8) })
+        .ok_or(AllocError::NoMemory)
+    }
+
+    /// Gives back a byte allocation, only if it is the most recent one.
+    pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
+        let pos = pos.as_ptr() as usize;
+        self.update(|start, b_off, p_off| {
+            (pos + layout.size() == start + b_off).then_some((pos - start, p_off, ()))
+        });
+    }
+
+    /// Allocates pages backward from `p_pos`.
+    pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        if num_pages == 0 || !align_pow2.is_power_of_two() {
+            return Err(AllocError::InvalidParam);
+        }
+        let align = align_pow2.max(PAGE_SIZE);
+        let size = num_pages
+            .checked_mul(PAGE_SIZE)
+            .ok_or(AllocError::NoMemory)?;
+        self.update(|start, b_off, p_off| {
+            let aligned_pos = (start + p_off).checked_sub(size)? & !(align - 1);
+            let new_p_off = aligned_pos.checked_sub(start)?;
+            (new_p_off >= b_off).then_some((b_off, new_p_off, aligned_pos))
+        })
+        .ok_or(AllocError::NoMemory)
+    }
+
+    /// Gives back pages, only if they are the most recent ones.
+    pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
+        self.update(|start, b_off, p_off| {
+            (pos == start + p_off).then_some((b_off, p_off + num_pages * PAGE_SIZE, ()))
+        });
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        unpack(self.pos.load(Ordering::Relaxed)).0
+    }
+
+    pub fn available_bytes(&self) -> usize {
+        let (b_off, p_off) = unpack(self.pos.load(Ordering::Relaxed));
+        p_off - b_off
+    }
+
+    pub fn used_pages(&self) -> usize {
+        let p_off = unpack(self.pos.load(Ordering::Relaxed)).1;
+        (self.size.load(Ordering::Relaxed) - p_off) / PAGE_SIZE
+    }
+
+    pub fn available_pages(&self) -> usize {
+        self.available_bytes() / PAGE_SIZE
+    }
+}
+
+impl<const PAGE_SIZE: usize> Default for SyncEarlyAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
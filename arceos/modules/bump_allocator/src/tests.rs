@@ -0,0 +1,737 @@
+extern crate std;
+
+use core::alloc::Layout;
+use std::vec::Vec;
+
+use allocator::{AllocError, BaseAllocator, ByteAllocator, PageAllocator};
+
+use crate::{
+    AllocKind, DynEarlyAllocator, EarlyAllocator, EarlyStats, FailureInfo, SyncEarlyAllocator,
+    GUARD_SIZE, MAX_FREE_RUNS, MAX_MARKS, MAX_REGIONS, MAX_SYNC_REGION_SIZE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const MB: usize = 0x10_0000;
+
+/// The pages are never accessed, so the region does not need to be backed.
+fn allocator(start: usize, size: usize) -> EarlyAllocator<PAGE_SIZE> {
+    let mut alloc = EarlyAllocator::new();
+    alloc.init(start, size);
+    alloc
+}
+
+#[test]
+fn huge_alignment() {
+    let mut alloc = allocator(0x8000_0000, 16 * MB);
+    let pos = alloc.alloc_pages_aligned(4 * MB / PAGE_SIZE, 21).unwrap();
+    assert_eq!(pos, 0x8000_0000 + 12 * MB);
+    assert_eq!(pos % (2 * MB), 0);
+
+    // The pages between the block and `p_pos` are not lost.
+    alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    let pos = alloc.alloc_pages_aligned(1, 21).unwrap();
+    assert_eq!(pos % (2 * MB), 0);
+    assert_eq!(alloc.used_pages(), (4 * MB / PAGE_SIZE) + 2);
+    assert_eq!(alloc.alloc_pages(1, PAGE_SIZE).unwrap(), pos + PAGE_SIZE);
+}
+
+#[test]
+fn skipped_pages_are_reused() {
+    let mut alloc = allocator(0x8000_0000, 4 * MB - PAGE_SIZE);
+    let pos = alloc.alloc_pages_aligned(1, 21).unwrap();
+    assert_eq!(pos, 0x8000_0000 + 2 * MB);
+    assert_eq!(alloc.used_pages(), 1);
+    assert_eq!(alloc.available_pages(), alloc.total_pages() - 1);
+    assert_eq!(alloc.alloc_pages(1, PAGE_SIZE).unwrap(), pos + PAGE_SIZE);
+}
+
+#[test]
+fn alignment_boundaries() {
+    let mut alloc = allocator(0x8000_0000, 4 * MB);
+    // Alignments below a page still give whole pages.
+    let pos = alloc.alloc_pages(1, 8).unwrap();
+    assert_eq!(pos, 0x8000_0000 + 4 * MB - PAGE_SIZE);
+    // The whole region, aligned to its size.
+    let mut alloc = allocator(0x8000_0000, 4 * MB);
+    assert_eq!(
+        alloc.alloc_pages_aligned(4 * MB / PAGE_SIZE, 22).unwrap(),
+        0x8000_0000
+    );
+    assert_eq!(alloc.available_pages(), 0);
+    // Larger than the region.
+    let mut alloc = allocator(0x8000_0000, 4 * MB);
+    assert!(matches!(
+        alloc.alloc_pages(4 * MB / PAGE_SIZE + 1, PAGE_SIZE),
+        Err(AllocError::NoMemory)
+    ));
+    // No aligned address in the region.
+    let mut alloc = allocator(0x8000_1000, 4 * MB - 2 * PAGE_SIZE);
+    assert!(matches!(
+        alloc.alloc_pages_aligned(1, 22),
+        Err(AllocError::NoMemory)
+    ));
+    assert_eq!(alloc.used_pages(), 0);
+}
+
+#[test]
+fn invalid_requests() {
+    let mut alloc = allocator(0x8000_0000, 4 * MB);
+    assert!(matches!(
+        alloc.alloc_pages(0, PAGE_SIZE),
+        Err(AllocError::InvalidParam)
+    ));
+    assert!(matches!(
+        alloc.alloc_pages(1, 3 * PAGE_SIZE),
+        Err(AllocError::InvalidParam)
+    ));
+    assert!(matches!(
+        alloc.alloc_pages_aligned(1, usize::BITS),
+        Err(AllocError::InvalidParam)
+    ));
+    assert!(matches!(
+        alloc.alloc_pages(usize::MAX, PAGE_SIZE),
+        Err(AllocError::NoMemory)
+    ));
+}
+
+#[test]
+fn runtime_page_size() {
+    const PAGE_16K: usize = 0x4000;
+    let mut alloc = DynEarlyAllocator::new_with_page_size(PAGE_16K);
+    alloc.init(0x8000_0000, 4 * MB);
+    assert_eq!(alloc.page_size(), PAGE_16K);
+    assert_eq!(alloc.total_pages(), 4 * MB / PAGE_16K);
+    let pos = alloc.alloc_pages(2, 8).unwrap();
+    assert_eq!(pos, 0x8000_0000 + 4 * MB - 2 * PAGE_16K);
+    assert_eq!(alloc.used_pages(), 2);
+    alloc.dealloc_pages(pos, 2);
+    assert_eq!(alloc.used_pages(), 0);
+}
+
+#[repr(align(4096))]
+struct Memory([u8; 16 * PAGE_SIZE]);
+
+#[test]
+fn zeroed_allocations() {
+    let mut mem = Memory([0xaa; 16 * PAGE_SIZE]);
+    let start = mem.0.as_mut_ptr() as usize;
+    let mut alloc = allocator(start, mem.0.len());
+    let pos = alloc.alloc_pages_zeroed(2, PAGE_SIZE).unwrap();
+    let pages = unsafe { core::slice::from_raw_parts(pos as *const u8, 2 * PAGE_SIZE) };
+    assert!(pages.iter().all(|&b| b == 0));
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    let ptr = alloc.alloc_zeroed(layout).unwrap();
+    let bytes = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), 100) };
+    assert!(bytes.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn zeroed_reused_pages() {
+    let mut mem = Memory([0; 16 * PAGE_SIZE]);
+    let start = mem.0.as_mut_ptr() as usize;
+    let mut alloc = allocator(start, mem.0.len());
+    alloc.set_untouched_zeroed(true);
+    let pos = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    unsafe { core::ptr::write_bytes(pos as *mut u8, 0xaa, PAGE_SIZE) };
+    alloc.dealloc_pages(pos, 1);
+    // The page was used, so it is zeroed again.
+    assert_eq!(alloc.alloc_pages_zeroed(1, PAGE_SIZE).unwrap(), pos);
+    let page = unsafe { core::slice::from_raw_parts(pos as *const u8, PAGE_SIZE) };
+    assert!(page.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn failure_info() {
+    let mut alloc = allocator(0x8000_1000, 4 * MB - 2 * PAGE_SIZE);
+    assert_eq!(alloc.last_failure(), None);
+    // Enough pages, but not at the requested alignment.
+    assert!(alloc.alloc_pages_aligned(1, 22).is_err());
+    assert_eq!(
+        alloc.last_failure(),
+        Some(FailureInfo {
+            kind: AllocKind::Pages,
+            size: PAGE_SIZE,
+            align: 4 * MB,
+            largest_gap: 4 * MB - 2 * PAGE_SIZE,
+            largest_satisfiable: 0,
+        })
+    );
+    assert!(alloc.alloc_pages(4 * MB / PAGE_SIZE, PAGE_SIZE).is_err());
+    let info = alloc.last_failure().unwrap();
+    assert_eq!(info.largest_satisfiable, 4 * MB - 2 * PAGE_SIZE);
+    alloc.init(0x8000_0000, 4 * MB);
+    assert_eq!(alloc.last_failure(), None);
+}
+
+#[test]
+fn adjacent_regions_stay_apart() {
+    let mut alloc = allocator(0x8000_0000, 2 * PAGE_SIZE);
+    alloc
+        .add_memory(0x8000_0000 + 2 * PAGE_SIZE, 2 * PAGE_SIZE)
+        .unwrap();
+    let top = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    let bottom = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert_eq!((top, bottom), (0x8000_1000, 0x8000_0000));
+    alloc.alloc_pages(2, PAGE_SIZE).unwrap();
+    assert_eq!(alloc.available_pages(), 0);
+
+    // The top page of the first region and the bottom page of the second
+    // one, freed at once, are not merged into a run across the boundary.
+    alloc.dealloc_pages(top, 2);
+    assert_eq!(alloc.used_pages(), 2);
+    assert!(matches!(
+        alloc.alloc_pages(2, PAGE_SIZE),
+        Err(AllocError::NoMemory)
+    ));
+    // The first region absorbs its free page, but not those of the second.
+    alloc.dealloc_pages(bottom, 1);
+    assert_eq!(alloc.used_pages(), 1);
+    assert_eq!(alloc.alloc_pages(2, PAGE_SIZE).unwrap(), 0x8000_0000);
+    assert_eq!(alloc.alloc_pages(1, PAGE_SIZE).unwrap(), 0x8000_2000);
+    assert_eq!(alloc.available_pages(), 0);
+}
+
+#[test]
+fn several_regions() {
+    let mut mem = Memory([0; 16 * PAGE_SIZE]);
+    let start = mem.0.as_mut_ptr() as usize;
+    let mut alloc = allocator(start, 2 * PAGE_SIZE);
+    alloc
+        .add_memory(start + 8 * PAGE_SIZE, 4 * PAGE_SIZE)
+        .unwrap();
+    assert_eq!(alloc.total_bytes(), 6 * PAGE_SIZE);
+    assert_eq!(alloc.total_pages(), 6);
+
+    // Served by the first region with enough space left.
+    let layout = Layout::from_size_align(PAGE_SIZE - 2 * GUARD_SIZE, 8).unwrap();
+    let first = alloc.alloc(layout).unwrap();
+    let second = alloc.alloc(layout).unwrap();
+    let third = alloc.alloc(layout).unwrap().as_ptr() as usize;
+    assert_eq!(first.as_ptr() as usize, start + GUARD_SIZE);
+    assert_eq!(second.as_ptr() as usize, start + PAGE_SIZE + GUARD_SIZE);
+    assert_eq!(third, start + 8 * PAGE_SIZE + GUARD_SIZE);
+    let pages = alloc.alloc_pages(2, PAGE_SIZE).unwrap();
+    assert_eq!(pages, start + 10 * PAGE_SIZE);
+    assert_eq!(alloc.used_bytes(), 3 * PAGE_SIZE);
+    assert_eq!(alloc.used_pages(), 2);
+
+    // Each region frees its bytes area when its last allocation is freed.
+    alloc.dealloc(first, layout);
+    alloc.dealloc(second, layout);
+    assert_eq!(alloc.used_bytes(), PAGE_SIZE);
+    assert_eq!(alloc.alloc_pages(2, PAGE_SIZE).unwrap(), start);
+}
+
+#[test]
+fn regions_exhausted() {
+    let mut alloc = allocator(0x8000_0000, 2 * PAGE_SIZE);
+    alloc.add_memory(0x9000_0000, 2 * PAGE_SIZE).unwrap();
+    // Fits in the two regions together, but in none of them.
+    assert!(matches!(
+        alloc.alloc_pages(3, PAGE_SIZE),
+        Err(AllocError::NoMemory)
+    ));
+    let layout = Layout::from_size_align(3 * PAGE_SIZE, 8).unwrap();
+    assert!(matches!(alloc.alloc(layout), Err(AllocError::NoMemory)));
+    for _ in 0..4 {
+        alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    }
+    assert_eq!(alloc.available_pages(), 0);
+    assert_eq!(alloc.available_bytes(), 0);
+    assert!(matches!(
+        alloc.alloc_pages(1, PAGE_SIZE),
+        Err(AllocError::NoMemory)
+    ));
+    assert_eq!(alloc.stats().failed_allocs, 3);
+}
+
+#[test]
+fn invalid_regions() {
+    let mut alloc = allocator(0x8000_0000, 4 * PAGE_SIZE);
+    assert!(matches!(
+        alloc.add_memory(0x8000_1000, 4 * PAGE_SIZE),
+        Err(AllocError::MemoryOverlap)
+    ));
+    assert!(matches!(
+        alloc.add_memory(0x7fff_f000, 2 * PAGE_SIZE),
+        Err(AllocError::MemoryOverlap)
+    ));
+    assert!(matches!(
+        alloc.add_memory(0x9000_0000, 0),
+        Err(AllocError::InvalidParam)
+    ));
+    assert!(matches!(
+        alloc.add_memory(usize::MAX, 2),
+        Err(AllocError::InvalidParam)
+    ));
+
+    // Up to `MAX_REGIONS` regions, including the initial one.
+    for i in 1..MAX_REGIONS {
+        alloc.add_memory(0x9000_0000 + i * MB, PAGE_SIZE).unwrap();
+    }
+    assert!(matches!(
+        alloc.add_memory(0x1000_0000, PAGE_SIZE),
+        Err(AllocError::NoMemory)
+    ));
+    assert_eq!(alloc.total_pages(), 4 + MAX_REGIONS - 1);
+    // `init` starts over with a single region.
+    alloc.init(0x8000_0000, PAGE_SIZE);
+    alloc.add_memory(0x1000_0000, PAGE_SIZE).unwrap();
+    assert_eq!(alloc.total_pages(), 2);
+}
+
+#[test]
+fn freed_pages_reused() {
+    let mut alloc = allocator(0x8000_0000, 16 * PAGE_SIZE);
+    let pages: [usize; 4] = core::array::from_fn(|_| alloc.alloc_pages(1, PAGE_SIZE).unwrap());
+    assert_eq!(pages, [0x8000_f000, 0x8000_e000, 0x8000_d000, 0x8000_c000]);
+
+    // Freed runs are merged with their neighbours, and reused first.
+    alloc.dealloc_pages(pages[1], 1);
+    alloc.dealloc_pages(pages[2], 1);
+    assert_eq!(alloc.used_pages(), 2);
+    assert_eq!(alloc.available_pages(), 14);
+    assert_eq!(alloc.alloc_pages(2, PAGE_SIZE).unwrap(), pages[2]);
+    // A run too small for the request is skipped.
+    alloc.dealloc_pages(pages[2], 2);
+    assert_eq!(alloc.alloc_pages(3, PAGE_SIZE).unwrap(), 0x8000_9000);
+    // The part of a run left by an aligned allocation stays free.
+    assert_eq!(alloc.alloc_pages(1, 2 * PAGE_SIZE).unwrap(), pages[1]);
+    assert_eq!(alloc.alloc_pages(1, PAGE_SIZE).unwrap(), pages[2]);
+
+    // Freeing the lowest pages moves `p_pos` up over the free runs next to
+    // them.
+    alloc.dealloc_pages(pages[1], 1);
+    alloc.dealloc_pages(pages[2], 1);
+    alloc.dealloc_pages(0x8000_9000, 3);
+    alloc.dealloc_pages(pages[3], 1);
+    assert_eq!(alloc.used_pages(), 1);
+    assert_eq!(alloc.alloc_pages(15, PAGE_SIZE).unwrap(), 0x8000_0000);
+}
+
+#[test]
+fn free_runs_full() {
+    const PAGES: usize = 2 * MAX_FREE_RUNS + 2;
+    let mut alloc = allocator(0x8000_0000, 2 * PAGES * PAGE_SIZE);
+    let mut pages = [0; PAGES];
+    for page in &mut pages {
+        *page = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    }
+    // One free run per freed page, as they are not next to each other.
+    for i in 0..MAX_FREE_RUNS {
+        alloc.dealloc_pages(pages[2 * i], 1);
+    }
+    assert_eq!(alloc.used_pages(), PAGES - MAX_FREE_RUNS);
+    // The list is full, the page stays allocated.
+    alloc.dealloc_pages(pages[2 * MAX_FREE_RUNS], 1);
+    assert_eq!(alloc.used_pages(), PAGES - MAX_FREE_RUNS);
+    // Merged runs take no more room.
+    alloc.dealloc_pages(pages[1], 1);
+    assert_eq!(alloc.used_pages(), PAGES - MAX_FREE_RUNS - 1);
+    assert_eq!(alloc.alloc_pages(3, PAGE_SIZE).unwrap(), pages[2]);
+}
+
+#[test]
+fn stats() {
+    let mut mem = Memory([0; 16 * PAGE_SIZE]);
+    let start = mem.0.as_mut_ptr() as usize;
+    let mut alloc = allocator(start, mem.0.len());
+    let small = Layout::from_size_align(96, 8).unwrap();
+    let large = Layout::from_size_align(304, 8).unwrap();
+    let first = alloc.alloc(small).unwrap();
+    let second = alloc.alloc(large).unwrap();
+    alloc.dealloc(first, small);
+    alloc.dealloc(second, large);
+    let pages = alloc.alloc_pages(3, PAGE_SIZE).unwrap();
+    alloc.dealloc_pages(pages, 3);
+    alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert!(alloc.alloc_pages(16, PAGE_SIZE).is_err());
+    assert_eq!(
+        alloc.stats(),
+        EarlyStats {
+            peak_used_bytes: 400 + 4 * GUARD_SIZE,
+            peak_used_pages: 3,
+            largest_alloc: 304,
+            largest_alloc_pages: 3,
+            allocs: 2,
+            deallocs: 2,
+            page_allocs: 2,
+            page_deallocs: 1,
+            failed_allocs: 1,
+        }
+    );
+    // The peaks are kept, the current usage is not.
+    assert_eq!((alloc.used_bytes(), alloc.used_pages()), (0, 1));
+    alloc.init(start, mem.0.len());
+    assert_eq!(alloc.stats(), EarlyStats::default());
+}
+
+#[test]
+fn remaining_regions() {
+    let mut mem = Memory([0; 16 * PAGE_SIZE]);
+    let start = mem.0.as_mut_ptr() as usize;
+    let mut alloc = allocator(start, 8 * PAGE_SIZE);
+    alloc
+        .add_memory(start + 12 * PAGE_SIZE, 2 * PAGE_SIZE)
+        .unwrap();
+    let all = [
+        (start, 8 * PAGE_SIZE),
+        (start + 12 * PAGE_SIZE, 2 * PAGE_SIZE),
+    ];
+    assert!(alloc.remaining_regions().eq(all));
+
+    alloc
+        .alloc(Layout::from_size_align(0x100, 8).unwrap())
+        .unwrap();
+    let b_pos = start + 0x100 + 2 * GUARD_SIZE;
+    let page = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    alloc.dealloc_pages(page, 1);
+    // The available areas of the regions, then the freed page runs.
+    let expected = [
+        (b_pos, start + 6 * PAGE_SIZE - b_pos),
+        (start + 12 * PAGE_SIZE, 2 * PAGE_SIZE),
+        (page, PAGE_SIZE),
+    ];
+    assert!(alloc.remaining_regions().eq(expected));
+    // Once consumed, the allocator gives the same memory.
+    assert!(alloc.into_regions().eq(expected));
+
+    // Full regions are skipped.
+    let mut alloc = allocator(start, 2 * PAGE_SIZE);
+    alloc.add_memory(start + 4 * PAGE_SIZE, PAGE_SIZE).unwrap();
+    alloc.alloc_pages(2, PAGE_SIZE).unwrap();
+    assert!(alloc
+        .into_regions()
+        .eq([(start + 4 * PAGE_SIZE, PAGE_SIZE)]));
+}
+
+#[test]
+fn grow_last() {
+    let mut mem = Memory([0; 16 * PAGE_SIZE]);
+    let start = mem.0.as_mut_ptr() as usize;
+    let mut alloc = allocator(start, 2 * PAGE_SIZE);
+    let layout = Layout::from_size_align(0x10, 8).unwrap();
+    let first = alloc.alloc(layout).unwrap();
+    alloc.grow_last(first, layout, 0x100).unwrap();
+    assert_eq!(alloc.used_bytes(), 0x100 + 2 * GUARD_SIZE);
+    assert_eq!(alloc.stats().largest_alloc, 0x100);
+
+    // Only the most recent allocation grows.
+    let second = alloc.alloc(layout).unwrap();
+    let grown = Layout::from_size_align(0x100, 8).unwrap();
+    assert!(matches!(
+        alloc.grow_last(first, grown, 0x200),
+        Err(AllocError::NoMemory)
+    ));
+    assert!(matches!(
+        alloc.grow_last(second, layout, 0x8),
+        Err(AllocError::InvalidParam)
+    ));
+    // Up to the pages area.
+    alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    let max_size = layout.size() + alloc.available_bytes();
+    assert!(matches!(
+        alloc.grow_last(second, layout, max_size + 1),
+        Err(AllocError::NoMemory)
+    ));
+    alloc.grow_last(second, layout, max_size).unwrap();
+    assert_eq!(alloc.available_bytes(), 0);
+
+    // Freed with its new size.
+    alloc.dealloc(first, grown);
+    let grown = Layout::from_size_align(max_size, 8).unwrap();
+    alloc.dealloc(second, grown);
+    assert_eq!(alloc.used_bytes(), 0);
+}
+
+/// Counts the errors reported by the checks of the `alloc-debug` feature.
+#[cfg(feature = "alloc-debug")]
+mod checks {
+    use core::fmt::{self, Write};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    pub static UNDERFLOWS: AtomicUsize = AtomicUsize::new(0);
+    pub static OVERFLOWS: AtomicUsize = AtomicUsize::new(0);
+    pub static USES_AFTER_FREE: AtomicUsize = AtomicUsize::new(0);
+
+    /// The start of a message, without allocating.
+    struct Line {
+        buf: [u8; 64],
+        len: usize,
+    }
+
+    impl Write for Line {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let n = s.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+            self.len += n;
+            Ok(())
+        }
+    }
+
+    struct Logger;
+
+    impl log::Log for Logger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let mut line = Line {
+                buf: [0; 64],
+                len: 0,
+            };
+            let _ = write!(line, "{}", record.args());
+            let line = core::str::from_utf8(&line.buf[..line.len]).unwrap_or_default();
+            let counter = if line.contains("underflow before") {
+                &UNDERFLOWS
+            } else if line.contains("overflow after") {
+                &OVERFLOWS
+            } else if line.contains("written after free") {
+                &USES_AFTER_FREE
+            } else {
+                return;
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn flush(&self) {}
+    }
+
+    pub fn init() {
+        let _ = log::set_logger(&Logger);
+        log::set_max_level(log::LevelFilter::Error);
+    }
+}
+
+#[cfg(feature = "alloc-debug")]
+#[test]
+fn guards_and_poison() {
+    use crate::debug::{GUARD_BYTE, POISON_BYTE};
+    use checks::{OVERFLOWS, UNDERFLOWS, USES_AFTER_FREE};
+    use core::sync::atomic::Ordering;
+
+    checks::init();
+    let mut mem = Memory([0; 16 * PAGE_SIZE]);
+    let start = mem.0.as_mut_ptr() as usize;
+    let mut alloc = allocator(start, 8 * PAGE_SIZE);
+    let bytes =
+        |pos: usize, len: usize| unsafe { core::slice::from_raw_parts_mut(pos as *mut u8, len) };
+    let layout = Layout::from_size_align(10, 8).unwrap();
+
+    // The allocations are surrounded by guard bytes.
+    let first = alloc.alloc(layout).unwrap();
+    let second = alloc.alloc(layout).unwrap();
+    let pos = first.as_ptr() as usize;
+    assert_eq!(pos, start + GUARD_SIZE);
+    assert!(bytes(start, GUARD_SIZE).iter().all(|&b| b == GUARD_BYTE));
+    assert!(bytes(pos + 10, GUARD_SIZE).iter().all(|&b| b == GUARD_BYTE));
+
+    // Overwritten guard bytes are reported when the allocation is freed,
+    // which is then poisoned.
+    bytes(pos, 11)[10] = 0;
+    alloc.dealloc(first, layout);
+    assert_eq!(OVERFLOWS.load(Ordering::Relaxed), 1);
+    let freed = bytes(start, 10 + 2 * GUARD_SIZE);
+    assert!(freed.iter().all(|&b| b == POISON_BYTE));
+
+    // Writes to freed memory are reported when the bytes area is reset.
+    bytes(pos, 1)[0] = 0;
+    alloc.dealloc(second, layout);
+    assert_eq!(USES_AFTER_FREE.load(Ordering::Relaxed), 1);
+    assert_eq!(alloc.used_bytes(), 0);
+
+    let third = alloc.alloc(layout).unwrap();
+    bytes(third.as_ptr() as usize - 1, 1)[0] = 0;
+    alloc.dealloc(third, layout);
+    assert_eq!(UNDERFLOWS.load(Ordering::Relaxed), 1);
+    assert_eq!(OVERFLOWS.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn sync_concurrent() {
+    const THREADS: usize = 8;
+    const ALLOCS: usize = 100;
+    let mut mem = std::vec![0u8; 4 * MB];
+    let start = mem.as_mut_ptr() as usize;
+    let alloc = SyncEarlyAllocator::<PAGE_SIZE>::new();
+    alloc.init(start, mem.len());
+
+    // Half of the threads allocate bytes, the other half pages.
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    let mut blocks: Vec<(usize, usize)> = std::thread::scope(|s| {
+        let threads: Vec<_> = (0..THREADS)
+            .map(|i| {
+                let alloc = &alloc;
+                s.spawn(move || {
+                    (0..ALLOCS)
+                        .map(|_| match i % 2 {
+                            0 => (alloc.alloc(layout).unwrap().as_ptr() as usize, 24),
+                            _ => (alloc.alloc_pages(1, PAGE_SIZE).unwrap(), PAGE_SIZE),
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .flat_map(|t| t.join().unwrap())
+            .collect()
+    });
+    blocks.sort();
+    assert!(blocks.windows(2).all(|w| w[0].0 + w[0].1 <= w[1].0));
+    assert!(blocks[0].0 >= start);
+    let (last, size) = blocks[blocks.len() - 1];
+    assert!(last + size <= start + mem.len());
+    assert_eq!(alloc.used_bytes(), THREADS / 2 * ALLOCS * 24);
+    assert_eq!(alloc.used_pages(), THREADS / 2 * ALLOCS);
+
+    // Only the most recent allocation is given back.
+    let pos = alloc.alloc(layout).unwrap();
+    alloc.dealloc(pos, layout);
+    assert_eq!(alloc.used_bytes(), THREADS / 2 * ALLOCS * 24);
+    let page = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    alloc.dealloc_pages(page, 1);
+    assert_eq!(alloc.used_pages(), THREADS / 2 * ALLOCS);
+}
+
+#[test]
+fn sync_exhausted() {
+    const THREADS: usize = 4;
+    let alloc = SyncEarlyAllocator::<PAGE_SIZE>::new();
+    alloc.init(0x8000_0000, 64 * PAGE_SIZE);
+    // The threads race for the pages until none is left, each one is
+    // given once.
+    let allocated: usize = std::thread::scope(|s| {
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                s.spawn(|| {
+                    (0..)
+                        .take_while(|_| alloc.alloc_pages(1, PAGE_SIZE).is_ok())
+                        .count()
+                })
+            })
+            .collect();
+        threads.into_iter().map(|t| t.join().unwrap()).sum()
+    });
+    assert_eq!(allocated, 64);
+    assert_eq!(alloc.available_pages(), 0);
+    assert!(matches!(
+        alloc.alloc_pages(1, PAGE_SIZE),
+        Err(AllocError::NoMemory)
+    ));
+    assert!(matches!(
+        alloc.alloc_pages(1, 3 * PAGE_SIZE),
+        Err(AllocError::InvalidParam)
+    ));
+    assert!(matches!(
+        alloc.alloc_pages(0, PAGE_SIZE),
+        Err(AllocError::InvalidParam)
+    ));
+}
+
+#[test]
+fn sync_offset_limit() {
+    // The offsets are 32 bits, larger regions are truncated.
+    let alloc = SyncEarlyAllocator::<PAGE_SIZE>::default();
+    alloc.init(0x1_0000_0000, 2 * MAX_SYNC_REGION_SIZE);
+    assert_eq!(alloc.available_bytes(), MAX_SYNC_REGION_SIZE);
+    assert_eq!(alloc.available_pages(), MAX_SYNC_REGION_SIZE / PAGE_SIZE);
+    let end = 0x1_0000_0000 + MAX_SYNC_REGION_SIZE;
+    let pos = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert_eq!(pos, (end - PAGE_SIZE) & !(PAGE_SIZE - 1));
+    assert_eq!(alloc.used_pages(), 1);
+    // The bytes area reaches up to the pages, not beyond.
+    let layout = Layout::from_size_align(pos - 0x1_0000_0000, 1).unwrap();
+    assert!(matches!(
+        alloc.alloc(Layout::from_size_align(layout.size() + 1, 1).unwrap()),
+        Err(AllocError::NoMemory)
+    ));
+    assert_eq!(
+        alloc.alloc(layout).unwrap().as_ptr() as usize,
+        0x1_0000_0000
+    );
+    assert_eq!(alloc.available_bytes(), 0);
+}
+
+#[test]
+fn rollback() {
+    let mut mem = Memory([0; 16 * PAGE_SIZE]);
+    let start = mem.0.as_mut_ptr() as usize;
+    let mut alloc = allocator(start, 8 * PAGE_SIZE);
+    let layout = Layout::from_size_align(0x10, 8).unwrap();
+    let kept = alloc.alloc(layout).unwrap();
+    let kept_page = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    let freed_page = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    let (used_bytes, available) = (alloc.used_bytes(), alloc.available_bytes());
+
+    let mark = alloc.checkpoint();
+    alloc
+        .add_memory(start + 12 * PAGE_SIZE, 4 * PAGE_SIZE)
+        .unwrap();
+    for _ in 0..10 {
+        alloc.alloc(layout).unwrap();
+    }
+    alloc.alloc_pages(3, PAGE_SIZE).unwrap();
+    alloc.alloc_pages(4, PAGE_SIZE).unwrap();
+    // Freed after the mark, allocated again by the rollback.
+    alloc.dealloc_pages(freed_page, 1);
+    alloc.rollback_to(mark).unwrap();
+
+    // The new region stays, empty.
+    assert_eq!(alloc.total_pages(), 12);
+    assert_eq!(alloc.used_bytes(), used_bytes);
+    assert_eq!(alloc.used_pages(), 2);
+    assert_eq!(alloc.available_bytes(), available + 4 * PAGE_SIZE);
+    assert_eq!(
+        alloc.alloc_pages(1, PAGE_SIZE).unwrap(),
+        freed_page - PAGE_SIZE
+    );
+    // The allocations made before the mark are freed as usual.
+    alloc.dealloc(kept, layout);
+    assert_eq!(alloc.used_bytes(), 0);
+    alloc.dealloc_pages(kept_page, 1);
+    assert_eq!(alloc.used_pages(), 2);
+}
+
+#[test]
+fn stale_marks() {
+    let mut alloc = allocator(0x8000_0000, 16 * PAGE_SIZE);
+    let outer = alloc.checkpoint();
+    alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    let inner = alloc.checkpoint();
+    alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    alloc.rollback_to(outer).unwrap();
+    assert_eq!(alloc.used_pages(), 0);
+    // Rolled back with the allocations made after `outer`, `inner` would
+    // mark the first page allocated again.
+    let page = alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert!(matches!(
+        alloc.rollback_to(inner),
+        Err(AllocError::InvalidParam)
+    ));
+    assert_eq!(alloc.used_pages(), 1);
+    alloc.dealloc_pages(page, 1);
+
+    // Marks taken before `init`.
+    let mark = alloc.checkpoint();
+    alloc.init(0x8000_0000, 16 * PAGE_SIZE);
+    assert!(matches!(
+        alloc.rollback_to(mark),
+        Err(AllocError::InvalidParam)
+    ));
+
+    // Only the newest `MAX_MARKS` marks are kept.
+    let oldest = alloc.checkpoint();
+    alloc.alloc_pages(1, PAGE_SIZE).unwrap();
+    let marks: [_; MAX_MARKS] = core::array::from_fn(|_| alloc.checkpoint());
+    assert!(matches!(
+        alloc.rollback_to(oldest),
+        Err(AllocError::InvalidParam)
+    ));
+    assert_eq!(alloc.used_pages(), 1);
+    let [first, ..] = marks;
+    alloc.rollback_to(first).unwrap();
+    assert_eq!(alloc.used_pages(), 1);
+}
@@ -9,6 +9,10 @@ pub use axio::{BufRead, BufReader, Error, Read, Seek, SeekFrom, Write};
 pub use self::stdio::__print_impl;
 pub use self::stdio::{stdin, stdout, Stdin, StdinLock, Stdout, StdoutLock};
 
+/// Colors and text attributes for console output, e.g.,
+/// `println!("{}", "hello".fg(Color::Red).bold())`.
+pub use arceos_api::modules::axlog::style;
+
 /// A specialized [`Result`] type for I/O operations.
 ///
 /// This type is broadly used across [`axstd::io`] for any operation which may
@@ -22,6 +22,10 @@
 //!     - `alloc-buddy`: Use the buddy system allocator.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
+//!     - `alloc-debug`: Check the byte allocations of the early allocator
+//!       (`alt_alloc`) for overflows and use after free.
+//!     - `alloc-oom-log`: Log the state of the early allocator (`alt_alloc`)
+//!       on its first allocation failure.
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.